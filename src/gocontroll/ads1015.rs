@@ -0,0 +1,432 @@
+use std::fmt::Debug;
+use std::fs::File;
+use std::io::{self,prelude::*};
+use std::path::Path;
+use std::time::{Duration,Instant};
+use i2c_linux::I2c;
+
+/// The i2c address of the ADS1015 on the Moduline controllers
+pub const ADDRESS: u16 = 0x48;
+
+const CONVERSIONREGISTER: u8 = 0x00;
+const CONFIGREGISTER: u8 = 0x01;
+const LOWTHRESHOLDREGISTER: u8 = 0x02;
+const HIGHTHRESHOLDREGISTER: u8 = 0x03;
+
+/// Set in the config register to start a single-shot conversion, reads back as set when no conversion is running
+const OPERATIONALSTATUS: u16 = 0x8000;
+/// How much longer than four conversion times a single-shot conversion may take before it is reported as timed out
+const CONVERSIONMARGIN: Duration = Duration::from_millis(10);
+
+#[allow(unused)]
+#[repr(u8)]
+#[derive(Debug,Copy,Clone,PartialEq,Eq)]
+/// The input multiplexer, a differential pair or a single input against ground
+pub enum Ads1015Input {
+    Differential0_1 = 0,
+    Differential0_3 = 1,
+    Differential1_3 = 2,
+    Differential2_3 = 3,
+    Single0 = 4,
+    Single1 = 5,
+    Single2 = 6,
+    Single3 = 7,
+}
+
+impl Ads1015Input {
+    /// The single ended input `input` against ground, only the lowest 2 bits are used
+    pub const fn single(input: u8) -> Ads1015Input {
+        match input & 0x03 {
+            0 => Ads1015Input::Single0,
+            1 => Ads1015Input::Single1,
+            2 => Ads1015Input::Single2,
+            _ => Ads1015Input::Single3,
+        }
+    }
+}
+
+#[allow(unused)]
+#[repr(u8)]
+#[derive(Debug,Copy,Clone,PartialEq,Eq)]
+/// The full scale range of the programmable gain amplifier, the inputs never go beyond the supply of the adc whatever the range
+pub enum Ads1015Gain {
+    Fsr6_144V = 0,
+    Fsr4_096V = 1,
+    Fsr2_048V = 2,
+    Fsr1_024V = 3,
+    Fsr0_512V = 4,
+    Fsr0_256V = 5,
+}
+
+impl Ads1015Gain {
+    /// The size of one count of a conversion in µV
+    pub const fn microvolts_per_count(self) -> i32 {
+        match self {
+            Ads1015Gain::Fsr6_144V => 3000,
+            Ads1015Gain::Fsr4_096V => 2000,
+            Ads1015Gain::Fsr2_048V => 1000,
+            Ads1015Gain::Fsr1_024V => 500,
+            Ads1015Gain::Fsr0_512V => 250,
+            Ads1015Gain::Fsr0_256V => 125,
+        }
+    }
+
+    /// Convert a conversion into µV
+    pub const fn microvolts(self, code: i16) -> i32 {
+        code as i32 * self.microvolts_per_count()
+    }
+}
+
+#[allow(unused)]
+#[repr(u8)]
+#[derive(Debug,Copy,Clone,PartialEq,Eq)]
+/// The number of conversions per second
+pub enum Ads1015DataRate {
+    Sps128 = 0,
+    Sps250 = 1,
+    Sps490 = 2,
+    Sps920 = 3,
+    Sps1600 = 4,
+    Sps2400 = 5,
+    Sps3300 = 6,
+}
+
+impl Ads1015DataRate {
+    pub const fn samples_per_second(self) -> u32 {
+        match self {
+            Ads1015DataRate::Sps128 => 128,
+            Ads1015DataRate::Sps250 => 250,
+            Ads1015DataRate::Sps490 => 490,
+            Ads1015DataRate::Sps920 => 920,
+            Ads1015DataRate::Sps1600 => 1600,
+            Ads1015DataRate::Sps2400 => 2400,
+            Ads1015DataRate::Sps3300 => 3300,
+        }
+    }
+
+    /// The duration of a single conversion
+    pub const fn conversion_time(self) -> Duration {
+        Duration::from_micros(1_000_000 / self.samples_per_second() as u64 + 1)
+    }
+}
+
+#[allow(unused)]
+#[derive(Debug,Copy,Clone,PartialEq,Eq)]
+/// The number of conversions beyond a threshold before the ALERT/RDY pin is asserted
+pub enum Ads1015Queue {
+    One = 0,
+    Two = 1,
+    Four = 2,
+}
+
+#[allow(unused)]
+#[derive(Debug,Copy,Clone,PartialEq,Eq)]
+/// What the ALERT/RDY pin signals:
+/// Disabled        -> Nothing, the pin stays high\
+/// Traditional     -> Asserted above `high`, released below `low`\
+/// Window          -> Asserted outside the window from `low` to `high`\
+/// ConversionReady -> Pulsed at the end of every conversion
+///
+/// The thresholds are conversions as returned by [Ads1015::read_single], `active_high` inverts the pin and a `latching` alert stays asserted until the conversion is read.
+/// A threshold beyond the conversions from -2048 to 2047 is clamped to that range, [Ads1015Config::with_alert] does so right away
+pub enum Ads1015Alert {
+    Disabled,
+    Traditional { low: i16, high: i16, queue: Ads1015Queue, active_high: bool, latching: bool },
+    Window { low: i16, high: i16, queue: Ads1015Queue, active_high: bool, latching: bool },
+    ConversionReady { active_high: bool },
+}
+
+/// The lowest and highest conversion of the 12 bit adc
+const CONVERSIONRANGE: (i16, i16) = (-2048, 2047);
+
+impl Ads1015Alert {
+    /// The alert with its thresholds clamped to the conversions of the adc
+    pub const fn clamped(self) -> Ads1015Alert {
+        const fn clamp(threshold: i16) -> i16 {
+            if threshold < CONVERSIONRANGE.0 {
+                CONVERSIONRANGE.0
+            } else if threshold > CONVERSIONRANGE.1 {
+                CONVERSIONRANGE.1
+            } else {
+                threshold
+            }
+        }
+        match self {
+            Ads1015Alert::Traditional { low, high, queue, active_high, latching } => Ads1015Alert::Traditional { low: clamp(low), high: clamp(high), queue, active_high, latching },
+            Ads1015Alert::Window { low, high, queue, active_high, latching } => Ads1015Alert::Window { low: clamp(low), high: clamp(high), queue, active_high, latching },
+            alert => alert,
+        }
+    }
+
+    /// The comparator bits of the config register
+    const fn config_bits(self) -> u16 {
+        match self {
+            Ads1015Alert::Disabled => 0x0003,
+            Ads1015Alert::Traditional { queue, active_high, latching, .. } => (active_high as u16) << 3 | (latching as u16) << 2 | queue as u16,
+            Ads1015Alert::Window { queue, active_high, latching, .. } => 0x0010 | (active_high as u16) << 3 | (latching as u16) << 2 | queue as u16,
+            Ads1015Alert::ConversionReady { active_high } => (active_high as u16) << 3,
+        }
+    }
+
+    /// The values of the low and high threshold registers, conversion ready is signalled with the most significant bit of the high threshold set and that of the low threshold cleared
+    const fn thresholds(self) -> (u16, u16) {
+        match self.clamped() {
+            Ads1015Alert::Disabled => (0x8000, 0x7ff0),
+            Ads1015Alert::Traditional { low, high, .. } | Ads1015Alert::Window { low, high, .. } => ((low << 4) as u16, (high << 4) as u16),
+            Ads1015Alert::ConversionReady { .. } => (0x0000, 0x8000),
+        }
+    }
+}
+
+#[derive(Debug,Copy,Clone,PartialEq,Eq)]
+/// The settings of an [Ads1015] that hold for every conversion
+pub struct Ads1015Config {
+    pub gain: Ads1015Gain,
+    pub data_rate: Ads1015DataRate,
+    pub alert: Ads1015Alert,
+}
+
+impl Default for Ads1015Config {
+    /// The ±4.096 V range at 3300 samples per second the supply measurements of the Moduline controllers are calibrated for
+    fn default() -> Self {
+        Ads1015Config::new(Ads1015Gain::Fsr4_096V, Ads1015DataRate::Sps3300)
+    }
+}
+
+impl Ads1015Config {
+    pub const fn new(gain: Ads1015Gain, data_rate: Ads1015DataRate) -> Ads1015Config {
+        Ads1015Config { gain, data_rate, alert: Ads1015Alert::Disabled }
+    }
+
+    pub const fn with_alert(mut self, alert: Ads1015Alert) -> Ads1015Config {
+        self.alert = alert.clamped();
+        self
+    }
+
+    /// The config register for a conversion of `input`
+    const fn register(&self, input: Ads1015Input, single_shot: bool) -> u16 {
+        (single_shot as u16) << 15
+            | (input as u16) << 12
+            | (self.gain as u16) << 9
+            | (single_shot as u16) << 8
+            | (self.data_rate as u16) << 5
+            | self.alert.config_bits()
+    }
+}
+
+/// The bus an [Ads1015] is connected to, the registers of the adc are 16 bits wide with the most significant byte first
+pub trait Ads1015Bus: Send + 'static {
+    fn write_register(&mut self, register: u8, value: u16) -> io::Result<()>;
+
+    fn read_register(&mut self, register: u8) -> io::Result<u16>;
+}
+
+impl Ads1015Bus for I2c<File> {
+    fn write_register(&mut self, register: u8, value: u16) -> io::Result<()> {
+        let [high, low] = value.to_be_bytes();
+        self.write_all(&[register, high, low])
+    }
+
+    fn read_register(&mut self, register: u8) -> io::Result<u16> {
+        let mut rx = [0u8;2];
+        self.write_all(&[register])?;
+        self.read_exact(&mut rx)?;
+        Ok(u16::from_be_bytes(rx))
+    }
+}
+
+/// The ADS1015 adc measuring the supply voltages on the older Moduline controllers, the bus stays open for the lifetime of the driver
+pub struct Ads1015<B: Ads1015Bus = I2c<File>> {
+    bus: B,
+    config: Ads1015Config,
+    /// The input converted continuously, None in single-shot mode
+    continuous: Option<Ads1015Input>,
+}
+
+impl Ads1015 {
+    /// Open the ADS1015 on the i2c bus at `path`
+    pub fn open<P: AsRef<Path>>(path: P, config: Ads1015Config) -> io::Result<Ads1015> {
+        let mut bus = I2c::from_path(path)?;
+        bus.smbus_set_slave_address(ADDRESS, false)?;
+        Ads1015::new(bus, config)
+    }
+}
+
+#[allow(unused)]
+impl<B: Ads1015Bus> Ads1015<B> {
+    /// Use the ADS1015 on `bus`, the thresholds of the alert are written right away
+    pub fn new(bus: B, config: Ads1015Config) -> io::Result<Ads1015<B>> {
+        let mut adc = Ads1015 { bus, config, continuous: None };
+        adc.configure(config)?;
+        Ok(adc)
+    }
+
+    pub fn config(&self) -> &Ads1015Config {
+        &self.config
+    }
+
+    /// Change the settings, a continuous conversion restarts with the new settings
+    pub fn configure(&mut self, config: Ads1015Config) -> io::Result<()> {
+        let (low, high) = config.alert.thresholds();
+        self.bus.write_register(LOWTHRESHOLDREGISTER, low)?;
+        self.bus.write_register(HIGHTHRESHOLDREGISTER, high)?;
+        self.config = config;
+        match self.continuous {
+            Some(input) => self.start_continuous(input),
+            None => Ok(()),
+        }
+    }
+
+    /// Convert `input` once and wait for the result, stops a continuous conversion.
+    /// The result counts in steps of [Ads1015Gain::microvolts_per_count] and is negative when the input is below ground or the second input of a pair.
+    pub fn read_single(&mut self, input: Ads1015Input) -> io::Result<i16> {
+        self.bus.write_register(CONFIGREGISTER, OPERATIONALSTATUS | self.config.register(input, true))?;
+        self.continuous = None;
+        let conversion_time = self.config.data_rate.conversion_time();
+        // the margin and the read after the deadline keep a thread that got scheduled out from giving up on a conversion that did finish
+        let deadline = Instant::now() + conversion_time * 4 + CONVERSIONMARGIN;
+        std::thread::sleep(conversion_time);
+        loop {
+            let expired = Instant::now() > deadline;
+            if self.bus.read_register(CONFIGREGISTER)? & OPERATIONALSTATUS != 0 {
+                return self.read_conversion();
+            }
+            if expired {
+                return Err(io::Error::new(io::ErrorKind::TimedOut, "ADS1015 conversion didn't finish"));
+            }
+        }
+    }
+
+    /// Convert `input` continuously at the data rate, [Ads1015::read_latest] returns the last conversion
+    pub fn start_continuous(&mut self, input: Ads1015Input) -> io::Result<()> {
+        self.bus.write_register(CONFIGREGISTER, self.config.register(input, false))?;
+        self.continuous = Some(input);
+        Ok(())
+    }
+
+    /// The input being converted continuously
+    pub fn continuous_input(&self) -> Option<Ads1015Input> {
+        self.continuous
+    }
+
+    /// The last conversion of a continuous conversion, without waiting for a new one
+    pub fn read_latest(&mut self) -> io::Result<i16> {
+        if self.continuous.is_none() {
+            return Err(io::Error::new(io::ErrorKind::InvalidInput, "ADS1015 isn't converting continuously"));
+        }
+        self.read_conversion()
+    }
+
+    /// Stop a continuous conversion, the adc powers down until the next conversion
+    pub fn stop(&mut self) -> io::Result<()> {
+        if let Some(input) = self.continuous.take() {
+            self.bus.write_register(CONFIGREGISTER, self.config.register(input, true))?;
+        }
+        Ok(())
+    }
+
+    fn read_conversion(&mut self) -> io::Result<i16> {
+        // the 12 bit conversion is left aligned, the arithmetic shift keeps the sign
+        Ok(self.bus.read_register(CONVERSIONREGISTER)? as i16 >> 4)
+    }
+}
+
+impl<B: Ads1015Bus> Debug for Ads1015<B> {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("Ads1015")
+            .field("config", &self.config)
+            .field("continuous", &self.continuous)
+            .finish_non_exhaustive()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::sync::{Arc,Mutex};
+
+    /// The registers of an ADS1015 whose conversions take `busy_reads` reads of the config register
+    #[derive(Default)]
+    struct FakeRegisters {
+        registers: [u16;4],
+        writes: Vec<(u8, u16)>,
+        busy_reads: u32,
+        conversion_busy: u32,
+    }
+
+    #[derive(Clone,Default)]
+    struct FakeBus(Arc<Mutex<FakeRegisters>>);
+
+    impl Ads1015Bus for FakeBus {
+        fn write_register(&mut self, register: u8, value: u16) -> io::Result<()> {
+            let mut fake = self.0.lock().unwrap();
+            fake.writes.push((register, value));
+            if register == CONFIGREGISTER && value & OPERATIONALSTATUS != 0 {
+                fake.conversion_busy = fake.busy_reads;
+                fake.registers[CONFIGREGISTER as usize] = value & !OPERATIONALSTATUS;
+            } else {
+                fake.registers[register as usize] = value;
+            }
+            Ok(())
+        }
+
+        fn read_register(&mut self, register: u8) -> io::Result<u16> {
+            let mut fake = self.0.lock().unwrap();
+            if register == CONFIGREGISTER {
+                if fake.conversion_busy == 0 {
+                    return Ok(fake.registers[1] | OPERATIONALSTATUS);
+                }
+                if fake.conversion_busy != u32::MAX {
+                    fake.conversion_busy -= 1;
+                }
+            }
+            Ok(fake.registers[register as usize])
+        }
+    }
+
+    #[test]
+    fn single_shot_and_continuous() {
+        let bus = FakeBus::default();
+        bus.0.lock().unwrap().busy_reads = 3;
+        let mut adc = Ads1015::new(bus.clone(), Ads1015Config::default()).unwrap();
+
+        bus.0.lock().unwrap().registers[0] = 0x7ff0;
+        assert_eq!(adc.read_single(Ads1015Input::single(3)).unwrap(), 2047);
+        assert_eq!(bus.0.lock().unwrap().writes.last(), Some(&(CONFIGREGISTER, 0xf3c3)));
+        assert_eq!(Ads1015Config::default().gain.microvolts(2047), 4_094_000);
+        bus.0.lock().unwrap().registers[0] = 0xfff0;
+        assert_eq!(adc.read_single(Ads1015Input::Differential0_1).unwrap(), -1);
+
+        assert!(adc.read_latest().is_err());
+        adc.start_continuous(Ads1015Input::Single1).unwrap();
+        bus.0.lock().unwrap().registers[0] = 0x1230;
+        assert_eq!(adc.read_latest().unwrap(), 0x123);
+        assert_eq!(bus.0.lock().unwrap().registers[1], 0x52c3);
+        adc.stop().unwrap();
+        assert_eq!(bus.0.lock().unwrap().registers[1], 0x53c3);
+
+        bus.0.lock().unwrap().busy_reads = u32::MAX;
+        assert_eq!(adc.read_single(Ads1015Input::Single0).unwrap_err().kind(), io::ErrorKind::TimedOut);
+    }
+
+    #[test]
+    fn alerts() {
+        let bus = FakeBus::default();
+        let config = Ads1015Config::new(Ads1015Gain::Fsr6_144V, Ads1015DataRate::Sps128)
+            .with_alert(Ads1015Alert::Window { low: -100, high: 1000, queue: Ads1015Queue::Two, active_high: true, latching: true });
+        let mut adc = Ads1015::new(bus.clone(), config).unwrap();
+        adc.start_continuous(Ads1015Input::Single2).unwrap();
+        assert_eq!(bus.0.lock().unwrap().registers, [0, 0x601d, (-100i16 << 4) as u16, 1000 << 4]);
+
+        adc.configure(Ads1015Config::default().with_alert(Ads1015Alert::ConversionReady { active_high: false })).unwrap();
+        assert_eq!(bus.0.lock().unwrap().registers, [0, 0x62c0, 0x0000, 0x8000]);
+        assert_eq!(adc.continuous_input(), Some(Ads1015Input::Single2));
+
+        // thresholds beyond the 12 bit conversions are clamped instead of overflowing the registers
+        let alert = Ads1015Alert::Traditional { low: -3000, high: 4000, queue: Ads1015Queue::One, active_high: false, latching: false };
+        let config = Ads1015Config::default().with_alert(alert);
+        assert_eq!(config.alert, Ads1015Alert::Traditional { low: -2048, high: 2047, queue: Ads1015Queue::One, active_high: false, latching: false });
+        adc.configure(Ads1015Config { alert, ..config }).unwrap();
+        assert_eq!(bus.0.lock().unwrap().registers[2..], [0x8000, 0x7ff0]);
+    }
+}
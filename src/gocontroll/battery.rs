@@ -0,0 +1,450 @@
+use std::collections::VecDeque;
+use std::ops::ControlFlow;
+use std::sync::{Arc,Mutex,PoisonError,mpsc};
+use std::time::{Duration,Instant};
+#[cfg(feature = "serde")]
+use serde::{Serialize,Deserialize};
+
+use super::{calibration::Millivolts,mainboard::AdcChannel,sampler::{SupplyReadings,SupplySample,Subscribers}};
+
+/// The number of finished events a [BatterySupervisor] keeps
+pub const EVENTLOG: usize = 64;
+
+#[derive(Debug,Copy,Clone,PartialEq,Eq)]
+#[cfg_attr(feature = "serde", derive(Serialize,Deserialize))]
+/// A dip of `drop` below the battery voltage before it, that comes back within `recovery` of that voltage before `max_duration` passed, is an engine crank.
+/// The undervoltage alarm waits for the end of a dip, a dip that lasts longer than `max_duration` is left to the undervoltage alarm
+pub struct CrankConfig {
+    pub drop: Millivolts,
+    pub recovery: Millivolts,
+    pub max_duration: Duration,
+}
+
+impl Default for CrankConfig {
+    /// A dip of 2 V that comes back within 1.5 V in at most 3 s
+    fn default() -> Self {
+        CrankConfig::new(Millivolts(2000), Millivolts(1500), Duration::from_secs(3))
+    }
+}
+
+impl CrankConfig {
+    pub const fn new(drop: Millivolts, recovery: Millivolts, max_duration: Duration) -> CrankConfig {
+        CrankConfig { drop, recovery, max_duration }
+    }
+}
+
+#[derive(Debug,Copy,Clone,PartialEq,Eq)]
+#[cfg_attr(feature = "serde", derive(Serialize,Deserialize))]
+/// The limits of the K30 supply, an alarm is raised once the voltage stayed beyond a limit for its delay
+/// and cleared once the voltage came back `hysteresis` within the limit
+pub struct BatteryConfig {
+    pub undervoltage: Millivolts,
+    pub undervoltage_delay: Duration,
+    pub overvoltage: Millivolts,
+    pub overvoltage_delay: Duration,
+    pub hysteresis: Millivolts,
+    /// None disables the crank detection
+    pub crank: Option<CrankConfig>,
+}
+
+impl Default for BatteryConfig {
+    fn default() -> Self {
+        BatteryConfig::nominal_12v()
+    }
+}
+
+impl BatteryConfig {
+    /// The limits with delays of 500 ms, a hysteresis of 500 mV and the default crank detection
+    pub const fn new(undervoltage: Millivolts, overvoltage: Millivolts) -> BatteryConfig {
+        BatteryConfig {
+            undervoltage,
+            undervoltage_delay: Duration::from_millis(500),
+            overvoltage,
+            overvoltage_delay: Duration::from_millis(500),
+            hysteresis: Millivolts(500),
+            crank: Some(CrankConfig::new(Millivolts(2000), Millivolts(1500), Duration::from_secs(3))),
+        }
+    }
+
+    /// Under 10.5 V and over 16 V
+    pub const fn nominal_12v() -> BatteryConfig {
+        BatteryConfig::new(Millivolts(10500), Millivolts(16000))
+    }
+
+    /// Under 21 V and over 32 V
+    pub const fn nominal_24v() -> BatteryConfig {
+        BatteryConfig::new(Millivolts(21000), Millivolts(32000))
+    }
+
+    pub const fn with_delays(mut self, undervoltage: Duration, overvoltage: Duration) -> BatteryConfig {
+        self.undervoltage_delay = undervoltage;
+        self.overvoltage_delay = overvoltage;
+        self
+    }
+
+    pub const fn with_hysteresis(mut self, hysteresis: Millivolts) -> BatteryConfig {
+        self.hysteresis = hysteresis;
+        self
+    }
+
+    pub const fn with_crank(mut self, crank: Option<CrankConfig>) -> BatteryConfig {
+        self.crank = crank;
+        self
+    }
+}
+
+#[allow(unused)]
+#[derive(Debug,Copy,Clone,PartialEq,Eq)]
+#[cfg_attr(feature = "serde", derive(Serialize,Deserialize))]
+pub enum BatteryCondition {
+    Undervoltage,
+    Overvoltage,
+    Crank,
+}
+
+#[derive(Debug,Copy,Clone,PartialEq,Eq)]
+/// A finished excursion of the battery voltage, from the first reading beyond the limit to the reading that ended it
+pub struct BatteryEvent {
+    pub condition: BatteryCondition,
+    pub start: Instant,
+    pub duration: Duration,
+    pub min: Millivolts,
+    pub max: Millivolts,
+}
+
+#[derive(Debug,Copy,Clone,PartialEq,Eq)]
+/// Raised    -> The voltage stayed beyond a limit for its delay\
+/// Cleared   -> The voltage of a raised condition came back, with the record of the whole event\
+/// Crank     -> The voltage recovered from an engine crank
+pub enum BatteryAlarm {
+    Raised { condition: BatteryCondition, time: Instant, voltage: Millivolts },
+    Cleared(BatteryEvent),
+    Crank(BatteryEvent),
+}
+
+#[derive(Debug,Copy,Clone)]
+struct Excursion {
+    start: Instant,
+    min: Millivolts,
+    max: Millivolts,
+    raised: bool,
+}
+
+impl Excursion {
+    fn new(sample: SupplySample) -> Excursion {
+        Excursion { start: sample.time, min: sample.voltage, max: sample.voltage, raised: false }
+    }
+
+    fn extend(&mut self, voltage: Millivolts) {
+        self.min = self.min.min(voltage);
+        self.max = self.max.max(voltage);
+    }
+
+    fn event(&self, condition: BatteryCondition, end: Instant) -> BatteryEvent {
+        BatteryEvent { condition, start: self.start, duration: end.saturating_duration_since(self.start), min: self.min, max: self.max }
+    }
+}
+
+#[derive(Debug,Clone)]
+/// The battery state machine, fed with K30 readings by a [BatterySupervisor] or by hand
+pub struct BatteryDetector {
+    config: BatteryConfig,
+    undervoltage: Option<Excursion>,
+    overvoltage: Option<Excursion>,
+    dip: Option<Excursion>,
+    /// The average battery voltage outside of dips, in mV
+    reference: Option<i32>,
+    events: VecDeque<BatteryEvent>,
+}
+
+#[allow(unused)]
+impl BatteryDetector {
+    pub fn new(config: BatteryConfig) -> BatteryDetector {
+        BatteryDetector { config, undervoltage: None, overvoltage: None, dip: None, reference: None, events: VecDeque::new() }
+    }
+
+    pub fn config(&self) -> &BatteryConfig {
+        &self.config
+    }
+
+    /// Whether `condition` is raised, for [BatteryCondition::Crank] whether a dip is in progress
+    pub fn is_raised(&self, condition: BatteryCondition) -> bool {
+        match condition {
+            BatteryCondition::Undervoltage => self.undervoltage.is_some_and(|excursion| excursion.raised),
+            BatteryCondition::Overvoltage => self.overvoltage.is_some_and(|excursion| excursion.raised),
+            BatteryCondition::Crank => self.dip.is_some(),
+        }
+    }
+
+    /// The last [EVENTLOG] finished events, oldest first
+    pub fn events(&self) -> impl Iterator<Item = &BatteryEvent> {
+        self.events.iter()
+    }
+
+    /// Process a K30 reading
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use std::time::{Duration,Instant};
+    /// use gocontroll_platform::gocontroll::{calibration::Millivolts,battery::*,sampler::SupplySample};
+    /// let start = Instant::now();
+    /// let mut detector = BatteryDetector::new(BatteryConfig::nominal_12v().with_crank(None));
+    /// let k30 = |ms: u64, mv: u16| SupplySample { time: start + Duration::from_millis(ms), voltage: Millivolts(mv) };
+    /// assert!(detector.update(k30(0, 9800)).is_empty());
+    /// assert_eq!(detector.update(k30(500, 9500)), [BatteryAlarm::Raised { condition: BatteryCondition::Undervoltage, time: start + Duration::from_millis(500), voltage: Millivolts(9500) }]);
+    /// assert!(matches!(detector.update(k30(800, 12500))[..], [BatteryAlarm::Cleared(BatteryEvent { min: Millivolts(9500), .. })]));
+    /// ```
+    pub fn update(&mut self, sample: SupplySample) -> Vec<BatteryAlarm> {
+        let mut alarms = Vec::new();
+        let voltage = sample.voltage.0;
+        let (config, hysteresis) = (self.config, self.config.hysteresis.0);
+        if self.track_crank(sample, &mut alarms) {
+            // the voltage below the limit during the crank was part of the crank
+            self.undervoltage = self.undervoltage.filter(|excursion| excursion.raised);
+        }
+        self.track(BatteryCondition::Undervoltage, sample, voltage < config.undervoltage.0,
+            voltage >= config.undervoltage.0.saturating_add(hysteresis), Some(config.undervoltage_delay).filter(|_| self.dip.is_none()), &mut alarms);
+        self.track(BatteryCondition::Overvoltage, sample, voltage > config.overvoltage.0,
+            voltage <= config.overvoltage.0.saturating_sub(hysteresis), Some(config.overvoltage_delay), &mut alarms);
+        alarms
+    }
+
+    /// Follow the dips of the battery voltage, true when a crank ended
+    fn track_crank(&mut self, sample: SupplySample, alarms: &mut Vec<BatteryAlarm>) -> bool {
+        let Some(crank) = self.config.crank else {
+            return false;
+        };
+        let voltage = sample.voltage.0 as i32;
+        match (&mut self.dip, self.reference) {
+            (Some(dip), Some(reference)) => {
+                if voltage + crank.recovery.0 as i32 >= reference {
+                    let event = dip.event(BatteryCondition::Crank, sample.time);
+                    self.dip = None;
+                    self.record(event);
+                    alarms.push(BatteryAlarm::Crank(event));
+                    return true;
+                } else if sample.time.saturating_duration_since(dip.start) > crank.max_duration {
+                    // not a crank but a lasting drop, the undervoltage alarm covers it
+                    self.dip = None;
+                    self.reference = Some(voltage);
+                } else {
+                    dip.extend(sample.voltage);
+                }
+            },
+            (None, Some(reference)) if voltage + crank.drop.0 as i32 <= reference => self.dip = Some(Excursion::new(sample)),
+            (_, reference) => self.reference = Some(reference.map_or(voltage, |reference| reference + (voltage - reference) / 8)),
+        }
+        false
+    }
+
+    /// Follow the excursion of `condition`, a `delay` of None holds the alarm back while the excursion keeps being followed
+    fn track(&mut self, condition: BatteryCondition, sample: SupplySample, outside: bool, recovered: bool, delay: Option<Duration>, alarms: &mut Vec<BatteryAlarm>) {
+        let excursion = match condition {
+            BatteryCondition::Undervoltage => &mut self.undervoltage,
+            _ => &mut self.overvoltage,
+        };
+        match excursion {
+            None if outside => *excursion = Some(Excursion::new(sample)),
+            None => return,
+            Some(current) if recovered => {
+                let current = *current;
+                *excursion = None;
+                if current.raised {
+                    let event = current.event(condition, sample.time);
+                    self.record(event);
+                    alarms.push(BatteryAlarm::Cleared(event));
+                }
+                return;
+            },
+            Some(current) => current.extend(sample.voltage),
+        }
+        if let (Some(current), Some(delay)) = (excursion.as_mut().filter(|current| !current.raised), delay) {
+            if sample.time.saturating_duration_since(current.start) >= delay {
+                current.raised = true;
+                alarms.push(BatteryAlarm::Raised { condition, time: sample.time, voltage: sample.voltage });
+            }
+        }
+    }
+
+    fn record(&mut self, event: BatteryEvent) {
+        if self.events.len() >= EVENTLOG {
+            self.events.pop_front();
+        }
+        self.events.push_back(event);
+    }
+}
+
+struct SupervisorShared {
+    detector: Mutex<BatteryDetector>,
+    subscribers: Subscribers<BatteryAlarm>,
+}
+
+#[derive(Clone)]
+/// Watches the K30 supply through the readings of a [SupplySampler](super::sampler::SupplySampler) and publishes [BatteryAlarm]s,
+/// until [BatterySupervisor::stop] is called or the last clone of the supervisor is dropped. A callback holding a clone of the supervisor keeps it watching until it is stopped.
+/// It works on the calibrated voltages, so on every adc the controller can have.
+pub struct BatterySupervisor {
+    shared: Arc<SupervisorShared>,
+}
+
+#[allow(unused)]
+impl BatterySupervisor {
+    /// Start supervising the readings of a running sampler, see also [MainBoard::start_battery_supervisor](super::mainboard::MainBoard::start_battery_supervisor)
+    pub fn start(readings: &SupplyReadings, config: BatteryConfig) -> BatterySupervisor {
+        let shared = Arc::new(SupervisorShared {
+            detector: Mutex::new(BatteryDetector::new(config)),
+            subscribers: Subscribers::new(),
+        });
+        let weak = Arc::downgrade(&shared);
+        readings.subscribe(move |round| {
+            let Some(shared) = weak.upgrade().filter(|shared| !shared.subscribers.is_closed()) else {
+                return ControlFlow::Break(());
+            };
+            let Some(k30) = round[AdcChannel::K30.index()] else {
+                return ControlFlow::Continue(());
+            };
+            let alarms = shared.detector.lock().unwrap_or_else(PoisonError::into_inner).update(k30);
+            for alarm in &alarms {
+                shared.subscribers.publish(alarm);
+            }
+            ControlFlow::Continue(())
+        });
+        BatterySupervisor { shared }
+    }
+
+    fn read<R>(&self, f: impl FnOnce(&BatteryDetector) -> R) -> R {
+        f(&self.shared.detector.lock().unwrap_or_else(PoisonError::into_inner))
+    }
+
+    pub fn is_raised(&self, condition: BatteryCondition) -> bool {
+        self.read(|detector| detector.is_raised(condition))
+    }
+
+    /// The last [EVENTLOG] finished events, oldest first
+    pub fn events(&self) -> Vec<BatteryEvent> {
+        self.read(|detector| detector.events().copied().collect())
+    }
+
+    /// Call `callback` on the sampler thread for every following alarm
+    pub fn on_alarm<F>(&self, callback: F)
+    where
+        F: FnMut(&BatteryAlarm) + Send + 'static,
+    {
+        self.shared.subscribers.on_event(callback);
+    }
+
+    /// A channel receiving every following alarm, dropping the receiver unsubscribes it
+    pub fn subscribe(&self) -> mpsc::Receiver<BatteryAlarm> {
+        self.shared.subscribers.subscribe()
+    }
+
+    /// Stop supervising for every clone of the supervisor, the callbacks are dropped and the channels disconnected.
+    /// The alarms and events stay readable as they were when the supervisor stopped.
+    pub fn stop(&self) {
+        self.shared.subscribers.close();
+    }
+}
+
+impl std::fmt::Debug for BatterySupervisor {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        self.read(|detector| f.debug_struct("BatterySupervisor").field("detector", detector).finish_non_exhaustive())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::sync::atomic::{AtomicU16,Ordering};
+    use crate::gocontroll::{calibration::{AdcCalibration,SupplyCalibration},sampler::{SamplerConfig,SupplySampler}};
+
+    #[test]
+    fn limits_and_cranks() {
+        let start = Instant::now();
+        let at = |ms: u64| start + Duration::from_millis(ms);
+        let k30 = |ms: u64, mv: u16| SupplySample { time: at(ms), voltage: Millivolts(mv) };
+        let mut detector = BatteryDetector::new(BatteryConfig::nominal_12v().with_delays(Duration::from_millis(200), Duration::from_millis(100)));
+
+        // a crank dips below the undervoltage limit for less than its delay
+        for ms in (0..100).step_by(10) {
+            assert!(detector.update(k30(ms, 12600)).is_empty());
+        }
+        assert!(detector.update(k30(100, 9000)).is_empty());
+        assert!(detector.is_raised(BatteryCondition::Crank));
+        assert!(detector.update(k30(150, 7800)).is_empty());
+        assert!(detector.update(k30(250, 11000)).is_empty());
+        let crank = BatteryEvent { condition: BatteryCondition::Crank, start: at(100), duration: Duration::from_millis(200), min: Millivolts(7800), max: Millivolts(11000) };
+        assert_eq!(detector.update(k30(300, 12200)), [BatteryAlarm::Crank(crank)]);
+        assert!(!detector.is_raised(BatteryCondition::Undervoltage));
+
+        // an overvoltage within the hysteresis stays raised
+        assert!(detector.update(k30(400, 16500)).is_empty());
+        assert_eq!(detector.update(k30(500, 17000)), [BatteryAlarm::Raised { condition: BatteryCondition::Overvoltage, time: at(500), voltage: Millivolts(17000) }]);
+        assert!(detector.update(k30(600, 15800)).is_empty());
+        assert!(detector.is_raised(BatteryCondition::Overvoltage));
+        let overvoltage = BatteryEvent { condition: BatteryCondition::Overvoltage, start: at(400), duration: Duration::from_millis(300), min: Millivolts(15800), max: Millivolts(17000) };
+        assert_eq!(detector.update(k30(700, 14000)), [BatteryAlarm::Cleared(overvoltage)]);
+
+        // a lasting drop isn't a crank
+        for ms in (800..5000).step_by(100) {
+            detector.update(k30(ms, 10000));
+        }
+        assert!(detector.is_raised(BatteryCondition::Undervoltage));
+        assert!(!detector.is_raised(BatteryCondition::Crank));
+        assert_eq!(detector.events().copied().collect::<Vec<_>>(), [crank, overvoltage]);
+    }
+
+    #[test]
+    fn long_crank() {
+        let start = Instant::now();
+        let at = |ms: u64| start + Duration::from_millis(ms);
+        let k30 = |ms: u64, mv: u16| SupplySample { time: at(ms), voltage: Millivolts(mv) };
+        let mut detector = BatteryDetector::new(BatteryConfig::nominal_12v());
+
+        // a crank of 1 s stays below the undervoltage limit for longer than its delay without raising it
+        for ms in (0..100).step_by(10) {
+            assert!(detector.update(k30(ms, 12600)).is_empty());
+        }
+        for ms in (100..1100).step_by(50) {
+            assert!(detector.update(k30(ms, 8000)).is_empty());
+        }
+        assert!(!detector.is_raised(BatteryCondition::Undervoltage));
+        let crank = BatteryEvent { condition: BatteryCondition::Crank, start: at(100), duration: Duration::from_millis(1000), min: Millivolts(8000), max: Millivolts(8000) };
+        assert_eq!(detector.update(k30(1100, 12000)), [BatteryAlarm::Crank(crank)]);
+        assert!(detector.update(k30(2000, 12000)).is_empty());
+        assert_eq!(detector.events().copied().collect::<Vec<_>>(), [crank]);
+
+        // a dip that lasts longer than a crank raises the undervoltage alarm, from the start of the dip
+        for ms in (2100..5100).step_by(100) {
+            assert!(detector.update(k30(ms, 9000)).is_empty());
+        }
+        assert_eq!(detector.update(k30(5200, 9000)), [BatteryAlarm::Raised { condition: BatteryCondition::Undervoltage, time: at(5200), voltage: Millivolts(9000) }]);
+        let undervoltage = BatteryEvent { condition: BatteryCondition::Undervoltage, start: at(2100), duration: Duration::from_millis(3200), min: Millivolts(9000), max: Millivolts(9000) };
+        assert_eq!(detector.update(k30(5300, 12000)), [BatteryAlarm::Cleared(undervoltage)]);
+    }
+
+    #[test]
+    fn supervisor_alarms() {
+        let k30 = Arc::new(AtomicU16::new(24000));
+        let unity = AdcCalibration::new(1.0, 0.0);
+        let read = k30.clone();
+        let sampler = SupplySampler::start(SamplerConfig::new(Duration::from_millis(1)), SupplyCalibration::new(unity, unity, unity, unity),
+            move |_| Ok(read.load(Ordering::Relaxed))).unwrap();
+        let supervisor = BatterySupervisor::start(&sampler.readings(), BatteryConfig::nominal_24v().with_delays(Duration::ZERO, Duration::ZERO).with_crank(None));
+        let alarms = supervisor.subscribe();
+        let (sender, callbacks) = mpsc::channel();
+        let queried = supervisor.clone();
+        supervisor.on_alarm(move |alarm| sender.send((*alarm, queried.is_raised(BatteryCondition::Undervoltage))).unwrap());
+
+        k30.store(20000, Ordering::Relaxed);
+        assert!(matches!(alarms.recv_timeout(Duration::from_secs(5)).unwrap(), BatteryAlarm::Raised { condition: BatteryCondition::Undervoltage, .. }));
+        assert!(matches!(callbacks.recv_timeout(Duration::from_secs(5)).unwrap(), (BatteryAlarm::Raised { .. }, true)));
+        assert!(supervisor.is_raised(BatteryCondition::Undervoltage));
+        k30.store(24000, Ordering::Relaxed);
+        let alarm = alarms.recv_timeout(Duration::from_secs(5)).unwrap();
+        assert!(matches!(alarm, BatteryAlarm::Cleared(BatteryEvent { condition: BatteryCondition::Undervoltage, min: Millivolts(20000), .. })));
+        assert_eq!(supervisor.events().len(), 1);
+        // the callback holds a clone of the supervisor
+        supervisor.stop();
+    }
+}
@@ -0,0 +1,223 @@
+use std::path::PathBuf;
+#[cfg(feature = "toml")]
+use std::{fs,io,path::Path};
+#[cfg(feature = "serde")]
+use serde::{Serialize,Deserialize};
+
+use super::mainboard::{ModuleLayout,LedControl};
+use super::calibration::SupplyCalibration;
+use super::error::{self,PlatformError};
+
+/// The adc input measuring K30, K15A, K15B and K15C on all Moduline controllers
+pub const DEFAULT_ADC_CHANNELS: [u8;4] = [3,0,1,2];
+
+#[allow(unused)]
+#[derive(Debug,Clone,Copy,PartialEq,Eq)]
+#[cfg_attr(feature = "serde", derive(Serialize,Deserialize))]
+/// The adc measuring the supply voltages of a controller
+pub enum AdcType {
+    None,
+    Mcp3004,
+    Ads1015,
+}
+
+/// Everything that differs between controller revisions
+#[derive(Debug,Clone)]
+#[cfg_attr(feature = "serde", derive(Serialize,Deserialize))]
+pub struct BoardDefinition {
+    /// The hardware string in the devicetree of the controller, a `*` matches any number of characters
+    pub hardware: String,
+    pub module_layout: ModuleLayout,
+    pub leds: LedControl,
+    pub adc: AdcType,
+    /// The adc input measuring K30, K15A, K15B and K15C
+    #[cfg_attr(feature = "serde", serde(default = "default_adc_channels"))]
+    pub adc_channels: [u8;4],
+    /// The calibration of the supply measurements of this revision, None uses the default calibration of the adc
+    #[cfg_attr(feature = "serde", serde(default))]
+    pub adc_calibration: Option<SupplyCalibration>,
+    /// The spidev of every module slot, None keeps the spidevs of the [PlatformPaths](super::mainboard::PlatformPaths)
+    #[cfg_attr(feature = "serde", serde(default))]
+    pub spidevs: Option<Vec<PathBuf>>,
+    /// The reset line of every module slot, None keeps the resets of the [PlatformPaths](super::mainboard::PlatformPaths)
+    #[cfg_attr(feature = "serde", serde(default))]
+    pub resets: Option<Vec<PathBuf>>,
+}
+
+#[cfg(feature = "serde")]
+const fn default_adc_channels() -> [u8;4] {
+    DEFAULT_ADC_CHANNELS
+}
+
+/// The built-in boards, none of them has calibration values of its own so they use [SupplyCalibration::default_for] their adc
+const BOARDS: [(&str, ModuleLayout, LedControl, AdcType);17] = [
+    ("Moduline IV V3.06", ModuleLayout::ModulineIV, LedControl::Rukr, AdcType::Mcp3004),
+    ("Moduline Mini V1.11", ModuleLayout::ModulineMini, LedControl::Rukr, AdcType::Mcp3004),
+    ("Moduline Screen V1.04", ModuleLayout::ModulineDisplay, LedControl::Rukr, AdcType::Mcp3004),
+    ("Moduline IV V3.00", ModuleLayout::ModulineIV, LedControl::Gpio, AdcType::Ads1015),
+    ("Moduline IV V3.01", ModuleLayout::ModulineIV, LedControl::Gpio, AdcType::Ads1015),
+    ("Moduline IV V3.02", ModuleLayout::ModulineIV, LedControl::Rukr, AdcType::Ads1015),
+    ("Moduline IV V3.03", ModuleLayout::ModulineIV, LedControl::Rukr, AdcType::Ads1015),
+    ("Moduline IV V3.04", ModuleLayout::ModulineIV, LedControl::Rukr, AdcType::Ads1015),
+    ("Moduline IV V3.05", ModuleLayout::ModulineIV, LedControl::Rukr, AdcType::Ads1015),
+    ("Moduline Mini V1.03", ModuleLayout::ModulineMini, LedControl::Rukr, AdcType::Ads1015),
+    ("Moduline Mini V1.05", ModuleLayout::ModulineMini, LedControl::Rukr, AdcType::Mcp3004),
+    ("Moduline Mini V1.06", ModuleLayout::ModulineMini, LedControl::Rukr, AdcType::Mcp3004),
+    ("Moduline Mini V1.07", ModuleLayout::ModulineMini, LedControl::Rukr, AdcType::Mcp3004),
+    ("Moduline Mini V1.10", ModuleLayout::ModulineMini, LedControl::None, AdcType::Mcp3004),
+    ("Moduline Screen V1.02", ModuleLayout::ModulineDisplay, LedControl::None, AdcType::Mcp3004),
+    ("Moduline Screen V1.03", ModuleLayout::ModulineDisplay, LedControl::None, AdcType::Mcp3004),
+    ("Moduline Screen V1.05", ModuleLayout::ModulineDisplay, LedControl::None, AdcType::Mcp3004),
+];
+
+#[cfg(feature = "toml")]
+#[derive(Deserialize)]
+struct BoardFile {
+    board: Vec<BoardDefinition>,
+}
+
+/// The board definitions a [MainBoard](super::mainboard::MainBoard) picks from, the first definition matching the hardware string wins
+#[derive(Debug,Clone)]
+pub struct BoardTable {
+    boards: Vec<BoardDefinition>,
+}
+
+impl Default for BoardTable {
+    fn default() -> Self {
+        Self::builtin()
+    }
+}
+
+#[allow(unused)]
+impl BoardDefinition {
+    pub fn new(hardware: &str, module_layout: ModuleLayout, leds: LedControl, adc: AdcType) -> BoardDefinition {
+        BoardDefinition {
+            hardware: hardware.to_string(),
+            module_layout,
+            leds,
+            adc,
+            adc_channels: DEFAULT_ADC_CHANNELS,
+            adc_calibration: None,
+            spidevs: None,
+            resets: None,
+        }
+    }
+
+    /// The calibration of the supply measurements on this revision, before the calibration of the unit itself is applied
+    pub fn supply_calibration(&self) -> SupplyCalibration {
+        self.adc_calibration.unwrap_or(SupplyCalibration::default_for(self.adc))
+    }
+
+    /// Check if `hardware`, the string from the devicetree, is described by this definition
+    pub fn matches(&self, hardware: &str) -> bool {
+        let mut parts = self.hardware.split('*');
+        let first = parts.next().unwrap_or_default();
+        let Some(mut rest) = hardware.strip_prefix(first) else {
+            return false;
+        };
+        let mut parts = parts.peekable();
+        if parts.peek().is_none() {
+            return rest.is_empty();
+        }
+        while let Some(part) = parts.next() {
+            if parts.peek().is_none() {
+                return rest.ends_with(part);
+            }
+            match rest.find(part) {
+                Some(index) => rest = &rest[index+part.len()..],
+                None => return false,
+            }
+        }
+        true
+    }
+}
+
+#[allow(unused)]
+impl BoardTable {
+    /// The definitions of all known Moduline controllers
+    pub fn builtin() -> BoardTable {
+        BoardTable {
+            boards: BOARDS.iter()
+                .map(|(hardware, layout, leds, adc)| BoardDefinition::new(hardware, *layout, *leds, *adc))
+                .collect(),
+        }
+    }
+
+    /// A table without any definitions
+    pub fn empty() -> BoardTable {
+        BoardTable { boards: Vec::new() }
+    }
+
+    /// Add a definition in front of the table, so it takes precedence over the definitions already in it
+    pub fn insert(&mut self, board: BoardDefinition) {
+        self.boards.insert(0, board);
+    }
+
+    /// Put all definitions of `boards` in front of this table
+    pub fn overlay(mut self, mut boards: BoardTable) -> BoardTable {
+        boards.boards.append(&mut self.boards);
+        boards
+    }
+
+    pub fn boards(&self) -> &[BoardDefinition] {
+        &self.boards
+    }
+
+    /// Find the definition of the controller with `hardware` as hardware string
+    pub fn find(&self, hardware: &str) -> error::Result<&BoardDefinition> {
+        self.boards.iter()
+            .find(|board| board.matches(hardware))
+            .ok_or_else(|| PlatformError::UnknownHardware(hardware.to_string()))
+    }
+
+    #[cfg(feature = "toml")]
+    /// Parse board definitions from a toml document with a `[[board]]` table per definition
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use gocontroll_platform::gocontroll::{board::BoardTable,mainboard::ModuleLayout};
+    /// let boards = BoardTable::from_toml(r#"
+    ///     [[board]]
+    ///     hardware = "Moduline IV V4.*"
+    ///     module_layout = "ModulineIV"
+    ///     leds = "Rukr"
+    ///     adc = "Mcp3004"
+    ///     spidevs = ["/dev/spidev1.0", "/dev/spidev1.1"]
+    /// "#).unwrap();
+    /// assert_eq!(boards.find("Moduline IV V4.01").unwrap().module_layout, ModuleLayout::ModulineIV);
+    /// ```
+    pub fn from_toml(document: &str) -> error::Result<BoardTable> {
+        let file: BoardFile = toml::from_str(document).map_err(|err| io::Error::new(io::ErrorKind::InvalidData, err))?;
+        Ok(BoardTable { boards: file.board })
+    }
+
+    #[cfg(feature = "toml")]
+    /// Read board definitions from a toml file, see [BoardTable::from_toml]
+    pub fn load<P: AsRef<Path>>(path: P) -> error::Result<BoardTable> {
+        Self::from_toml(&fs::read_to_string(path)?)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn board_lookup() {
+        let boards = BoardTable::builtin();
+        assert_eq!(boards.find("Moduline Mini V1.05").unwrap().adc, AdcType::Mcp3004);
+        assert_eq!(boards.find("Moduline IV V3.03").unwrap().adc, AdcType::Ads1015);
+        assert!(matches!(boards.find("Moduline IV V3.03 "), Err(PlatformError::UnknownHardware(_))));
+        assert!(matches!(boards.find("Moduline IV V9.00"), Err(PlatformError::UnknownHardware(_))));
+
+        let boards = boards.overlay({
+            let mut custom = BoardTable::empty();
+            custom.insert(BoardDefinition::new("Moduline * V9.*", ModuleLayout::ModulineMini, LedControl::None, AdcType::None));
+            custom
+        });
+        assert_eq!(boards.find("Moduline IV V9.00").unwrap().module_layout, ModuleLayout::ModulineMini);
+        assert!(boards.find("Moduline IV V8.00").is_err());
+        assert_eq!(boards.find("Moduline IV V3.03").unwrap().module_layout, ModuleLayout::ModulineIV);
+    }
+}
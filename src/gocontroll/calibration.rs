@@ -0,0 +1,155 @@
+use core::fmt::Display;
+use std::io;
+#[cfg(feature = "serde")]
+use serde::{Serialize,Deserialize};
+
+use super::board::AdcType;
+
+/// The names of the supply measurements in the order of [BoardDefinition::adc_channels](super::board::BoardDefinition::adc_channels)
+pub const SUPPLY_NAMES: [&str;4] = ["K30", "K15A", "K15B", "K15C"];
+
+#[derive(Debug,Copy,Clone,PartialEq,Eq,PartialOrd,Ord,Hash,Default)]
+#[cfg_attr(feature = "serde", derive(Serialize,Deserialize))]
+/// A voltage in mV
+pub struct Millivolts(pub u16);
+
+impl Display for Millivolts {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{} mV", self.0)
+    }
+}
+
+#[derive(Debug,Copy,Clone,PartialEq)]
+#[cfg_attr(feature = "serde", derive(Serialize,Deserialize))]
+/// Converts a raw adc reading into mV as `raw * gain + offset`
+pub struct AdcCalibration {
+    /// mV per adc count
+    pub gain: f32,
+    /// mV added to every reading
+    pub offset: f32,
+}
+
+#[derive(Debug,Copy,Clone,PartialEq,Eq)]
+/// A raw adc reading taken with a known reference voltage applied to the input
+pub struct CalibrationPoint {
+    pub raw: u16,
+    pub reference: Millivolts,
+}
+
+impl AdcCalibration {
+    pub const fn new(gain: f32, offset: f32) -> AdcCalibration {
+        AdcCalibration { gain, offset }
+    }
+
+    /// The calibration of the voltage dividers in front of `adc`, used when neither the board definition nor the controller holds one
+    pub const fn default_for(adc: AdcType) -> AdcCalibration {
+        match adc {
+            AdcType::Mcp3004 => AdcCalibration::new(25.54, 0.0),
+            AdcType::Ads1015 => AdcCalibration::new(15.608, 0.0),
+            AdcType::None => AdcCalibration::new(0.0, 0.0),
+        }
+    }
+
+    /// The calibration through two points, None when both points have the same raw reading
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use gocontroll_platform::gocontroll::calibration::*;
+    /// let calibration = AdcCalibration::from_points(
+    ///     CalibrationPoint { raw: 470, reference: Millivolts(12000) },
+    ///     CalibrationPoint { raw: 940, reference: Millivolts(24000) }).unwrap();
+    /// assert_eq!(calibration.millivolts(705), Millivolts(18000));
+    /// ```
+    pub fn from_points(first: CalibrationPoint, second: CalibrationPoint) -> Option<AdcCalibration> {
+        if first.raw == second.raw {
+            return None;
+        }
+        let gain = (second.reference.0 as f32 - first.reference.0 as f32) / (second.raw as f32 - first.raw as f32);
+        Some(AdcCalibration::new(gain, first.reference.0 as f32 - first.raw as f32 * gain))
+    }
+
+    /// Convert a raw reading, limited to the range of [Millivolts]
+    pub fn millivolts(&self, raw: u16) -> Millivolts {
+        Millivolts((raw as f32 * self.gain + self.offset).round().clamp(0.0, u16::MAX as f32) as u16)
+    }
+}
+
+#[derive(Debug,Copy,Clone,PartialEq)]
+#[cfg_attr(feature = "serde", derive(Serialize,Deserialize), serde(transparent))]
+/// The calibration of the K30, K15A, K15B and K15C measurements
+pub struct SupplyCalibration {
+    pub channels: [AdcCalibration;4],
+}
+
+impl SupplyCalibration {
+    pub const fn new(k30: AdcCalibration, k15a: AdcCalibration, k15b: AdcCalibration, k15c: AdcCalibration) -> SupplyCalibration {
+        SupplyCalibration { channels: [k30, k15a, k15b, k15c] }
+    }
+
+    /// The default calibration of `adc` on all measurements
+    pub const fn default_for(adc: AdcType) -> SupplyCalibration {
+        let calibration = AdcCalibration::default_for(adc);
+        SupplyCalibration::new(calibration, calibration, calibration, calibration)
+    }
+
+    /// Apply the calibration file of a controller, it holds a line with the name, gain and offset of every measurement calibrated on that unit,
+    /// `#` starts a comment. The measurements that aren't in the file keep their calibration.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use gocontroll_platform::gocontroll::{board::AdcType,calibration::*};
+    /// let calibration = SupplyCalibration::default_for(AdcType::Mcp3004).with_overrides("K30 25.61 -12.5 # calibrated at 12 V and 24 V").unwrap();
+    /// assert_eq!(calibration.channels[0], AdcCalibration::new(25.61, -12.5));
+    /// assert_eq!(calibration.channels[1], AdcCalibration::new(25.54, 0.0));
+    /// ```
+    pub fn with_overrides(mut self, file: &str) -> io::Result<SupplyCalibration> {
+        let invalid = |line: &str| io::Error::new(io::ErrorKind::InvalidData, format!("invalid calibration line \"{}\"", line));
+        for line in file.lines() {
+            let line = line.split('#').next().unwrap_or_default().trim();
+            if line.is_empty() {
+                continue;
+            }
+            let mut fields = line.split_whitespace();
+            let index = fields.next()
+                .and_then(|name| SUPPLY_NAMES.iter().position(|supply| *supply == name))
+                .ok_or_else(|| invalid(line))?;
+            let mut number = || fields.next().and_then(|field| field.parse::<f32>().ok()).ok_or_else(|| invalid(line));
+            self.channels[index] = AdcCalibration::new(number()?, number()?);
+            if fields.next().is_some() {
+                return Err(invalid(line));
+            }
+        }
+        Ok(self)
+    }
+}
+
+impl Display for SupplyCalibration {
+    /// The calibration in the format of the calibration file of a controller
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        for (name, calibration) in SUPPLY_NAMES.iter().zip(self.channels) {
+            writeln!(f, "{} {} {}", name, calibration.gain, calibration.offset)?;
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn calibration_file() {
+        let calibration = SupplyCalibration::default_for(AdcType::Ads1015)
+            .with_overrides("# unit 1234\nK15C 15.5 3\n\nK30 15.7 -20\n").unwrap();
+        assert_eq!(calibration.channels, [AdcCalibration::new(15.7, -20.0), AdcCalibration::new(15.608, 0.0), AdcCalibration::new(15.608, 0.0), AdcCalibration::new(15.5, 3.0)]);
+        assert_eq!(SupplyCalibration::default_for(AdcType::None).with_overrides(&calibration.to_string()).unwrap(), calibration);
+        assert!(calibration.with_overrides("K31 15.7 0").is_err());
+        assert!(calibration.with_overrides("K30 15.7").is_err());
+        assert!(calibration.with_overrides("K30 15.7 0 1").is_err());
+
+        assert_eq!(AdcCalibration::new(15.7, -20.0).millivolts(1), Millivolts(0));
+        assert!(AdcCalibration::from_points(CalibrationPoint { raw: 10, reference: Millivolts(0) }, CalibrationPoint { raw: 10, reference: Millivolts(5) }).is_none());
+    }
+}
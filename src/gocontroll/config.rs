@@ -0,0 +1,245 @@
+use std::{io,path::Path};
+#[cfg(any(feature = "toml", feature = "json"))]
+use std::fs;
+use serde::{Serialize,Deserialize};
+
+use super::{module::{GOcontrollModule,ModuleSlot,ModuleTransport,SpidevTransport},
+    inputmodule6ch::{InputModule6Ch,InputModule6ChConfig,Inputmodule6chSupplyConfig,InputModuleSupply},
+    inputmodule10ch::{InputModule10Ch,InputModule10ChConfig},
+    inputmodule420ma::{InputModule420mA,InputModule420mAConfig},
+    outputmodule6ch::{OutputModule6Ch,OutputModule6ChConfig,OutputModule6ChFrequecyConfig},
+    outputmodule10ch::{OutputModule10Ch,OutputModule10ChConfig,OutputModule10ChFrequencyConfig},
+    mainboard::MainBoard,
+    error::{self,ConfigError}};
+
+#[derive(Debug,Copy,Clone,Serialize,Deserialize)]
+/// The configuration of a single channel of a module, the settings of the channel sit next to its number
+pub struct ChannelConfig<C> {
+    /// The number of the channel, starting at 1 like the channel enums of the drivers
+    pub channel: u8,
+    #[serde(flatten)]
+    pub config: C,
+}
+
+impl<C> ChannelConfig<C> {
+    pub const fn new(channel: u8, config: C) -> ChannelConfig<C> {
+        ChannelConfig { channel, config }
+    }
+}
+
+#[derive(Debug,Clone,Serialize,Deserialize)]
+#[serde(tag = "type")]
+/// The configuration of a module, `type` selects the module and channels that aren't listed are unused
+pub enum ModuleConfig {
+    InputModule6Ch {
+        slot: ModuleSlot,
+        supplies: Inputmodule6chSupplyConfig,
+        #[serde(default, rename = "channel")]
+        channels: Vec<ChannelConfig<InputModule6ChConfig>>,
+    },
+    InputModule10Ch {
+        slot: ModuleSlot,
+        supply: InputModuleSupply,
+        #[serde(default, rename = "channel")]
+        channels: Vec<ChannelConfig<InputModule10ChConfig>>,
+    },
+    InputModule420mA {
+        slot: ModuleSlot,
+        supplies: [InputModuleSupply;3],
+        #[serde(default, rename = "channel")]
+        channels: Vec<ChannelConfig<InputModule420mAConfig>>,
+    },
+    OutputModule6Ch {
+        slot: ModuleSlot,
+        frequencies: OutputModule6ChFrequecyConfig,
+        #[serde(default, rename = "channel")]
+        channels: Vec<ChannelConfig<OutputModule6ChConfig>>,
+    },
+    OutputModule10Ch {
+        slot: ModuleSlot,
+        frequencies: OutputModule10ChFrequencyConfig,
+        #[serde(default, rename = "channel")]
+        channels: Vec<ChannelConfig<OutputModule10ChConfig>>,
+    },
+}
+
+impl ModuleConfig {
+    pub fn slot(&self) -> ModuleSlot {
+        match self {
+            ModuleConfig::InputModule6Ch { slot, .. } |
+            ModuleConfig::InputModule10Ch { slot, .. } |
+            ModuleConfig::InputModule420mA { slot, .. } |
+            ModuleConfig::OutputModule6Ch { slot, .. } |
+            ModuleConfig::OutputModule10Ch { slot, .. } => *slot,
+        }
+    }
+
+    /// Create the driver of the module, fails when a channel doesn't exist on the module or is listed twice
+    pub fn build<T: ModuleTransport>(&self) -> error::Result<ConfiguredModule<T>> {
+        Ok(match self {
+            ModuleConfig::InputModule6Ch { slot, supplies, channels } =>
+                ConfiguredModule::InputModule6Ch(InputModule6Ch::new(*slot, place_channels(channels)?, *supplies)),
+            ModuleConfig::InputModule10Ch { slot, supply, channels } =>
+                ConfiguredModule::InputModule10Ch(InputModule10Ch::new(*slot, place_channels(channels)?, *supply)),
+            ModuleConfig::InputModule420mA { slot, supplies, channels } =>
+                ConfiguredModule::InputModule420mA(InputModule420mA::new(*slot, place_channels(channels)?, *supplies)),
+            ModuleConfig::OutputModule6Ch { slot, frequencies, channels } =>
+                ConfiguredModule::OutputModule6Ch(OutputModule6Ch::new(*slot, place_channels(channels)?, *frequencies)),
+            ModuleConfig::OutputModule10Ch { slot, frequencies, channels } =>
+                ConfiguredModule::OutputModule10Ch(OutputModule10Ch::new(*slot, place_channels(channels)?, *frequencies)),
+        })
+    }
+}
+
+/// Put every channel configuration at the index of its channel
+fn place_channels<C: Copy, const N: usize>(configs: &[ChannelConfig<C>]) -> Result<[Option<C>;N], ConfigError> {
+    let mut channels = [None;N];
+    for config in configs {
+        let index = (config.channel as usize).checked_sub(1)
+            .filter(|index| *index < N)
+            .ok_or(ConfigError::NoSuchChannel(config.channel))?;
+        if channels[index].replace(config.config).is_some() {
+            return Err(ConfigError::DuplicateChannel(config.channel));
+        }
+    }
+    Ok(channels)
+}
+
+#[allow(unused)]
+#[derive(Debug)]
+/// A module driver created from a [ModuleConfig], match on it to use the driver
+pub enum ConfiguredModule<T: ModuleTransport = SpidevTransport> {
+    InputModule6Ch(InputModule6Ch<T>),
+    InputModule10Ch(InputModule10Ch<T>),
+    InputModule420mA(InputModule420mA<T>),
+    OutputModule6Ch(OutputModule6Ch<T>),
+    OutputModule10Ch(OutputModule10Ch<T>),
+}
+
+impl<T: ModuleTransport> ConfiguredModule<T> {
+    /// Attach a transport to the module, see [ModuleTransport]
+    pub fn with_transport(self, transport: T) -> ConfiguredModule<T> {
+        match self {
+            ConfiguredModule::InputModule6Ch(module) => ConfiguredModule::InputModule6Ch(module.with_transport(transport)),
+            ConfiguredModule::InputModule10Ch(module) => ConfiguredModule::InputModule10Ch(module.with_transport(transport)),
+            ConfiguredModule::InputModule420mA(module) => ConfiguredModule::InputModule420mA(module.with_transport(transport)),
+            ConfiguredModule::OutputModule6Ch(module) => ConfiguredModule::OutputModule6Ch(module.with_transport(transport)),
+            ConfiguredModule::OutputModule10Ch(module) => ConfiguredModule::OutputModule10Ch(module.with_transport(transport)),
+        }
+    }
+
+    pub fn module(&self) -> &dyn GOcontrollModule {
+        match self {
+            ConfiguredModule::InputModule6Ch(module) => module,
+            ConfiguredModule::InputModule10Ch(module) => module,
+            ConfiguredModule::InputModule420mA(module) => module,
+            ConfiguredModule::OutputModule6Ch(module) => module,
+            ConfiguredModule::OutputModule10Ch(module) => module,
+        }
+    }
+
+    pub fn module_mut(&mut self) -> &mut dyn GOcontrollModule {
+        match self {
+            ConfiguredModule::InputModule6Ch(module) => module,
+            ConfiguredModule::InputModule10Ch(module) => module,
+            ConfiguredModule::InputModule420mA(module) => module,
+            ConfiguredModule::OutputModule6Ch(module) => module,
+            ConfiguredModule::OutputModule10Ch(module) => module,
+        }
+    }
+
+    /// The driver as a trait object, like the drivers created by the [ModuleRegistry](super::registry::ModuleRegistry)
+    pub fn into_module(self) -> Box<dyn GOcontrollModule> {
+        match self {
+            ConfiguredModule::InputModule6Ch(module) => Box::new(module),
+            ConfiguredModule::InputModule10Ch(module) => Box::new(module),
+            ConfiguredModule::InputModule420mA(module) => Box::new(module),
+            ConfiguredModule::OutputModule6Ch(module) => Box::new(module),
+            ConfiguredModule::OutputModule10Ch(module) => Box::new(module),
+        }
+    }
+}
+
+#[derive(Debug,Clone,Default,Serialize,Deserialize)]
+/// The modules of a controller, a `[[module]]` table per module with a `[[module.channel]]` table per used channel
+pub struct ControllerConfig {
+    #[serde(default, rename = "module")]
+    pub modules: Vec<ModuleConfig>,
+}
+
+impl ControllerConfig {
+    #[cfg(feature = "toml")]
+    /// Parse a controller configuration from a toml document, channel settings are checked like the builders of the drivers check them
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use gocontroll_platform::gocontroll::{config::*,module::ModuleSlot};
+    /// let config = ControllerConfig::from_toml(r#"
+    ///     [[module]]
+    ///     type = "InputModule6Ch"
+    ///     slot = "Moduleslot1"
+    ///     supplies = ["On", "Off", "Off"]
+    ///
+    ///     [[module.channel]]
+    ///     channel = 1
+    ///     function = "AnalogmV"
+    ///     voltage_range = "Voltage0_12V"
+    ///     analog_filter_samples = 10
+    ///
+    ///     [[module]]
+    ///     type = "OutputModule6Ch"
+    ///     slot = "Moduleslot2"
+    ///     frequencies = ["Freq1KHz", "Freq1KHz", "Freq10KHz"]
+    ///
+    ///     [[module.channel]]
+    ///     channel = 5
+    ///     function = "HighSideDutyCycle"
+    ///     max_current = 2500
+    /// "#).unwrap();
+    /// assert_eq!(config.modules[1].slot(), ModuleSlot::Moduleslot2);
+    /// ```
+    pub fn from_toml(document: &str) -> error::Result<ControllerConfig> {
+        toml::from_str(document).map_err(|err| io::Error::new(io::ErrorKind::InvalidData, err).into())
+    }
+
+    #[cfg(feature = "toml")]
+    pub fn to_toml(&self) -> error::Result<String> {
+        toml::to_string(self).map_err(|err| io::Error::new(io::ErrorKind::InvalidData, err).into())
+    }
+
+    #[cfg(feature = "json")]
+    /// Parse a controller configuration from a json document with the layout of [ControllerConfig::from_toml]
+    pub fn from_json(document: &str) -> error::Result<ControllerConfig> {
+        serde_json::from_str(document).map_err(|err| io::Error::new(io::ErrorKind::InvalidData, err).into())
+    }
+
+    #[cfg(feature = "json")]
+    pub fn to_json(&self) -> error::Result<String> {
+        serde_json::to_string_pretty(self).map_err(|err| io::Error::new(io::ErrorKind::InvalidData, err).into())
+    }
+
+    /// Read a controller configuration from a `.toml` or `.json` file, each format needs its feature
+    pub fn load<P: AsRef<Path>>(path: P) -> error::Result<ControllerConfig> {
+        let path = path.as_ref();
+        match path.extension().and_then(|extension| extension.to_str()) {
+            #[cfg(feature = "toml")]
+            Some("toml") => Self::from_toml(&fs::read_to_string(path)?),
+            #[cfg(feature = "json")]
+            Some("json") => Self::from_json(&fs::read_to_string(path)?),
+            _ => Err(io::Error::new(io::ErrorKind::Unsupported, format!("unsupported configuration file {}", path.display())).into()),
+        }
+    }
+
+    /// Create the drivers of all modules without touching the hardware
+    pub fn build<T: ModuleTransport>(&self) -> error::Result<Vec<ConfiguredModule<T>>> {
+        self.modules.iter().map(ModuleConfig::build).collect()
+    }
+
+    /// Create the drivers of all modules and initialize the MainBoard with them, see [MainBoard::init]
+    pub fn init(&self, mainboard: &mut MainBoard) -> error::Result<Vec<ConfiguredModule>> {
+        let mut modules = self.build()?;
+        mainboard.init(&mut modules.iter_mut().map(ConfiguredModule::module_mut).collect::<Vec<_>>())?;
+        Ok(modules)
+    }
+}
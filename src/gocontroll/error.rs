@@ -0,0 +1,158 @@
+use core::fmt::Display;
+use std::io;
+
+#[cfg(feature = "modules")]
+use super::{mainboard::ModuleLayout,module::{ModuleSlot,EscapeBootloaderResponse,FirmwareVersion},outputmodule::OutputCommand};
+#[cfg(feature = "experimental")]
+use super::flasher::FlashStage;
+
+#[cfg(feature = "modules")]
+#[allow(unused)]
+#[derive(Debug,Copy,Clone,PartialEq,Eq)]
+/// The reasons a module channel configuration is rejected, `Copy` so the builders can return it from `const` contexts:
+/// PullUpAndPullDown   -> A pull-up and a pull-down were both set on the same input\
+/// NotApplicable       -> The setting has no effect with the function of the channel\
+/// MissingSetting      -> The function of the channel can't work without the setting\
+/// NoSuchChannel       -> The channel number doesn't exist on the module\
+/// DuplicateChannel    -> The channel was configured more than once
+pub enum ConfigError {
+    PullUpAndPullDown,
+    NotApplicable(&'static str),
+    MissingSetting(&'static str),
+    NoSuchChannel(u8),
+    DuplicateChannel(u8),
+}
+
+#[allow(unused)]
+#[derive(Debug)]
+/// The errors returned by this crate
+pub enum PlatformError {
+    #[cfg(feature = "modules")]
+    /// Two or more modules were configured on the same slot
+    SlotConflict(ModuleSlot),
+    #[cfg(feature = "modules")]
+    /// A module was configured on a slot that doesn't exist on this controller
+    UnsupportedSlot { slot: ModuleSlot, layout: ModuleLayout },
+    #[cfg(feature = "modules")]
+    /// The module didn't leave its bootloader, `response` is the last answer it gave to the escape message
+    BootloaderEscape { slot: ModuleSlot, response: EscapeBootloaderResponse },
+    #[cfg(feature = "modules")]
+    /// The module fitted in a slot isn't the module configured for it, `found` is None when no running module was found
+    ModuleMismatch { slot: ModuleSlot, expected: u8, found: Option<u8> },
+    #[cfg(feature = "modules")]
+    /// The module runs a firmware version its driver doesn't support
+    UnsupportedFirmware { slot: ModuleSlot, firmware: FirmwareVersion },
+    #[cfg(feature = "experimental")]
+    /// The bootloader of the module kept rejecting a step of flashing, `address` is the start of the block that failed
+    FlashFailed { slot: ModuleSlot, stage: FlashStage, address: u32 },
+    #[cfg(feature = "modules")]
+    /// A message from a module carried a wrong checksum, `expected` is the checksum calculated over the message
+    ChecksumMismatch { expected: u8, actual: u8 },
+    #[cfg(feature = "modules")]
+    /// The function an output channel is configured with can't execute the command, `channel` counts from 0
+    InvalidOutputCommand { channel: usize, command: OutputCommand },
+    #[cfg(feature = "modules")]
+    /// No driver is registered for the module id of the module found in a slot
+    UnknownModule { slot: ModuleSlot, module_id: u8 },
+    #[cfg(feature = "modules")]
+    /// No driver is registered under the name
+    UnknownDriver(String),
+    #[cfg(feature = "modules")]
+    /// A module channel configuration was rejected by its builder
+    InvalidConfig(ConfigError),
+    #[cfg(feature = "adcs")]
+    /// The two points of a calibration had the same raw reading
+    InvalidCalibration,
+    /// The hardware string of the controller matches none of the board definitions
+    UnknownHardware(String),
+    /// The hardware needed for this action is not available on this controller
+    MissingHardware(&'static str),
+    /// The MainBoard was used before it was initialized
+    NotInitialized,
+    /// An error from the underlying device
+    Io(io::Error),
+}
+
+/// Result type of this crate
+pub type Result<T> = core::result::Result<T, PlatformError>;
+
+impl Display for PlatformError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            #[cfg(feature = "modules")]
+            PlatformError::SlotConflict(slot) => write!(f, "{} is occupied by 2 or more modules, check your module initialisation", slot),
+            #[cfg(feature = "modules")]
+            PlatformError::UnsupportedSlot { slot, layout } => write!(f, "{} doesn't exist on a {:?} controller", slot, layout),
+            #[cfg(feature = "modules")]
+            PlatformError::BootloaderEscape { slot, response } => write!(f, "The module in {} is unable to escape the bootloader, last response: bootloader {} firmware {}", slot, response.bootloader, response.firmware),
+            #[cfg(feature = "modules")]
+            PlatformError::ModuleMismatch { slot, expected, found: Some(found) } => write!(f, "{} holds a module with id {}, the configuration expects module id {}", slot, found, expected),
+            #[cfg(feature = "modules")]
+            PlatformError::ModuleMismatch { slot, expected, found: None } => write!(f, "{} holds no running module, the configuration expects module id {}", slot, expected),
+            #[cfg(feature = "modules")]
+            PlatformError::UnsupportedFirmware { slot, firmware } => write!(f, "The module in {} runs firmware {}, which its driver doesn't support", slot, firmware),
+            #[cfg(feature = "experimental")]
+            PlatformError::FlashFailed { slot, stage, address } => write!(f, "Flashing the module in {} failed in the {:?} stage at address {:#010x}", slot, stage, address),
+            #[cfg(feature = "modules")]
+            PlatformError::ChecksumMismatch { expected, actual } => write!(f, "Module message checksum mismatch, expected {} got {}", expected, actual),
+            #[cfg(feature = "modules")]
+            PlatformError::InvalidOutputCommand { channel, command } => write!(f, "Output channel {} can't execute {:?} with the function it is configured with", channel+1, command),
+            #[cfg(feature = "modules")]
+            PlatformError::UnknownModule { slot, module_id } => write!(f, "{} holds a module with id {}, no driver is registered for it", slot, module_id),
+            #[cfg(feature = "modules")]
+            PlatformError::UnknownDriver(name) => write!(f, "No module driver is registered as \"{}\"", name),
+            #[cfg(feature = "modules")]
+            PlatformError::InvalidConfig(err) => write!(f, "Invalid module channel configuration: {}", err),
+            #[cfg(feature = "adcs")]
+            PlatformError::InvalidCalibration => write!(f, "The calibration points need two different raw readings, apply two different reference voltages"),
+            PlatformError::UnknownHardware(hardware) => write!(f, "Unknown controller hardware \"{}\", add a board definition for it", hardware),
+            PlatformError::MissingHardware(hardware) => write!(f, "This controller has no {}", hardware),
+            PlatformError::NotInitialized => write!(f, "The main board is not initialized yet"),
+            PlatformError::Io(err) => write!(f, "{}", err),
+        }
+    }
+}
+
+#[cfg(feature = "modules")]
+impl Display for ConfigError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            ConfigError::PullUpAndPullDown => write!(f, "a pull-up and a pull-down can't be combined"),
+            ConfigError::NotApplicable(setting) => write!(f, "{} doesn't apply to the function of the channel", setting),
+            ConfigError::MissingSetting(setting) => write!(f, "the function of the channel needs {}", setting),
+            ConfigError::NoSuchChannel(channel) => write!(f, "the module has no channel {}", channel),
+            ConfigError::DuplicateChannel(channel) => write!(f, "channel {} is configured more than once", channel),
+        }
+    }
+}
+
+impl std::error::Error for PlatformError {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        match self {
+            PlatformError::Io(err) => Some(err),
+            _ => None,
+        }
+    }
+}
+
+impl From<io::Error> for PlatformError {
+    fn from(err: io::Error) -> Self {
+        PlatformError::Io(err)
+    }
+}
+
+#[cfg(feature = "modules")]
+impl From<ConfigError> for PlatformError {
+    fn from(err: ConfigError) -> Self {
+        PlatformError::InvalidConfig(err)
+    }
+}
+
+impl From<PlatformError> for io::Error {
+    fn from(err: PlatformError) -> Self {
+        match err {
+            PlatformError::Io(err) => err,
+            err => io::Error::other(err),
+        }
+    }
+}
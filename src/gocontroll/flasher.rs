@@ -0,0 +1,411 @@
+use std::{fs,io,path::Path};
+
+use super::module::{ModuleSlot,ModuleTransport,SpidevTransport,ModuleIdentity,BOOTMESSAGELENGTH,BOOTMESSAGELENGTHCHECK};
+use super::mainboard::{MainBoard,PlatformPaths};
+use super::error::{self,PlatformError};
+
+/// Leaves the bootloader and starts the firmware, the same message that escapes the bootloader during initialization
+pub(crate) const RESTARTCOMMAND: u8 = 19;
+
+/// Where the parts of a bootloader message sit, as byte offsets into the message, and how the answer reports success.
+/// Byte 0 of a message always carries the command and the last byte the checksum.
+#[derive(Debug,Copy,Clone,PartialEq,Eq)]
+pub struct BootloaderLayout {
+    /// A second byte carrying the command, None when only byte 0 carries it
+    pub command: Option<usize>,
+    /// The first of the 4 bytes of the address
+    pub address: usize,
+    pub address_big_endian: bool,
+    /// The number of data bytes of a program message, or the number of bytes to read back of a verify message
+    pub length: usize,
+    /// The first data byte of a program message
+    pub data: usize,
+    /// The first byte read back in the answer to a verify message
+    pub read_data: usize,
+    /// The byte of the answer that echoes the command, None when the answer doesn't echo it
+    pub echo: Option<usize>,
+    /// The status byte of the answer
+    pub status: usize,
+    /// The status of a command that succeeded
+    pub status_ok: u8,
+}
+
+impl BootloaderLayout {
+    /// The most data bytes that fit in a program and a verify message, None when a part of the layout lies outside the message
+    pub const fn max_block_size(&self) -> Option<usize> {
+        let end = BOOTMESSAGELENGTH - 1;
+        let command_fits = match self.command { Some(command) => command < end, None => true };
+        let echo_fits = match self.echo { Some(echo) => echo < end, None => true };
+        if !command_fits || !echo_fits || self.address + 4 > end || self.length >= end || self.status >= end || self.data >= end || self.read_data >= end {
+            return None;
+        }
+        let first_data = if self.data > self.read_data { self.data } else { self.read_data };
+        Some(end - first_data)
+    }
+}
+
+/// The commands of the module bootloader used for flashing and the layout of its messages.
+/// The bootloader protocol of the modules is not published, this crate ships neither the commands nor the layout
+/// and the flasher has not been checked against a real module, which is why it needs the "experimental" feature.
+/// Take the protocol from the GOcontroll upload tool, a wrong protocol can leave a module without a working firmware.
+#[derive(Debug,Copy,Clone,PartialEq,Eq)]
+pub struct BootloaderProtocol {
+    /// Erases the application area of the module
+    pub erase: u8,
+    /// Writes up to `block_size` bytes at an address
+    pub program: u8,
+    /// Reads back up to `block_size` bytes from an address
+    pub verify: u8,
+    /// The number of data bytes in a program or verify message, at most [BootloaderLayout::max_block_size]
+    pub block_size: usize,
+    pub layout: BootloaderLayout,
+}
+
+/// The number of times a bootloader message is repeated before flashing stops
+const RETRIES: u8 = 3;
+
+/// A firmware image, the data of all records merged into contiguous segments
+#[derive(Debug,Clone,PartialEq,Eq)]
+pub struct FirmwareImage {
+    segments: Vec<(u32, Vec<u8>)>,
+}
+
+#[allow(unused)]
+#[derive(Debug,Copy,Clone,PartialEq,Eq)]
+/// The steps of flashing a module, in order
+pub enum FlashStage {
+    Erase,
+    Program,
+    Verify,
+    Restart,
+    Done,
+}
+
+/// How far flashing has come, `done` and `total` count bytes for the program and verify stages
+#[derive(Debug,Copy,Clone,PartialEq,Eq)]
+pub struct FlashProgress {
+    pub stage: FlashStage,
+    pub done: usize,
+    pub total: usize,
+}
+
+/// Flashes a firmware image into a module through its bootloader, using the commands of a [BootloaderProtocol].
+/// When flashing fails the flasher keeps its position, calling [ModuleFlasher::flash] again resumes at the block that failed.
+///
+/// # Examples
+///
+/// ```no_run
+/// use gocontroll_platform::gocontroll::{flasher::*,mainboard::MainBoard,module::ModuleSlot};
+/// let mut mainboard = MainBoard::new();
+/// # let layout = BootloaderLayout { command: None, address: 1, address_big_endian: false, length: 5, data: 6, read_data: 6, echo: None, status: 1, status_ok: 0 };
+/// # let protocol = BootloaderProtocol { erase: 0, program: 0, verify: 0, block_size: 32, layout };
+/// // the bootloader commands and message layout of the module, taken from the GOcontroll upload tool
+/// let image = FirmwareImage::load("/usr/module-firmware/20-10-1-5-1-0-2.srec").unwrap();
+/// let mut flasher: ModuleFlasher = ModuleFlasher::open(mainboard.paths(), ModuleSlot::Moduleslot1, &image, protocol).unwrap();
+/// let identity = flasher.flash(&mut mainboard, |progress| println!("{:?} {}/{}", progress.stage, progress.done, progress.total)).unwrap();
+/// println!("{}", identity);
+/// ```
+pub struct ModuleFlasher<T: ModuleTransport = SpidevTransport> {
+    slot: ModuleSlot,
+    transport: T,
+    protocol: BootloaderProtocol,
+    blocks: Vec<(u32, Vec<u8>)>,
+    stage: FlashStage,
+    next_block: usize,
+}
+
+#[allow(unused)]
+impl FirmwareImage {
+    /// Parse an Intel HEX or Motorola S-record image, the format is recognized by the first character of the first record
+    pub fn parse(image: &str) -> error::Result<FirmwareImage> {
+        match image.trim_start().chars().next() {
+            Some(':') => Self::from_intel_hex(image),
+            Some('S') => Self::from_srec(image),
+            _ => Err(invalid_image("not an Intel HEX or S-record image")),
+        }
+    }
+
+    /// Read and parse an image file, see [FirmwareImage::parse]
+    pub fn load<P: AsRef<Path>>(path: P) -> error::Result<FirmwareImage> {
+        Self::parse(&fs::read_to_string(path)?)
+    }
+
+    pub fn from_intel_hex(image: &str) -> error::Result<FirmwareImage> {
+        let mut result = FirmwareImage { segments: Vec::new() };
+        let mut base: u32 = 0;
+        for line in image.lines().map(str::trim).filter(|line| !line.is_empty()) {
+            let record = line.strip_prefix(':').ok_or_else(|| invalid_image("Intel HEX record without ':'"))?;
+            let bytes = decode_hex(record)?;
+            if bytes.len() < 5 || bytes.len() != bytes[0] as usize + 5 {
+                return Err(invalid_image("Intel HEX record with a wrong length"));
+            }
+            if bytes.iter().fold(0u8, |sum, byte| sum.wrapping_add(*byte)) != 0 {
+                return Err(invalid_image("Intel HEX record with a wrong checksum"));
+            }
+            let address = u16::from_be_bytes([bytes[1], bytes[2]]) as u32;
+            let data = &bytes[4..bytes.len()-1];
+            match bytes[3] {
+                0x00 => result.add(base.wrapping_add(address), data),
+                0x01 => return Ok(result),
+                0x02 if data.len() == 2 => base = (u16::from_be_bytes([data[0], data[1]]) as u32) << 4,
+                0x04 if data.len() == 2 => base = (u16::from_be_bytes([data[0], data[1]]) as u32) << 16,
+                0x03 | 0x05 => (),
+                _ => return Err(invalid_image("unsupported Intel HEX record")),
+            }
+        }
+        Err(invalid_image("Intel HEX image without end of file record"))
+    }
+
+    pub fn from_srec(image: &str) -> error::Result<FirmwareImage> {
+        let mut result = FirmwareImage { segments: Vec::new() };
+        for line in image.lines().map(str::trim).filter(|line| !line.is_empty()) {
+            let record = line.strip_prefix('S').ok_or_else(|| invalid_image("S-record without 'S'"))?;
+            let kind = record.chars().next().ok_or_else(|| invalid_image("S-record without type"))?;
+            let bytes = decode_hex(&record[kind.len_utf8()..])?;
+            if bytes.is_empty() || bytes.len() != bytes[0] as usize + 1 {
+                return Err(invalid_image("S-record with a wrong length"));
+            }
+            if bytes.iter().fold(0u8, |sum, byte| sum.wrapping_add(*byte)) != 0xff {
+                return Err(invalid_image("S-record with a wrong checksum"));
+            }
+            let address_length = match kind {
+                '1' => 2,
+                '2' => 3,
+                '3' => 4,
+                '0' | '5' | '6' | '7' | '8' | '9' => continue,
+                _ => return Err(invalid_image("unsupported S-record")),
+            };
+            if bytes.len() < address_length + 2 {
+                return Err(invalid_image("S-record with a wrong length"));
+            }
+            let address = bytes[1..=address_length].iter().fold(0u32, |address, byte| address << 8 | *byte as u32);
+            result.add(address, &bytes[address_length+1..bytes.len()-1]);
+        }
+        Ok(result)
+    }
+
+    /// The contiguous segments of the image as address and data
+    pub fn segments(&self) -> &[(u32, Vec<u8>)] {
+        &self.segments
+    }
+
+    /// The number of data bytes in the image
+    pub fn len(&self) -> usize {
+        self.segments.iter().map(|(_, data)| data.len()).sum()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    fn add(&mut self, address: u32, data: &[u8]) {
+        if data.is_empty() {
+            return;
+        }
+        match self.segments.last_mut() {
+            Some((start, segment)) if *start as usize + segment.len() == address as usize => segment.extend_from_slice(data),
+            _ => self.segments.push((address, data.to_vec())),
+        }
+    }
+}
+
+fn invalid_image(message: &'static str) -> PlatformError {
+    PlatformError::Io(io::Error::new(io::ErrorKind::InvalidData, message))
+}
+
+fn invalid_protocol(message: &'static str) -> PlatformError {
+    PlatformError::Io(io::Error::new(io::ErrorKind::InvalidInput, message))
+}
+
+fn decode_hex(record: &str) -> error::Result<Vec<u8>> {
+    if !record.len().is_multiple_of(2) || !record.is_ascii() {
+        return Err(invalid_image("odd number of hex digits in record"));
+    }
+    (0..record.len()).step_by(2)
+        .map(|index| u8::from_str_radix(&record[index..index+2], 16).map_err(|_| invalid_image("invalid hex digit in record")))
+        .collect()
+}
+
+#[allow(unused)]
+impl<T: ModuleTransport> ModuleFlasher<T> {
+    /// Create a flasher that talks to the module over `transport`, a block size outside 1 to [BootloaderLayout::max_block_size] is clamped.
+    /// Fails when the layout of the protocol doesn't fit in a bootloader message.
+    pub fn new(slot: ModuleSlot, image: &FirmwareImage, mut protocol: BootloaderProtocol, transport: T) -> error::Result<ModuleFlasher<T>> {
+        let max_block_size = protocol.layout.max_block_size()
+            .filter(|max_block_size| *max_block_size > 0)
+            .ok_or_else(|| invalid_protocol("the bootloader layout doesn't fit in a bootloader message"))?;
+        protocol.block_size = protocol.block_size.clamp(1, max_block_size.min(u8::MAX as usize));
+        let block_size = protocol.block_size;
+        let blocks = image.segments.iter()
+            .flat_map(|(address, data)| data.chunks(block_size).enumerate()
+                .map(move |(index, block)| (address + (index*block_size) as u32, block.to_vec())))
+            .collect();
+        Ok(ModuleFlasher { slot, transport, protocol, blocks, stage: FlashStage::Erase, next_block: 0 })
+    }
+
+    /// Create a flasher that talks to the module over the transport of its slot
+    pub fn open(paths: &PlatformPaths, slot: ModuleSlot, image: &FirmwareImage, protocol: BootloaderProtocol) -> error::Result<ModuleFlasher<T>> {
+        Self::new(slot, image, protocol, T::open(paths, slot)?)
+    }
+
+    pub fn stage(&self) -> FlashStage {
+        self.stage
+    }
+
+    pub fn progress(&self) -> FlashProgress {
+        let total = self.blocks.iter().map(|(_, block)| block.len()).sum();
+        let done = match self.stage {
+            FlashStage::Erase => 0,
+            FlashStage::Program | FlashStage::Verify => self.blocks[..self.next_block].iter().map(|(_, block)| block.len()).sum(),
+            FlashStage::Restart | FlashStage::Done => total,
+        };
+        FlashProgress { stage: self.stage, done, total }
+    }
+
+    /// Erase, program and verify the image and restart the module into it, returns the identification of the new firmware.
+    /// The module is reset into its bootloader first, unless an earlier call failed halfway, then flashing resumes where it stopped.
+    pub fn flash(&mut self, mainboard: &mut MainBoard, mut progress: impl FnMut(FlashProgress)) -> error::Result<ModuleIdentity> {
+        if self.stage == FlashStage::Done || (self.stage == FlashStage::Erase && self.next_block == 0) {
+            self.stage = FlashStage::Erase;
+            mainboard.reset_module(self.slot)?;
+        }
+        loop {
+            progress(self.progress());
+            match self.stage {
+                FlashStage::Erase => {
+                    self.command(self.protocol.erase, 0, 0, &[])?;
+                    self.stage = FlashStage::Program;
+                    self.next_block = 0;
+                },
+                FlashStage::Program => {
+                    let Some((address, block)) = self.blocks.get(self.next_block).cloned() else {
+                        self.stage = FlashStage::Verify;
+                        self.next_block = 0;
+                        continue;
+                    };
+                    self.command(self.protocol.program, address, block.len(), &block)?;
+                    self.next_block += 1;
+                },
+                FlashStage::Verify => {
+                    let Some((address, block)) = self.blocks.get(self.next_block).cloned() else {
+                        self.stage = FlashStage::Restart;
+                        continue;
+                    };
+                    let rx = self.command(self.protocol.verify, address, block.len(), &[])?;
+                    let read_data = self.protocol.layout.read_data;
+                    if rx[read_data..read_data+block.len()] != block[..] {
+                        // the block has to be programmed again, which needs a fresh erase
+                        self.stage = FlashStage::Erase;
+                        self.next_block = 0;
+                        return Err(PlatformError::FlashFailed { slot: self.slot, stage: FlashStage::Verify, address });
+                    }
+                    self.next_block += 1;
+                },
+                FlashStage::Restart => {
+                    let rx = self.exchange(RESTARTCOMMAND, 0, 0, &[])?;
+                    let identity = ModuleIdentity::decode(&rx)
+                        .ok_or(PlatformError::FlashFailed { slot: self.slot, stage: FlashStage::Restart, address: 0 })?;
+                    self.stage = FlashStage::Done;
+                    progress(self.progress());
+                    return Ok(identity);
+                },
+                FlashStage::Done => unreachable!("flashing restarts from the erase stage"),
+            }
+        }
+    }
+
+    /// Send a bootloader command and check the status in its answer, repeating it when the answer is corrupted or reports an error
+    fn command(&mut self, command: u8, address: u32, length: usize, data: &[u8]) -> error::Result<[u8;BOOTMESSAGELENGTHCHECK]> {
+        let layout = self.protocol.layout;
+        let mut attempt = 0;
+        loop {
+            attempt += 1;
+            match self.exchange(command, address, length, data) {
+                Ok(rx) if layout.echo.is_none_or(|echo| rx[echo] == command) && rx[layout.status] == layout.status_ok => return Ok(rx),
+                Ok(_) | Err(PlatformError::ChecksumMismatch { .. }) if attempt < RETRIES => (),
+                Ok(_) | Err(PlatformError::ChecksumMismatch { .. }) => {
+                    return Err(PlatformError::FlashFailed { slot: self.slot, stage: self.stage, address });
+                },
+                Err(err) => return Err(err),
+            }
+        }
+    }
+
+    /// Exchange a bootloader message, the restart message is the bootloader escape and carries nothing of the layout
+    fn exchange(&mut self, command: u8, address: u32, length: usize, data: &[u8]) -> error::Result<[u8;BOOTMESSAGELENGTHCHECK]> {
+        let layout = self.protocol.layout;
+        let mut tx: [u8;BOOTMESSAGELENGTHCHECK] = [0;BOOTMESSAGELENGTHCHECK];
+        let mut rx: [u8;BOOTMESSAGELENGTHCHECK] = [0;BOOTMESSAGELENGTHCHECK];
+        tx[0] = command;
+        if command == RESTARTCOMMAND {
+            tx[1] = {BOOTMESSAGELENGTH -1} as u8;
+            tx[2] = command;
+        } else {
+            if let Some(index) = layout.command {
+                tx[index] = command;
+            }
+            let address = if layout.address_big_endian { address.to_be_bytes() } else { address.to_le_bytes() };
+            tx[layout.address..layout.address+4].copy_from_slice(&address);
+            tx[layout.length] = length as u8;
+            tx[layout.data..layout.data+data.len()].copy_from_slice(data);
+        }
+        tx[BOOTMESSAGELENGTH-1] = MainBoard::calculate_checksum(&tx, BOOTMESSAGELENGTH);
+
+        self.transport.transfer(&tx, &mut rx)?;
+        MainBoard::module_checksum(&rx, BOOTMESSAGELENGTH)?;
+        Ok(rx)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parse_images() {
+        let hex = FirmwareImage::parse(":020000040800F2\n:0400000001020304F2\n:020004000506EF\n:00000001FF\n").unwrap();
+        assert_eq!(hex.segments(), &[(0x0800_0000, vec![1,2,3,4,5,6])]);
+        let srec = FirmwareImage::parse("S00600004844521B\nS3090800000001020304E4\nS307080000040506E1\nS70508000000F2\n").unwrap();
+        assert_eq!(srec, hex);
+
+        assert!(FirmwareImage::parse(":0400000001020304F3\n:00000001FF\n").is_err());
+        assert!(FirmwareImage::parse(":0400000001020304F2\n").is_err());
+        assert!(FirmwareImage::parse("S30908000000010203040E\n").is_err());
+    }
+
+    #[derive(Default)]
+    struct RecordingTransport {
+        sent: Vec<Vec<u8>>,
+    }
+
+    impl ModuleTransport for RecordingTransport {
+        fn open(_paths: &PlatformPaths, _slot: ModuleSlot) -> io::Result<Self> {
+            Err(io::ErrorKind::Unsupported.into())
+        }
+
+        fn transfer(&mut self, tx: &[u8], rx: &mut [u8]) -> io::Result<()> {
+            self.sent.push(tx.to_vec());
+            rx[BOOTMESSAGELENGTH-1] = MainBoard::calculate_checksum(rx, BOOTMESSAGELENGTH);
+            Ok(())
+        }
+    }
+
+    #[test]
+    fn messages_follow_the_layout() {
+        let layout = BootloaderLayout { command: None, address: 1, address_big_endian: true, length: 5, data: 6, read_data: 8, echo: None, status: 1, status_ok: 0 };
+        let protocol = BootloaderProtocol { erase: 1, program: 2, verify: 3, block_size: 255, layout };
+        let image = FirmwareImage::parse(":020000040800F2\n:02001000AABB89\n:00000001FF\n").unwrap();
+        let mut flasher = ModuleFlasher::new(ModuleSlot::Moduleslot1, &image, protocol, RecordingTransport::default()).unwrap();
+        assert_eq!(flasher.protocol.block_size, BOOTMESSAGELENGTH - 9);
+
+        flasher.command(protocol.program, 0x0800_0010, 2, &[0xaa, 0xbb]).unwrap();
+        flasher.command(protocol.verify, 0x0800_0010, 2, &[]).unwrap();
+        let sent = &flasher.transport.sent;
+        assert_eq!(sent[0][..9], [2, 0x08, 0x00, 0x00, 0x10, 2, 0xaa, 0xbb, 0]);
+        assert_eq!(sent[1][..7], [3, 0x08, 0x00, 0x00, 0x10, 2, 0]);
+        assert_eq!(sent[0][BOOTMESSAGELENGTH-1], MainBoard::calculate_checksum(&sent[0], BOOTMESSAGELENGTH));
+
+        let outside = BootloaderLayout { address: BOOTMESSAGELENGTH - 4, ..layout };
+        assert!(ModuleFlasher::new(ModuleSlot::Moduleslot1, &image, BootloaderProtocol { layout: outside, ..protocol }, RecordingTransport::default()).is_err());
+    }
+}
@@ -0,0 +1,198 @@
+use std::io;
+
+use super::module::{CommunicationDirection,MessageType,BOOTMESSAGELENGTHCHECK};
+use super::mainboard::MainBoard;
+use super::error::{self,PlatformError};
+
+/// The longest message that can be exchanged with a module
+pub const MAXMESSAGELENGTH: usize = BOOTMESSAGELENGTHCHECK;
+/// The bytes in front of the payload: command, length, direction, module id, message type and message index
+pub const HEADERLENGTH: usize = 6;
+
+/// A message exchanged with a module.
+/// The header is kept in typed fields, the payload is accessed through a cursor with little-endian put and get helpers,
+/// the cursor is relative to the start of the payload so no code has to account for the header.
+///
+/// # Examples
+///
+/// ```
+/// use gocontroll_platform::gocontroll::{frame::ModuleFrame,module::{CommunicationDirection,MessageType}};
+/// let mut frame = ModuleFrame::new(1, CommunicationDirection::ToModule, 11, MessageType::Data, 2, 55);
+/// frame.put_u8(3).put_i32(-1000);
+/// let mut buffer = [0u8;55];
+/// frame.encode(&mut buffer);
+/// let mut decoded = ModuleFrame::decode(&buffer, 55).unwrap();
+/// assert_eq!(decoded.get_u8(), 3);
+/// assert_eq!(decoded.get_i32(), -1000);
+/// ```
+#[derive(Debug,Clone,Copy)]
+pub struct ModuleFrame {
+    pub command: u8,
+    pub direction: CommunicationDirection,
+    pub module_id: u8,
+    pub message_type: MessageType,
+    pub message_index: u8,
+    length: usize,
+    payload: [u8;MAXMESSAGELENGTH-HEADERLENGTH-1],
+    cursor: usize,
+}
+
+#[allow(unused)]
+impl ModuleFrame {
+    /// Create a new frame with an empty payload
+    ///
+    /// # Arguments
+    ///
+    /// * `length` - The length of the complete message, including the header and the checksum
+    pub const fn new(command: u8, direction: CommunicationDirection, module_id: u8, message_type: MessageType, message_index: u8, length: usize) -> ModuleFrame {
+        assert!(length > HEADERLENGTH && length <= MAXMESSAGELENGTH, "module message length out of range");
+        ModuleFrame {
+            command,
+            direction,
+            module_id,
+            message_type,
+            message_index,
+            length,
+            payload: [0;MAXMESSAGELENGTH-HEADERLENGTH-1],
+            cursor: 0,
+        }
+    }
+
+    /// The length of the complete message, including the header and the checksum
+    pub const fn length(&self) -> usize {
+        self.length
+    }
+
+    /// The payload of the message
+    pub fn payload(&self) -> &[u8] {
+        &self.payload[..self.payload_length()]
+    }
+
+    const fn payload_length(&self) -> usize {
+        self.length - HEADERLENGTH - 1
+    }
+
+    /// Move the cursor to `offset` bytes into the payload
+    pub const fn seek(&mut self, offset: usize) -> &mut ModuleFrame {
+        assert!(offset <= self.payload_length(), "seek beyond the end of the module message payload");
+        self.cursor = offset;
+        self
+    }
+
+    /// The current position of the cursor in the payload
+    pub const fn position(&self) -> usize {
+        self.cursor
+    }
+
+    const fn put_bytes<const N: usize>(&mut self, bytes: [u8;N]) -> &mut ModuleFrame {
+        assert!(self.cursor + N <= self.payload_length(), "write beyond the end of the module message payload");
+        let mut index = 0;
+        while index < N {
+            self.payload[self.cursor + index] = bytes[index];
+            index += 1;
+        }
+        self.cursor += N;
+        self
+    }
+
+    const fn get_bytes<const N: usize>(&mut self) -> [u8;N] {
+        assert!(self.cursor + N <= self.payload_length(), "read beyond the end of the module message payload");
+        let mut bytes = [0u8;N];
+        let mut index = 0;
+        while index < N {
+            bytes[index] = self.payload[self.cursor + index];
+            index += 1;
+        }
+        self.cursor += N;
+        bytes
+    }
+
+    pub const fn put_u8(&mut self, value: u8) -> &mut ModuleFrame {
+        self.put_bytes([value])
+    }
+
+    pub const fn put_u16(&mut self, value: u16) -> &mut ModuleFrame {
+        self.put_bytes(value.to_le_bytes())
+    }
+
+    pub const fn put_i16(&mut self, value: i16) -> &mut ModuleFrame {
+        self.put_bytes(value.to_le_bytes())
+    }
+
+    pub const fn put_u32(&mut self, value: u32) -> &mut ModuleFrame {
+        self.put_bytes(value.to_le_bytes())
+    }
+
+    pub const fn put_i32(&mut self, value: i32) -> &mut ModuleFrame {
+        self.put_bytes(value.to_le_bytes())
+    }
+
+    pub const fn get_u8(&mut self) -> u8 {
+        self.get_bytes::<1>()[0]
+    }
+
+    pub const fn get_u16(&mut self) -> u16 {
+        u16::from_le_bytes(self.get_bytes())
+    }
+
+    pub const fn get_i16(&mut self) -> i16 {
+        i16::from_le_bytes(self.get_bytes())
+    }
+
+    pub const fn get_u32(&mut self) -> u32 {
+        u32::from_le_bytes(self.get_bytes())
+    }
+
+    pub const fn get_i32(&mut self) -> i32 {
+        i32::from_le_bytes(self.get_bytes())
+    }
+
+    /// Write the complete message into `buffer`, including the header and the checksum, returns the length of the message
+    pub fn encode(&self, buffer: &mut [u8]) -> usize {
+        let length = self.length;
+        buffer[0] = self.command;
+        buffer[1] = {length-1} as u8;
+        buffer[2] = self.direction as u8;
+        buffer[3] = self.module_id;
+        buffer[4] = self.message_type as u8;
+        buffer[5] = self.message_index;
+        buffer[HEADERLENGTH..length-1].copy_from_slice(self.payload());
+        buffer[length-1] = MainBoard::calculate_checksum(buffer, length);
+        length
+    }
+
+    /// Read a message of `length` bytes from `buffer`, the checksum and the header are verified, the cursor starts at the beginning of the payload
+    pub fn decode(buffer: &[u8], length: usize) -> error::Result<ModuleFrame> {
+        if length <= HEADERLENGTH || length > MAXMESSAGELENGTH || length > buffer.len() {
+            return Err(PlatformError::Io(io::Error::new(io::ErrorKind::InvalidInput, "module message length out of range")));
+        }
+        MainBoard::module_checksum(buffer, length)?;
+        let direction = CommunicationDirection::try_from(buffer[2])?;
+        let message_type = MessageType::try_from(buffer[4])?;
+        let mut frame = ModuleFrame::new(buffer[0], direction, buffer[3], message_type, buffer[5], length);
+        frame.payload[..length-HEADERLENGTH-1].copy_from_slice(&buffer[HEADERLENGTH..length-1]);
+        Ok(frame)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn decode_rejects_corruption() {
+        let mut frame = ModuleFrame::new(1, CommunicationDirection::FromModule, 22, MessageType::Data, 1, 44);
+        frame.seek(33).put_u32(0xdeadbeef);
+        assert_eq!(frame.position(), 37);
+        let mut buffer = [0u8;50];
+        assert_eq!(frame.encode(&mut buffer), 44);
+        assert_eq!(buffer[1], 43);
+        assert_eq!(&buffer[42..44], &[0xde, MainBoard::calculate_checksum(&buffer, 44)]);
+
+        assert_eq!(ModuleFrame::decode(&buffer, 44).unwrap().seek(33).get_u32(), 0xdeadbeef);
+        buffer[10] ^= 1;
+        assert!(matches!(ModuleFrame::decode(&buffer, 44), Err(PlatformError::ChecksumMismatch { .. })));
+        // a slot without a module reads as zeroes, which has a valid checksum but no valid header
+        assert!(ModuleFrame::decode(&[0u8;44], 44).is_err());
+    }
+}
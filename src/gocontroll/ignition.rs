@@ -0,0 +1,378 @@
+use std::ops::ControlFlow;
+use std::sync::{Arc,Mutex,PoisonError,mpsc};
+use std::time::{Duration,Instant};
+#[cfg(feature = "serde")]
+use serde::{Serialize,Deserialize};
+
+use super::{calibration::Millivolts,mainboard::AdcChannel,sampler::{SupplyReadings,SupplySample,Subscribers}};
+
+#[allow(unused)]
+#[derive(Debug,Copy,Clone,PartialEq,Eq)]
+#[cfg_attr(feature = "serde", derive(Serialize,Deserialize))]
+/// The ignition inputs of the controller
+pub enum IgnitionLine {
+    K15A,
+    K15B,
+    K15C,
+}
+
+impl IgnitionLine {
+    pub const ALL: [IgnitionLine;3] = [IgnitionLine::K15A, IgnitionLine::K15B, IgnitionLine::K15C];
+
+    /// The supply measurement of the line
+    pub const fn channel(self) -> AdcChannel {
+        match self {
+            IgnitionLine::K15A => AdcChannel::K15A,
+            IgnitionLine::K15B => AdcChannel::K15B,
+            IgnitionLine::K15C => AdcChannel::K15C,
+        }
+    }
+}
+
+#[allow(unused)]
+#[derive(Debug,Copy,Clone,PartialEq,Eq)]
+#[cfg_attr(feature = "serde", derive(Serialize,Deserialize))]
+pub enum IgnitionState {
+    Off,
+    On,
+}
+
+#[allow(unused)]
+#[derive(Debug,Copy,Clone,PartialEq,Eq,Default)]
+#[cfg_attr(feature = "serde", derive(Serialize,Deserialize))]
+/// How the states of the monitored lines combine into the ignition state:
+/// Any -> The ignition is on when one of the lines is on\
+/// All -> The ignition is on when all lines are on
+pub enum IgnitionCombination {
+    #[default]
+    Any,
+    All,
+}
+
+#[derive(Debug,Copy,Clone,PartialEq,Eq)]
+#[cfg_attr(feature = "serde", derive(Serialize,Deserialize))]
+/// A line turns on at `threshold` and off below `threshold - hysteresis`, a change only counts once it held for `debounce`
+pub struct IgnitionLineConfig {
+    pub threshold: Millivolts,
+    pub hysteresis: Millivolts,
+    pub debounce: Duration,
+}
+
+impl Default for IgnitionLineConfig {
+    /// On at 6 V, off below 5 V, debounced for 50 ms
+    fn default() -> Self {
+        IgnitionLineConfig::new(Millivolts(6000), Millivolts(1000), Duration::from_millis(50))
+    }
+}
+
+impl IgnitionLineConfig {
+    pub const fn new(threshold: Millivolts, hysteresis: Millivolts, debounce: Duration) -> IgnitionLineConfig {
+        IgnitionLineConfig { threshold, hysteresis, debounce }
+    }
+}
+
+#[derive(Debug,Copy,Clone,PartialEq,Eq)]
+#[cfg_attr(feature = "serde", derive(Serialize,Deserialize))]
+/// The monitored lines in the order of [IgnitionLine::ALL], None for a line that isn't monitored
+pub struct IgnitionConfig {
+    pub lines: [Option<IgnitionLineConfig>;3],
+    #[cfg_attr(feature = "serde", serde(default))]
+    pub combination: IgnitionCombination,
+}
+
+impl Default for IgnitionConfig {
+    /// K15A with the default line settings
+    fn default() -> Self {
+        IgnitionConfig::new(IgnitionCombination::Any).with_line(IgnitionLine::K15A, IgnitionLineConfig::default())
+    }
+}
+
+impl IgnitionConfig {
+    /// A configuration without monitored lines, which keeps the ignition off
+    pub const fn new(combination: IgnitionCombination) -> IgnitionConfig {
+        IgnitionConfig { lines: [None;3], combination }
+    }
+
+    pub const fn with_line(mut self, line: IgnitionLine, config: IgnitionLineConfig) -> IgnitionConfig {
+        self.lines[line as usize] = Some(config);
+        self
+    }
+}
+
+#[derive(Debug,Copy,Clone,PartialEq,Eq)]
+/// A change of the ignition state, with the time of the reading that completed the change and how long the previous state held.
+/// `off_for` is None for the first ignition after the detector was created, the ignition may already have been on before that.
+pub enum IgnitionEvent {
+    IgnitionOn { time: Instant, off_for: Option<Duration> },
+    IgnitionOff { time: Instant, on_for: Duration },
+}
+
+impl IgnitionEvent {
+    /// The ignition state after the event
+    pub const fn state(&self) -> IgnitionState {
+        match self {
+            IgnitionEvent::IgnitionOn { .. } => IgnitionState::On,
+            IgnitionEvent::IgnitionOff { .. } => IgnitionState::Off,
+        }
+    }
+
+    pub const fn time(&self) -> Instant {
+        match self {
+            IgnitionEvent::IgnitionOn { time, .. } | IgnitionEvent::IgnitionOff { time, .. } => *time,
+        }
+    }
+}
+
+#[derive(Debug,Copy,Clone)]
+struct LineState {
+    /// The state after the hysteresis, before the debounce
+    level: IgnitionState,
+    level_since: Instant,
+    state: IgnitionState,
+}
+
+#[derive(Debug,Clone)]
+/// The ignition state machine, fed with supply readings by an [IgnitionMonitor] or by hand
+pub struct IgnitionDetector {
+    config: IgnitionConfig,
+    lines: [LineState;3],
+    state: IgnitionState,
+    since: Instant,
+    /// Whether the ignition changed since the detector was created, before that the off state wasn't seen starting
+    changed: bool,
+}
+
+#[allow(unused)]
+impl IgnitionDetector {
+    /// A detector with all lines off since `now`
+    pub fn new(config: IgnitionConfig, now: Instant) -> IgnitionDetector {
+        let line = LineState { level: IgnitionState::Off, level_since: now, state: IgnitionState::Off };
+        IgnitionDetector { config, lines: [line;3], state: IgnitionState::Off, since: now, changed: false }
+    }
+
+    pub fn config(&self) -> &IgnitionConfig {
+        &self.config
+    }
+
+    /// The combined ignition state
+    pub fn state(&self) -> IgnitionState {
+        self.state
+    }
+
+    /// The moment the ignition entered its current state, the creation of the detector until the first change
+    pub fn since(&self) -> Instant {
+        self.since
+    }
+
+    /// The debounced state of `line`, a line that isn't monitored stays off
+    pub fn line_state(&self, line: IgnitionLine) -> IgnitionState {
+        self.lines[line as usize].state
+    }
+
+    /// Process a round of supply readings in the order of [AdcChannel::ALL], a missing reading leaves its line as it was
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use std::time::{Duration,Instant};
+    /// use gocontroll_platform::gocontroll::{calibration::Millivolts,ignition::*,sampler::SupplySample};
+    /// let start = Instant::now();
+    /// let mut detector = IgnitionDetector::new(IgnitionConfig::default(), start);
+    /// let k15a = |ms: u64, mv: u16| [None, Some(SupplySample { time: start + Duration::from_millis(ms), voltage: Millivolts(mv) }), None, None];
+    /// assert_eq!(detector.update(&k15a(10, 12000)), None);
+    /// assert_eq!(detector.update(&k15a(60, 12000)), Some(IgnitionEvent::IgnitionOn { time: start + Duration::from_millis(60), off_for: None }));
+    /// assert_eq!(detector.state(), IgnitionState::On);
+    /// ```
+    pub fn update(&mut self, round: &[Option<SupplySample>;4]) -> Option<IgnitionEvent> {
+        let mut latest = None;
+        for line in IgnitionLine::ALL {
+            let (Some(config), Some(sample)) = (self.config.lines[line as usize], round[line.channel().index()]) else {
+                continue;
+            };
+            latest = latest.max(Some(sample.time));
+            let state = &mut self.lines[line as usize];
+            let level = match state.level {
+                IgnitionState::Off if sample.voltage >= config.threshold => IgnitionState::On,
+                IgnitionState::On if sample.voltage.0 < config.threshold.0.saturating_sub(config.hysteresis.0) => IgnitionState::Off,
+                level => level,
+            };
+            if level != state.level {
+                state.level = level;
+                state.level_since = sample.time;
+            }
+            if state.state != state.level && sample.time.saturating_duration_since(state.level_since) >= config.debounce {
+                state.state = state.level;
+            }
+        }
+
+        let mut monitored = self.config.lines.iter().zip(&self.lines).filter(|(config, _)| config.is_some()).map(|(_, line)| line.state == IgnitionState::On);
+        let on = match self.config.combination {
+            IgnitionCombination::Any => monitored.any(|on| on),
+            IgnitionCombination::All => monitored.next().is_some_and(|first| first && monitored.all(|on| on)),
+        };
+        let state = if on { IgnitionState::On } else { IgnitionState::Off };
+        let time = latest?;
+        if state == self.state {
+            return None;
+        }
+        let held = time.saturating_duration_since(self.since);
+        let observed = std::mem::replace(&mut self.changed, true);
+        self.state = state;
+        self.since = time;
+        Some(match state {
+            IgnitionState::On => IgnitionEvent::IgnitionOn { time, off_for: observed.then_some(held) },
+            IgnitionState::Off => IgnitionEvent::IgnitionOff { time, on_for: held },
+        })
+    }
+}
+
+struct MonitorShared {
+    detector: Mutex<IgnitionDetector>,
+    subscribers: Subscribers<IgnitionEvent>,
+}
+
+#[derive(Clone)]
+/// Watches the K15 lines through the readings of a [SupplySampler](super::sampler::SupplySampler) and publishes [IgnitionEvent]s,
+/// until [IgnitionMonitor::stop] is called or the last clone of the monitor is dropped.
+/// A callback holding a clone of the monitor keeps it watching until it is stopped.
+pub struct IgnitionMonitor {
+    shared: Arc<MonitorShared>,
+}
+
+#[allow(unused)]
+impl IgnitionMonitor {
+    /// Start monitoring the readings of a running sampler, see also [MainBoard::start_ignition_monitor](super::mainboard::MainBoard::start_ignition_monitor)
+    pub fn start(readings: &SupplyReadings, config: IgnitionConfig) -> IgnitionMonitor {
+        let shared = Arc::new(MonitorShared {
+            detector: Mutex::new(IgnitionDetector::new(config, Instant::now())),
+            subscribers: Subscribers::new(),
+        });
+        let weak = Arc::downgrade(&shared);
+        readings.subscribe(move |round| {
+            let Some(shared) = weak.upgrade().filter(|shared| !shared.subscribers.is_closed()) else {
+                return ControlFlow::Break(());
+            };
+            let event = shared.detector.lock().unwrap_or_else(PoisonError::into_inner).update(round);
+            if let Some(event) = event {
+                shared.subscribers.publish(&event);
+            }
+            ControlFlow::Continue(())
+        });
+        IgnitionMonitor { shared }
+    }
+
+    fn read<R>(&self, f: impl FnOnce(&IgnitionDetector) -> R) -> R {
+        f(&self.shared.detector.lock().unwrap_or_else(PoisonError::into_inner))
+    }
+
+    pub fn state(&self) -> IgnitionState {
+        self.read(IgnitionDetector::state)
+    }
+
+    /// The moment the ignition entered its current state
+    pub fn since(&self) -> Instant {
+        self.read(IgnitionDetector::since)
+    }
+
+    /// How long the ignition has held its current state
+    pub fn held_for(&self) -> Duration {
+        self.since().elapsed()
+    }
+
+    pub fn line_state(&self, line: IgnitionLine) -> IgnitionState {
+        self.read(|detector| detector.line_state(line))
+    }
+
+    /// Call `callback` on the sampler thread for every following event
+    pub fn on_event<F>(&self, callback: F)
+    where
+        F: FnMut(&IgnitionEvent) + Send + 'static,
+    {
+        self.shared.subscribers.on_event(callback);
+    }
+
+    /// A channel receiving every following event, dropping the receiver unsubscribes it
+    pub fn subscribe(&self) -> mpsc::Receiver<IgnitionEvent> {
+        self.shared.subscribers.subscribe()
+    }
+
+    /// Stop watching the readings for every clone of the monitor, the callbacks are dropped and the channels disconnected.
+    /// The state stays readable as it was when the monitor stopped.
+    pub fn stop(&self) {
+        self.shared.subscribers.close();
+    }
+}
+
+impl std::fmt::Debug for IgnitionMonitor {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        self.read(|detector| f.debug_struct("IgnitionMonitor").field("detector", detector).finish_non_exhaustive())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::sync::atomic::{AtomicU16,Ordering};
+    use crate::gocontroll::{calibration::{AdcCalibration,SupplyCalibration},sampler::{SamplerConfig,SupplySampler}};
+
+    fn round(start: Instant, ms: u64, k15: [u16;3]) -> [Option<SupplySample>;4] {
+        let sample = |voltage| Some(SupplySample { time: start + Duration::from_millis(ms), voltage: Millivolts(voltage) });
+        [sample(24000), sample(k15[0]), sample(k15[1]), sample(k15[2])]
+    }
+
+    #[test]
+    fn hysteresis_debounce_and_combination() {
+        let start = Instant::now();
+        let line = IgnitionLineConfig::new(Millivolts(6000), Millivolts(1000), Duration::from_millis(20));
+        let mut any = IgnitionDetector::new(IgnitionConfig::new(IgnitionCombination::Any).with_line(IgnitionLine::K15A, line).with_line(IgnitionLine::K15B, line), start);
+        let mut all = IgnitionDetector::new(IgnitionConfig::new(IgnitionCombination::All).with_line(IgnitionLine::K15A, line).with_line(IgnitionLine::K15B, line), start);
+
+        // a bounce shorter than the debounce
+        for (ms, k15a) in [(0, 7000), (10, 0), (20, 7000), (30, 7000)] {
+            assert_eq!(any.update(&round(start, ms, [k15a, 0, 24000])), None);
+        }
+        assert_eq!(any.update(&round(start, 40, [7000, 0, 0])), Some(IgnitionEvent::IgnitionOn { time: start + Duration::from_millis(40), off_for: None }));
+        assert_eq!((any.line_state(IgnitionLine::K15A), any.line_state(IgnitionLine::K15B), any.line_state(IgnitionLine::K15C)),
+            (IgnitionState::On, IgnitionState::Off, IgnitionState::Off));
+        // within the hysteresis the line stays on
+        assert_eq!(any.update(&round(start, 100, [5500, 0, 0])), None);
+        assert_eq!(any.update(&round(start, 200, [5500, 0, 0])), None);
+        assert_eq!(any.update(&round(start, 300, [4900, 0, 0])), None);
+        assert_eq!(any.update(&round(start, 320, [4900, 0, 0])), Some(IgnitionEvent::IgnitionOff { time: start + Duration::from_millis(320), on_for: Duration::from_millis(280) }));
+        assert_eq!(any.since(), start + Duration::from_millis(320));
+        assert_eq!(any.update(&round(start, 400, [7000, 0, 0])), None);
+        assert_eq!(any.update(&round(start, 420, [7000, 0, 0])), Some(IgnitionEvent::IgnitionOn { time: start + Duration::from_millis(420), off_for: Some(Duration::from_millis(100)) }));
+
+        assert_eq!(all.update(&round(start, 0, [7000, 0, 0])), None);
+        assert_eq!(all.update(&round(start, 30, [7000, 6000, 0])), None);
+        assert_eq!(all.line_state(IgnitionLine::K15A), IgnitionState::On);
+        assert_eq!(all.update(&round(start, 50, [7000, 6000, 0])).map(|event| event.state()), Some(IgnitionState::On));
+        assert_eq!(IgnitionDetector::new(IgnitionConfig::new(IgnitionCombination::All), start).update(&round(start, 0, [24000;3])), None);
+    }
+
+    #[test]
+    fn monitor_events() {
+        let k15a = Arc::new(AtomicU16::new(0));
+        let unity = AdcCalibration::new(1.0, 0.0);
+        let read = k15a.clone();
+        let sampler = SupplySampler::start(SamplerConfig::new(Duration::from_millis(1)), SupplyCalibration::new(unity, unity, unity, unity),
+            move |_| Ok(read.load(Ordering::Relaxed))).unwrap();
+        let config = IgnitionConfig::new(IgnitionCombination::Any).with_line(IgnitionLine::K15A, IgnitionLineConfig::new(Millivolts(6000), Millivolts(1000), Duration::from_millis(5)));
+        let monitor = IgnitionMonitor::start(&sampler.readings(), config);
+        let events = monitor.subscribe();
+        let (sender, callbacks) = mpsc::channel();
+        let queried = monitor.clone();
+        monitor.on_event(move |event| sender.send((event.state(), queried.state())).unwrap());
+
+        k15a.store(12000, Ordering::Relaxed);
+        assert_eq!(events.recv_timeout(Duration::from_secs(5)).unwrap().state(), IgnitionState::On);
+        assert_eq!(callbacks.recv_timeout(Duration::from_secs(5)).unwrap(), (IgnitionState::On, IgnitionState::On));
+        assert_eq!(monitor.state(), IgnitionState::On);
+        k15a.store(0, Ordering::Relaxed);
+        let off = events.recv_timeout(Duration::from_secs(5)).unwrap();
+        assert!(matches!(off, IgnitionEvent::IgnitionOff { on_for, .. } if on_for >= Duration::from_millis(5)));
+        assert_eq!(monitor.since(), off.time());
+        // the callback holds a clone of the monitor
+        monitor.stop();
+    }
+}
@@ -0,0 +1,311 @@
+use std::fmt::Debug;
+use std::io;
+use std::marker::PhantomData;
+use std::ops::RangeInclusive;
+use std::sync::Mutex;
+use std::time::Instant;
+
+use super::{module::{GOcontrollModule,ModuleSlot,ModuleTransport,SpidevTransport,ModuleLink,MessageType,CommunicationDirection,FirmwareVersion},
+    frame::ModuleFrame,
+    mainboard::{MainBoard,PlatformPaths},
+    error::{self,ConfigError}};
+
+#[allow(unused)]
+#[derive(Debug,Copy,Clone,PartialEq,Eq)]
+/// The value of an input module channel, typed by the function the channel is configured with:
+/// Unused      -> The channel has no configuration or is configured as None\
+/// Adc         -> The raw ADC value\
+/// Millivolts  -> The input voltage in mV\
+/// Digital     -> The state of the input, true when high\
+/// Frequency   -> The frequency of the incoming signal in Hz\
+/// DutyCycle   -> The low or high period duty cycle in permille\
+/// Rpm         -> The rotational speed in RPM\
+/// PulseCount  -> The number of pulses counted on the input
+pub enum InputModuleValue {
+    Unused,
+    Adc(i32),
+    Millivolts(i32),
+    Digital(bool),
+    Frequency(i32),
+    DutyCycle(i32),
+    Rpm(i32),
+    PulseCount(i32),
+}
+
+#[derive(Debug,Copy,Clone,PartialEq)]
+/// A pulse counter extended to 64 bits
+pub struct PulseCounterReading {
+    /// The pulses counted in total
+    pub total: u64,
+    /// The pulses counted since the previous reading of this counter
+    pub delta: u64,
+    /// The pulses per second since the previous reading of this counter, 0 on the first reading
+    pub rate: f64,
+}
+
+#[derive(Debug,Copy,Clone)]
+/// Follows the 32 bit pulse counter of a channel to extend it to 64 bits, the firmware counter may wrap as long as it is read at least once per 2^31 pulses
+pub(crate) struct PulseCounter {
+    raw: u32,
+    total: u64,
+    last_total: u64,
+    last_read: Option<Instant>,
+}
+
+impl PulseCounter {
+    /// A counter matching a module that just started counting from 0
+    pub(crate) const fn new() -> PulseCounter {
+        PulseCounter { raw: 0, total: 0, last_total: 0, last_read: None }
+    }
+
+    /// Take a value of the firmware counter, a value a little behind the last one comes from an older exchange and is ignored.
+    /// A value closer to 0 than to the last one means the firmware counter restarted, after a brown-out or a watchdog reset, and is counted from 0.
+    /// A restart seen while escaping the bootloader is followed with [PulseCounter::rebase], the drivers do so in [GOcontrollModule::firmware_started]
+    pub(crate) fn update(&mut self, raw: i32) {
+        let raw = raw as u32;
+        let delta = raw.wrapping_sub(self.raw);
+        if delta < 1 << 31 {
+            self.total = self.total.wrapping_add(delta as u64);
+            self.raw = raw;
+        } else if raw < self.raw.wrapping_sub(raw) {
+            self.total = self.total.wrapping_add(raw as u64);
+            self.raw = raw;
+        }
+    }
+
+    /// Set the total after the firmware counter was set to the lower 32 bits of `total`
+    pub(crate) fn preset(&mut self, total: u64) {
+        self.raw = total as u32;
+        self.total = total;
+        self.last_total = total;
+    }
+
+    /// Continue counting from `total` with a firmware counter that started from 0
+    pub(crate) fn restore(&mut self, total: u64) {
+        self.raw = 0;
+        self.total = total;
+        self.last_total = total;
+    }
+
+    /// Follow the firmware counter after it was set to `raw`, without changing the total
+    pub(crate) fn rebase(&mut self, raw: i32) {
+        self.raw = raw as u32;
+    }
+
+    pub(crate) fn read(&mut self) -> PulseCounterReading {
+        let now = Instant::now();
+        let delta = self.total.wrapping_sub(self.last_total);
+        let rate = match self.last_read {
+            Some(last_read) if now > last_read => delta as f64 / (now - last_read).as_secs_f64(),
+            _ => 0.0,
+        };
+        self.last_total = self.total;
+        self.last_read = Some(now);
+        PulseCounterReading { total: self.total, delta, rate }
+    }
+}
+
+/// What sets the input modules apart, the channel functions and the layout of their messages.
+/// Implemented by the layout types of the modules, [InputModule6ChLayout](super::inputmodule6ch::InputModule6ChLayout) and [InputModule10ChLayout](super::inputmodule10ch::InputModule10ChLayout)
+pub trait InputModuleLayout<const N: usize>: Send + Sync + 'static {
+    type Function: Copy + Debug + Send + Sync;
+    type Channel: Copy + Into<usize>;
+
+    /// The module id in the header of the messages of this module
+    const MODULEID: u8;
+    /// The firmware versions using this layout
+    const SUPPORTEDFIRMWARE: RangeInclusive<FirmwareVersion>;
+    const MESSAGELENGTH: usize;
+    /// The distance in bytes between the values of two channels in the data message
+    const VALUESTRIDE: usize;
+
+    /// Interpret a raw value the module reports for a channel with `function`
+    fn value(function: Self::Function, raw: i32) -> InputModuleValue;
+
+    /// A channel with `function` counts pulses
+    fn counts_pulses(function: Self::Function) -> bool;
+}
+
+#[allow(unused)]
+#[derive(Debug)]
+/// An input module with `N` channels, the modules are [InputModule6Ch](super::inputmodule6ch::InputModule6Ch) and [InputModule10Ch](super::inputmodule10ch::InputModule10Ch)
+pub struct InputModule<L: InputModuleLayout<N>, const N: usize, T: ModuleTransport = SpidevTransport> {
+    slot: ModuleSlot,
+    functions: [Option<L::Function>;N],
+    counters: Mutex<[PulseCounter;N]>,
+    configuration: ModuleFrame,
+    link: ModuleLink<T>,
+    layout: PhantomData<L>,
+}
+
+#[allow(unused)]
+impl<L: InputModuleLayout<N>, const N: usize, T: ModuleTransport> InputModule<L,N,T> {
+    /// Create the module from the configuration message its layout built
+    pub(crate) const fn from_configuration(slot: ModuleSlot, configuration: ModuleFrame, functions: [Option<L::Function>;N]) -> InputModule<L,N,T> {
+        InputModule { slot, functions, counters: Mutex::new([PulseCounter::new();N]), configuration, link: ModuleLink::new(), layout: PhantomData }
+    }
+
+    /// Change the configuration of the channel at `index` through `put`, which writes it into a copy of the configuration message.
+    /// The pulse counter of the channel starts over from the value the module reports after the configuration
+    pub(crate) fn reconfigure(&mut self, index: usize, function: Option<L::Function>, put: impl FnOnce(&mut ModuleFrame)) -> error::Result<()> {
+        let mut configuration = self.configuration;
+        put(&mut configuration);
+        let values = self.link.reconfigure(&[configuration], &Self::values_request())?.map(Self::parse_values).transpose()?;
+        self.configuration = configuration;
+        self.functions[index] = function;
+        self.lock_counters()[index] = PulseCounter::new();
+        if let Some(values) = values {
+            self.lock_counters()[index].rebase(values[index]);
+            self.update_counters(values);
+        }
+        Ok(())
+    }
+
+    /// Attach a transport to the module, see [ModuleTransport]
+    pub fn with_transport(mut self, transport: T) -> InputModule<L,N,T> {
+        self.link.attach(transport);
+        self
+    }
+
+    /// Get the values of all channels, typed by the function each channel is configured with
+    pub fn get_values(&self) -> error::Result<[InputModuleValue;N]> {
+        self.get_raw_values().map(|values| self.typed_values(values))
+    }
+
+    #[cfg(feature="async")]
+    /// Get the values of all channels on the blocking thread pool of the tokio runtime
+    pub async fn get_values_async(&self) -> error::Result<[InputModuleValue;N]> {
+        self.get_raw_values_async().await.map(|values| self.typed_values(values))
+    }
+
+    /// Get the values of all channels as the module reports them
+    pub fn get_raw_values(&self) -> error::Result<[i32;N]> {
+        let values = MainBoard::exchange_module_frame(self.link.get()?, &Self::values_request()).and_then(Self::parse_values)?;
+        self.update_counters(values);
+        Ok(values)
+    }
+
+    #[cfg(feature="async")]
+    /// Get the raw values of all channels on the blocking thread pool of the tokio runtime
+    pub async fn get_raw_values_async(&self) -> error::Result<[i32;N]> {
+        let transport = self.link.get()?.clone();
+        let request = Self::values_request();
+        let values = MainBoard::run_blocking(move || MainBoard::exchange_module_frame(&transport, &request).and_then(Self::parse_values)).await?;
+        self.update_counters(values);
+        Ok(values)
+    }
+
+    /// Read the module and get the pulse counter of `channel` extended to 64 bits, every read of the module keeps the counters of all PulseCounter channels up to date
+    pub fn read_pulse_counter(&self, channel: L::Channel) -> error::Result<PulseCounterReading> {
+        self.check_pulse_counter(channel)?;
+        self.get_raw_values()?;
+        Ok(self.lock_counters()[channel.into()].read())
+    }
+
+    #[cfg(feature="async")]
+    /// Read the pulse counter of `channel` on the blocking thread pool of the tokio runtime
+    pub async fn read_pulse_counter_async(&self, channel: L::Channel) -> error::Result<PulseCounterReading> {
+        self.check_pulse_counter(channel)?;
+        self.get_raw_values_async().await?;
+        Ok(self.lock_counters()[channel.into()].read())
+    }
+
+    /// Set the pulse counter of `channel` to `total`, the counter of the module gets the lower 32 bits of it
+    pub fn preset_pulse_counter(&self, channel: L::Channel, total: u64) -> error::Result<()> {
+        self.check_pulse_counter(channel)?;
+        self.reset_pulse_counter(channel, total as u32 as i32)?;
+        self.lock_counters()[channel.into()].preset(total);
+        Ok(())
+    }
+
+    /// Continue the pulse counter of `channel` from a `total` saved before a restart, the pulses the module counted since it was initialized are added to it
+    pub fn restore_pulse_counter(&self, channel: L::Channel, total: u64) -> error::Result<()> {
+        self.check_pulse_counter(channel)?;
+        self.lock_counters()[channel.into()].restore(total);
+        Ok(())
+    }
+
+    /// Set the counter of the module to `value`, the 64 bit pulse counter of the channel keeps its total
+    pub fn reset_pulse_counter(&self, channel: L::Channel, value: i32) -> error::Result<()> {
+        let mut request = ModuleFrame::new(1, CommunicationDirection::ToModule, L::MODULEID, MessageType::Data, 2, L::MESSAGELENGTH);
+        request.put_u8(channel.into() as u8).put_i32(value);
+        MainBoard::send_module_frame(self.link.get()?, &request)?;
+        self.lock_counters()[channel.into()].rebase(value);
+        Ok(())
+    }
+
+    fn check_pulse_counter(&self, channel: L::Channel) -> error::Result<()> {
+        match self.functions[channel.into()] {
+            Some(function) if L::counts_pulses(function) => Ok(()),
+            _ => Err(ConfigError::NotApplicable("pulse counter").into()),
+        }
+    }
+
+    fn update_counters(&self, values: [i32;N]) {
+        let mut counters = self.lock_counters();
+        for (channel, counter) in counters.iter_mut().enumerate() {
+            if self.functions[channel].is_some_and(L::counts_pulses) {
+                counter.update(values[channel]);
+            }
+        }
+    }
+
+    fn lock_counters(&self) -> std::sync::MutexGuard<'_, [PulseCounter;N]> {
+        self.counters.lock().unwrap_or_else(|poisoned| poisoned.into_inner())
+    }
+
+    fn typed_values(&self, values: [i32;N]) -> [InputModuleValue;N] {
+        std::array::from_fn(|channel| match self.functions[channel] {
+            Some(function) => L::value(function, values[channel]),
+            None => InputModuleValue::Unused,
+        })
+    }
+
+    const fn values_request() -> ModuleFrame {
+        ModuleFrame::new(1, CommunicationDirection::FromModule, L::MODULEID, MessageType::Data, 1, L::MESSAGELENGTH)
+    }
+
+    fn parse_values(mut response: ModuleFrame) -> error::Result<[i32;N]> {
+        let mut result = [0;N];
+        for (channel, value) in result.iter_mut().enumerate() {
+            *value = response.seek(channel*L::VALUESTRIDE).get_i32();
+        }
+        Ok(result)
+    }
+}
+
+impl<L: InputModuleLayout<N>, const N: usize, T: ModuleTransport> GOcontrollModule for InputModule<L,N,T> {
+    fn put_configuration(&mut self, mainboard: &mut MainBoard) -> error::Result<()> {
+        mainboard.check_module(self)?;
+
+        self.open_transport(mainboard.paths())?;
+
+        MainBoard::send_module_frame(self.link.get()?, &self.configuration)
+    }
+
+    fn get_slot(&self) -> ModuleSlot {
+        self.slot
+    }
+
+    fn module_id(&self) -> u8 {
+        L::MODULEID
+    }
+
+    fn supported_firmware(&self) -> RangeInclusive<FirmwareVersion> {
+        L::SUPPORTEDFIRMWARE
+    }
+
+    fn open_transport(&mut self, paths: &PlatformPaths) -> io::Result<()> {
+        self.link.open(paths, self.slot)
+    }
+
+    fn transfer(&self, tx: &[u8], rx: &mut [u8]) -> io::Result<()> {
+        self.link.transfer(tx, rx)
+    }
+
+    fn firmware_started(&self) {
+        for counter in self.lock_counters().iter_mut() {
+            counter.rebase(0);
+        }
+    }
+}
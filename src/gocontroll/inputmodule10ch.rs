@@ -1,15 +1,17 @@
-use std::io;
-use std::sync::{Arc,Mutex};
+use std::ops::RangeInclusive;
+#[cfg(feature = "serde")]
+use serde::{Serialize,Deserialize};
 
-use spidev::Spidev;
-
-use super::module::{GOcontrollModule, ModuleSlot, MessageType, CommunicationDirection};
+use super::module::{ModuleSlot, ModuleTransport, SpidevTransport, MessageType, CommunicationDirection, FirmwareVersion};
+use super::frame::ModuleFrame;
+use super::inputmodule::{InputModule,InputModuleLayout,InputModuleValue};
 use super::inputmodule6ch::InputModuleSupply;
-use super::mainboard::MainBoard;
+use super::error::{self,ConfigError};
 
 #[allow(unused)]
 #[repr(u8)]
 #[derive(Debug,Copy, Clone)]
+#[cfg_attr(feature = "serde", derive(Serialize,Deserialize))]
 pub enum InputModule10ChFunction {
     None = 0,
     Adc12Bit = 1,
@@ -22,24 +24,45 @@ pub enum InputModule10ChFunction {
     PulseCounter = 8,
 }
 
+impl InputModule10ChFunction {
+    /// Interpret a raw value the module reports for a channel with this function
+    pub const fn value(self, raw: i32) -> InputModuleValue {
+        match self {
+            InputModule10ChFunction::None => InputModuleValue::Unused,
+            InputModule10ChFunction::Adc12Bit => InputModuleValue::Adc(raw),
+            InputModule10ChFunction::AnalogmV => InputModuleValue::Millivolts(raw),
+            InputModule10ChFunction::DigitalIn => InputModuleValue::Digital(raw != 0),
+            InputModule10ChFunction::FrequencyIn => InputModuleValue::Frequency(raw),
+            InputModule10ChFunction::DutyCycleLow | InputModule10ChFunction::DutyCycleHigh => InputModuleValue::DutyCycle(raw),
+            InputModule10ChFunction::Rpm => InputModuleValue::Rpm(raw),
+            InputModule10ChFunction::PulseCounter => InputModuleValue::PulseCount(raw),
+        }
+    }
+}
+
 #[allow(unused)]
 #[repr(u8)]
-#[derive(Debug,Copy, Clone)]
+#[derive(Debug,Copy, Clone, Default)]
+#[cfg_attr(feature = "serde", derive(Serialize,Deserialize))]
 pub enum InputModule10ChPullDown {
+    #[default]
     PullDownNone = 0,
     PullDown3_3k = 3,
 }
 
 #[allow(unused)]
 #[repr(u8)]
-#[derive(Debug,Copy, Clone)]
+#[derive(Debug,Copy, Clone, Default)]
+#[cfg_attr(feature = "serde", derive(Serialize,Deserialize))]
 pub enum InputModule10ChPullUp {
+    #[default]
     PulUpnNone = 0,
     PullUp3_3k = 3,
 }
 
 #[allow(unused)]
 #[repr(u8)]
+#[derive(Debug,Copy,Clone,PartialEq,Eq)]
 pub enum InputModuleChannel {
     Channel1 = 0u8,
     Channel2 = 1u8,
@@ -53,10 +76,14 @@ pub enum InputModuleChannel {
     Channel10 = 9u8,
 }
 
-const MODULEID:u8 =12;
+/// The module id in the header of the messages of this module
+pub const MODULEID:u8 =12;
+/// The firmware versions using the message layout of this driver
+const SUPPORTEDFIRMWARE: RangeInclusive<FirmwareVersion> = FirmwareVersion::new(0,0,0)..=FirmwareVersion::new(1,255,255);
 const MESSAGELENGTH:usize = 50;
 
 #[derive(Debug,Copy, Clone)]
+#[cfg_attr(feature = "serde", derive(Serialize,Deserialize), serde(try_from = "InputModule10ChConfigBuilder", into = "InputModule10ChConfigBuilder"))]
 pub struct InputModule10ChConfig {
     function: InputModule10ChFunction,
     pull_down: InputModule10ChPullDown,
@@ -65,109 +92,127 @@ pub struct InputModule10ChConfig {
 
 #[allow(unused)]
 impl InputModule10ChConfig {
+    /// Create a channel configuration without any checks, [InputModule10ChConfig::builder] rejects a pull-up combined with a pull-down
     pub const fn new(function: InputModule10ChFunction, pull_down: InputModule10ChPullDown, pull_up: InputModule10ChPullUp) -> InputModule10ChConfig {
         InputModule10ChConfig { function, pull_down, pull_up }
     }
+
+    /// Start a channel configuration with `function` without pulls
+    pub const fn builder(function: InputModule10ChFunction) -> InputModule10ChConfigBuilder {
+        InputModule10ChConfigBuilder { function, pull_down: InputModule10ChPullDown::PullDownNone, pull_up: InputModule10ChPullUp::PulUpnNone }
+    }
 }
 
-#[allow(unused)]
-#[derive(Debug)]
-pub struct InputModule10Ch {
-    slot: ModuleSlot,
-    pulse_counter_reset: [u8; 10],
-    sync_counter: [u32; 6],
-    tx: [u8;56],
-    rx: [u8;56],
-    spidev: Option<Arc<Mutex<Spidev>>>,
+#[derive(Debug,Copy, Clone)]
+#[cfg_attr(feature = "serde", derive(Serialize,Deserialize))]
+/// Builds an [InputModule10ChConfig], checking the combination of settings.
+/// A deserialized [InputModule10ChConfig] goes through the builder, the pulls can be left out.
+pub struct InputModule10ChConfigBuilder {
+    function: InputModule10ChFunction,
+    #[cfg_attr(feature = "serde", serde(default))]
+    pull_down: InputModule10ChPullDown,
+    #[cfg_attr(feature = "serde", serde(default))]
+    pull_up: InputModule10ChPullUp,
 }
 
 #[allow(unused)]
-impl InputModule10Ch {
-    pub const fn new(slot: ModuleSlot, channels: [Option<InputModule10ChConfig>;10],
-    sensor_supply: InputModuleSupply ) -> InputModule10Ch {
-        let mut tx_data = [0u8;56];
-        let mut index: usize = 0;
+impl InputModule10ChConfigBuilder {
+    pub const fn pull_down(mut self, pull_down: InputModule10ChPullDown) -> InputModule10ChConfigBuilder {
+        self.pull_down = pull_down;
+        self
+    }
 
-        while index < 10 {
-            match channels[index] {
-                Some(config) => {
-                    tx_data[index*4] = config.function as u8;
-                    tx_data[index*4 +1] = (config.pull_up as u8) | ((config.pull_down as u8) << 2 );
-                    index +=1;
-                },
-                None => index +=1
-            }
-        }
-        
-        tx_data[46] = sensor_supply as u8;
-        InputModule10Ch { slot, pulse_counter_reset: [0u8;10], sync_counter: [0u32;6], tx: tx_data, rx: [0u8;56], spidev: None }
+    pub const fn pull_up(mut self, pull_up: InputModule10ChPullUp) -> InputModule10ChConfigBuilder {
+        self.pull_up = pull_up;
+        self
     }
 
-    pub fn get_values(&self) -> io::Result<[i32;10]> {
-        let mut result: [i32;10] = [0;10];
-        let mut tx:[u8;56] = [0;56];
-        let mut rx:[u8;56] = [0;56];
-        MainBoard::send_receive_module_spi(
-            self.get_spidev(),
-            1,
-            CommunicationDirection::FromModule,
-            MODULEID,
-            MessageType::Data,
-            1,
-            &mut tx,
-            &mut rx,
-            MESSAGELENGTH
-        )?;
-        for i in 0..9 {
-            result[i] = i32::from_le_bytes(rx[i*4+6..i*4+10].try_into().unwrap());
+    pub const fn build(self) -> Result<InputModule10ChConfig, ConfigError> {
+        if !matches!(self.pull_up, InputModule10ChPullUp::PulUpnNone) && !matches!(self.pull_down, InputModule10ChPullDown::PullDownNone) {
+            return Err(ConfigError::PullUpAndPullDown);
         }
-        Ok(result)
+        Ok(InputModule10ChConfig::new(self.function, self.pull_down, self.pull_up))
+    }
+}
+
+impl TryFrom<InputModule10ChConfigBuilder> for InputModule10ChConfig {
+    type Error = ConfigError;
+
+    fn try_from(builder: InputModule10ChConfigBuilder) -> Result<InputModule10ChConfig, ConfigError> {
+        builder.build()
     }
+}
 
-    pub fn reset_pulse_counter(&self, channel: InputModuleChannel, value: i32) -> io::Result<()> {
-        let mut tx:[u8;56] = [0;56];
-        tx[6] = channel as u8;
-        tx[7] = value as u8;
-        tx[8] = {value >> 8} as u8;
-        tx[9] = {value >> 16} as u8;
-        tx[10] = {value >> 24} as u8;
-        MainBoard::send_module_spi(
-            self.get_spidev(),
-            1,
-            CommunicationDirection::ToModule,
-            MODULEID,
-            MessageType::Data,
-            2,
-            &mut tx,
-            MESSAGELENGTH
-        )
+impl From<InputModule10ChConfig> for InputModule10ChConfigBuilder {
+    fn from(config: InputModule10ChConfig) -> InputModule10ChConfigBuilder {
+        InputModule10ChConfigBuilder { function: config.function, pull_down: config.pull_down, pull_up: config.pull_up }
     }
+}
 
+impl From<InputModuleChannel> for usize {
+    fn from(channel: InputModuleChannel) -> usize {
+        channel as usize
+    }
 }
 
-impl GOcontrollModule for InputModule10Ch {
-    fn put_configuration(&mut self, mainboard: &mut MainBoard) -> io::Result<()>{
-
-        mainboard.check_module(self)?;
-
-        self.spidev = Some(Arc::new(Mutex::new(MainBoard::create_spi(self.slot as usize)?)));
-        
-        MainBoard::send_module_spi(
-            self.get_spidev(),
-            1,
-            CommunicationDirection::ToModule,
-            MODULEID,
-            MessageType::Configuration,
-            1,
-            &mut self.tx,
-            MESSAGELENGTH
-        )
+#[derive(Debug,Copy,Clone)]
+/// The channel functions and message layout of the 10 channel input module
+pub struct InputModule10ChLayout;
+
+impl InputModuleLayout<10> for InputModule10ChLayout {
+    type Function = InputModule10ChFunction;
+    type Channel = InputModuleChannel;
+
+    const MODULEID: u8 = MODULEID;
+    const SUPPORTEDFIRMWARE: RangeInclusive<FirmwareVersion> = SUPPORTEDFIRMWARE;
+    const MESSAGELENGTH: usize = MESSAGELENGTH;
+    const VALUESTRIDE: usize = 4;
+
+    fn value(function: InputModule10ChFunction, raw: i32) -> InputModuleValue {
+        function.value(raw)
     }
-    fn get_slot(&self) -> ModuleSlot {
-        self.slot
+
+    fn counts_pulses(function: InputModule10ChFunction) -> bool {
+        matches!(function, InputModule10ChFunction::PulseCounter)
     }
+}
+
+pub type InputModule10Ch<T = SpidevTransport> = InputModule<InputModule10ChLayout, 10, T>;
 
-    fn get_spidev(&self) -> Arc<Mutex<Spidev>> {
-        self.spidev.as_ref().unwrap().clone()
+#[allow(unused)]
+impl<T: ModuleTransport> InputModule<InputModule10ChLayout, 10, T> {
+    pub const fn new(slot: ModuleSlot, channels: [Option<InputModule10ChConfig>;10],
+    sensor_supply: InputModuleSupply ) -> InputModule10Ch<T> {
+        let mut configuration = ModuleFrame::new(1, CommunicationDirection::ToModule, MODULEID, MessageType::Configuration, 1, MESSAGELENGTH);
+        let mut functions = [None;10];
+        let mut index: usize = 0;
+
+        while index < 10 {
+            if let Some(config) = channels[index] {
+                functions[index] = Some(config.function);
+            }
+            Self::put_channel(&mut configuration, index, channels[index]);
+            index +=1;
+        }
+
+        configuration.seek(40).put_u8(sensor_supply as u8);
+        InputModule::from_configuration(slot, configuration, functions)
     }
-}
\ No newline at end of file
+
+    /// Write the configuration of a channel into the configuration message, the bytes of an unused channel are cleared
+    const fn put_channel(configuration: &mut ModuleFrame, index: usize, channel: Option<InputModule10ChConfig>) {
+        configuration.seek(index*4).put_u16(0);
+        if let Some(config) = channel {
+            configuration.seek(index*4)
+                .put_u8(config.function as u8)
+                .put_u8((config.pull_up as u8) | ((config.pull_down as u8) << 2 ));
+        }
+    }
+
+    /// Change the configuration of a channel at runtime, None makes the channel unused.
+    /// See [GOcontrollModule](super::module::GOcontrollModule) for how the module gets it
+    pub fn reconfigure_channel(&mut self, channel: InputModuleChannel, config: Option<InputModule10ChConfig>) -> error::Result<()> {
+        let index = channel as usize;
+        self.reconfigure(index, config.map(|config| config.function), |configuration| Self::put_channel(configuration, index, config))
+    }
+}
@@ -0,0 +1,285 @@
+use std::io;
+use std::ops::RangeInclusive;
+#[cfg(feature = "serde")]
+use serde::{Serialize,Deserialize};
+
+use super::module::{GOcontrollModule, ModuleSlot, ModuleTransport, SpidevTransport, MessageType, CommunicationDirection, FirmwareVersion, ModuleLink};
+use super::frame::ModuleFrame;
+use super::inputmodule6ch::InputModuleSupply;
+use super::mainboard::{MainBoard,PlatformPaths};
+use super::error;
+
+#[allow(unused)]
+#[repr(u8)]
+#[derive(Debug,Copy, Clone)]
+#[cfg_attr(feature = "serde", derive(Serialize,Deserialize))]
+/// 4-20 mA input module channel functions:
+/// None        -> Channel is unused\
+/// Current     -> Measure the loop current in µA
+pub enum InputModule420mAFunction {
+    None = 0,
+    Current = 1,
+}
+
+/// A loop current below this many µA means the wire to the transmitter is broken
+pub const WIREBREAKCURRENT: i32 = 3600;
+/// A loop current above this many µA is outside the range of the transmitter
+pub const OVERRANGECURRENT: i32 = 21000;
+
+#[allow(unused)]
+#[derive(Debug,Copy,Clone,PartialEq,Eq)]
+/// The state of the loop of a channel:
+/// Unused      -> The channel is not configured to measure the loop current\
+/// Ok          -> The loop current is within the range of the transmitter\
+/// WireBreak   -> The loop current is below [WIREBREAKCURRENT]\
+/// OverRange   -> The loop current is above [OVERRANGECURRENT]
+pub enum InputModule420mAStatus {
+    Unused,
+    Ok,
+    WireBreak,
+    OverRange,
+}
+
+#[derive(Debug,Copy,Clone,PartialEq)]
+#[cfg_attr(feature = "serde", derive(Serialize,Deserialize))]
+/// Linear scaling of the loop current into the unit of the transmitter
+pub struct InputModule420mAScale {
+    at_4ma: f32,
+    at_20ma: f32,
+}
+
+#[allow(unused)]
+impl InputModule420mAScale {
+    /// `at_4ma` and `at_20ma` are the values the transmitter reports with 4 mA and 20 mA, 0.0 and 250.0 for a 0-250 bar pressure transmitter
+    pub const fn new(at_4ma: f32, at_20ma: f32) -> InputModule420mAScale {
+        InputModule420mAScale { at_4ma, at_20ma }
+    }
+
+    /// Convert a loop current in µA into the unit of the transmitter
+    pub fn scale(&self, microamps: i32) -> f32 {
+        self.at_4ma + (microamps - 4000) as f32 * (self.at_20ma - self.at_4ma) / 16000.0
+    }
+}
+
+#[derive(Debug,Copy,Clone,PartialEq)]
+/// A reading of a single channel
+pub struct InputModule420mAReading {
+    /// The loop current in µA
+    pub current: i32,
+    pub status: InputModule420mAStatus,
+    /// The current converted with the scale of the channel, None without a scale, on an unused channel or with a broken wire
+    pub value: Option<f32>,
+}
+
+#[allow(unused)]
+#[repr(u8)]
+#[derive(Debug,Copy,Clone,PartialEq,Eq)]
+pub enum InputModuleChannel {
+    Channel1 = 0u8,
+    Channel2 = 1u8,
+    Channel3 = 2u8,
+    Channel4 = 3u8,
+    Channel5 = 4u8,
+    Channel6 = 5u8,
+}
+
+/// The module id in the header of the messages of this module, unverified like the rest of the layout, see [InputModule420mA]
+pub const MODULEID:u8 =13;
+/// The firmware versions using the message layout of this driver
+const SUPPORTEDFIRMWARE: RangeInclusive<FirmwareVersion> = FirmwareVersion::new(0,0,0)..=FirmwareVersion::new(1,255,255);
+const MESSAGELENGTH:usize = 44;
+
+#[derive(Debug,Copy, Clone)]
+#[cfg_attr(feature = "serde", derive(Serialize,Deserialize))]
+pub struct InputModule420mAConfig {
+    function: InputModule420mAFunction,
+    filter_samples: u16,
+    #[cfg_attr(feature = "serde", serde(default, skip_serializing_if = "Option::is_none"))]
+    scale: Option<InputModule420mAScale>,
+}
+
+#[allow(unused)]
+impl InputModule420mAConfig {
+    /// `filter_samples` is the number of samples the module averages the loop current over
+    pub const fn new(function: InputModule420mAFunction, filter_samples: u16) -> InputModule420mAConfig {
+        InputModule420mAConfig { function, filter_samples, scale: None }
+    }
+
+    /// Scale the readings of the channel into the unit of the transmitter
+    pub const fn with_scale(mut self, scale: InputModule420mAScale) -> InputModule420mAConfig {
+        self.scale = Some(scale);
+        self
+    }
+
+    const fn is_used(&self) -> bool {
+        !matches!(self.function, InputModule420mAFunction::None)
+    }
+}
+
+#[allow(unused)]
+#[derive(Debug)]
+/// The driver of the 4-20 mA input module.
+///
+/// Unverified: the module id, the message length and the positions below follow the 6 channel input module,
+/// they have not been checked against the module firmware or a message recorded from a real module.
+/// The configuration message holds the function of channel n in byte 4n followed by its filter sample count as big endian u16,
+/// the same byte order the 6 channel input module uses for its analog filter, and the 3 sensor supplies in bytes 24 to 26.
+/// The data message holds the loop current of channel n in µA as i32 in bytes 4n.
+pub struct InputModule420mA<T: ModuleTransport = SpidevTransport> {
+    slot: ModuleSlot,
+    used: [bool;6],
+    scales: [Option<InputModule420mAScale>;6],
+    configuration: ModuleFrame,
+    link: ModuleLink<T>,
+}
+
+#[allow(unused)]
+impl<T: ModuleTransport> InputModule420mA<T> {
+    /// Create a new 4-20 mA input module object, every pair of channels shares a sensor supply
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// # use gocontroll_platform::gocontroll::{inputmodule420ma::*,inputmodule6ch::InputModuleSupply,module::ModuleSlot};
+    /// let pressure = InputModule420mAConfig::new(InputModule420mAFunction::Current, 10)
+    ///     .with_scale(InputModule420mAScale::new(0.0, 250.0));
+    /// let input_module: InputModule420mA = InputModule420mA::new(ModuleSlot::Moduleslot1, [Some(pressure), None, None, None, None, None],
+    ///     [InputModuleSupply::On, InputModuleSupply::Off, InputModuleSupply::Off]);
+    /// ```
+    pub const fn new(slot: ModuleSlot, channels: [Option<InputModule420mAConfig>;6], sensor_supplies: [InputModuleSupply;3]) -> InputModule420mA<T> {
+        let mut configuration = ModuleFrame::new(1, CommunicationDirection::ToModule, MODULEID, MessageType::Configuration, 1, MESSAGELENGTH);
+        let mut used = [false;6];
+        let mut scales = [None;6];
+        let mut index: usize = 0;
+
+        while index < 6 {
+            if let Some(config) = channels[index] {
+                used[index] = config.is_used();
+                scales[index] = config.scale;
+            }
+            Self::put_channel(&mut configuration, index, channels[index]);
+            index +=1;
+        }
+
+        configuration.seek(24)
+            .put_u8(sensor_supplies[0] as u8)
+            .put_u8(sensor_supplies[1] as u8)
+            .put_u8(sensor_supplies[2] as u8);
+        InputModule420mA { slot, used, scales, configuration, link: ModuleLink::new() }
+    }
+
+    /// Write the configuration of a channel into the configuration message, the bytes of an unused channel are cleared
+    const fn put_channel(configuration: &mut ModuleFrame, index: usize, channel: Option<InputModule420mAConfig>) {
+        configuration.seek(index*4).put_u8(0).put_u16(0);
+        if let Some(config) = channel {
+            configuration.seek(index*4)
+                .put_u8(config.function as u8)
+                .put_u8((config.filter_samples >> 8) as u8)
+                .put_u8(config.filter_samples as u8);
+        }
+    }
+
+    /// Change the configuration of a channel at runtime, None makes the channel unused, see [GOcontrollModule] for how the module gets it
+    pub fn reconfigure_channel(&mut self, channel: InputModuleChannel, config: Option<InputModule420mAConfig>) -> error::Result<()> {
+        let index = channel as usize;
+        let mut configuration = self.configuration;
+        Self::put_channel(&mut configuration, index, config);
+        self.link.reconfigure(&[configuration], &Self::values_request())?;
+        self.configuration = configuration;
+        self.used[index] = config.is_some_and(|config| config.is_used());
+        self.scales[index] = config.and_then(|config| config.scale);
+        Ok(())
+    }
+
+    /// Attach a transport to the module, see [ModuleTransport]
+    pub fn with_transport(mut self, transport: T) -> InputModule420mA<T> {
+        self.link.attach(transport);
+        self
+    }
+
+    /// Get the loop current of all channels in µA
+    pub fn get_values(&self) -> error::Result<[i32;6]> {
+        MainBoard::exchange_module_frame(self.link.get()?, &Self::values_request()).and_then(Self::parse_values)
+    }
+
+    #[cfg(feature="async")]
+    /// Get the values of all channels on the blocking thread pool of the tokio runtime
+    pub async fn get_values_async(&self) -> error::Result<[i32;6]> {
+        let transport = self.link.get()?.clone();
+        let request = Self::values_request();
+        MainBoard::run_blocking(move || MainBoard::exchange_module_frame(&transport, &request).and_then(Self::parse_values)).await
+    }
+
+    /// Get the loop current of all channels with the state of the loop and the scaled value
+    pub fn get_readings(&self) -> error::Result<[InputModule420mAReading;6]> {
+        self.get_values().map(|values| self.readings(values))
+    }
+
+    #[cfg(feature="async")]
+    /// Get the readings of all channels on the blocking thread pool of the tokio runtime
+    pub async fn get_readings_async(&self) -> error::Result<[InputModule420mAReading;6]> {
+        self.get_values_async().await.map(|values| self.readings(values))
+    }
+
+    fn readings(&self, values: [i32;6]) -> [InputModule420mAReading;6] {
+        std::array::from_fn(|channel| {
+            let current = values[channel];
+            let status = if !self.used[channel] {
+                InputModule420mAStatus::Unused
+            } else if current < WIREBREAKCURRENT {
+                InputModule420mAStatus::WireBreak
+            } else if current > OVERRANGECURRENT {
+                InputModule420mAStatus::OverRange
+            } else {
+                InputModule420mAStatus::Ok
+            };
+            let value = match status {
+                InputModule420mAStatus::Unused | InputModule420mAStatus::WireBreak => None,
+                _ => self.scales[channel].map(|scale| scale.scale(current)),
+            };
+            InputModule420mAReading { current, status, value }
+        })
+    }
+
+    const fn values_request() -> ModuleFrame {
+        ModuleFrame::new(1, CommunicationDirection::FromModule, MODULEID, MessageType::Data, 1, MESSAGELENGTH)
+    }
+
+    fn parse_values(mut response: ModuleFrame) -> error::Result<[i32;6]> {
+        let mut result: [i32;6] = [0;6];
+        for value in result.iter_mut() {
+            *value = response.get_i32();
+        }
+        Ok(result)
+    }
+}
+
+impl<T: ModuleTransport> GOcontrollModule for InputModule420mA<T> {
+    fn put_configuration(&mut self, mainboard: &mut MainBoard) -> error::Result<()>{
+
+        mainboard.check_module(self)?;
+
+        self.open_transport(mainboard.paths())?;
+
+        MainBoard::send_module_frame(self.link.get()?, &self.configuration)
+    }
+    fn get_slot(&self) -> ModuleSlot {
+        self.slot
+    }
+
+    fn module_id(&self) -> u8 {
+        MODULEID
+    }
+
+    fn supported_firmware(&self) -> RangeInclusive<FirmwareVersion> {
+        SUPPORTEDFIRMWARE
+    }
+
+    fn open_transport(&mut self, paths: &PlatformPaths) -> io::Result<()> {
+        self.link.open(paths, self.slot)
+    }
+
+    fn transfer(&self, tx: &[u8], rx: &mut [u8]) -> io::Result<()> {
+        self.link.transfer(tx, rx)
+    }
+}
@@ -1,27 +1,26 @@
-use std::io;
-use std::sync::{Arc,Mutex};
-
-use spidev::Spidev;
+use std::ops::RangeInclusive;
+#[cfg(feature = "serde")]
+use serde::{Serialize,Deserialize};
 
 use super::{
 	module::{
-		GOcontrollModule,
 		ModuleSlot,
+		ModuleTransport,
+		SpidevTransport,
 		CommunicationDirection,
-		MessageType
+		MessageType,
+		FirmwareVersion
 	},
-	mainboard::MainBoard
-};
-
-#[cfg("async")]
-use tokio::{
-	task::spawn_blocking,
-	
+	frame::ModuleFrame,
+	inputmodule::{InputModule,InputModuleLayout},
+	error::{self,ConfigError}
 };
+pub use super::inputmodule::{InputModuleValue,PulseCounterReading};
 
 #[allow(unused)]
 #[repr(u8)]
 #[derive(Debug,Copy, Clone)]
+#[cfg_attr(feature = "serde", derive(Serialize,Deserialize))]
 /// Input module channel functions:
 /// None            -> Channel is unused\
 /// Adc12bit        -> Read the raw ADC value\
@@ -44,10 +43,28 @@ pub enum InputModule6ChFunction {
 	PulseCounter = 8,
 }
 
+impl InputModule6ChFunction {
+	/// Interpret a raw value the module reports for a channel with this function
+	pub const fn value(self, raw: i32) -> InputModuleValue {
+		match self {
+			InputModule6ChFunction::None => InputModuleValue::Unused,
+			InputModule6ChFunction::Adc12Bit => InputModuleValue::Adc(raw),
+			InputModule6ChFunction::AnalogmV => InputModuleValue::Millivolts(raw),
+			InputModule6ChFunction::DigitalIn => InputModuleValue::Digital(raw != 0),
+			InputModule6ChFunction::FrequencyIn => InputModuleValue::Frequency(raw),
+			InputModule6ChFunction::DutyCycleLow | InputModule6ChFunction::DutyCycleHigh => InputModuleValue::DutyCycle(raw),
+			InputModule6ChFunction::Rpm => InputModuleValue::Rpm(raw),
+			InputModule6ChFunction::PulseCounter => InputModuleValue::PulseCount(raw),
+		}
+	}
+}
+
 #[allow(unused)]
 #[repr(u8)]
-#[derive(Debug,Copy, Clone)]
+#[derive(Debug,Copy, Clone, Default)]
+#[cfg_attr(feature = "serde", derive(Serialize,Deserialize))]
 pub enum InputModule6ChPullDown {
+	#[default]
 	PullDownNone = 0,
 	PullDown3_3k = 3,
 	PullDown4_7k = 1,
@@ -56,8 +73,10 @@ pub enum InputModule6ChPullDown {
 
 #[allow(unused)]
 #[repr(u8)]
-#[derive(Debug,Copy, Clone)]
+#[derive(Debug,Copy, Clone, Default)]
+#[cfg_attr(feature = "serde", derive(Serialize,Deserialize))]
 pub enum InputModule6ChPullUp {
+	#[default]
 	PulUpnNone = 0,
 	PullUp3_3k = 3,
 	PullUp4_7k = 1,
@@ -66,8 +85,10 @@ pub enum InputModule6ChPullUp {
 
 #[allow(unused)]
 #[repr(u8)]
-#[derive(Debug,Copy, Clone)]
+#[derive(Debug,Copy, Clone, Default)]
+#[cfg_attr(feature = "serde", derive(Serialize,Deserialize))]
 pub enum InputModule6ChVoltageRange {
+	#[default]
 	Voltage0_5V = 0,
 	Voltage0_12V = 1,
 	Voltage0_24V = 2,
@@ -76,6 +97,7 @@ pub enum InputModule6ChVoltageRange {
 #[allow(unused)]
 #[repr(u8)]
 #[derive(Debug,Copy, Clone)]
+#[cfg_attr(feature = "serde", derive(Serialize,Deserialize))]
 pub enum InputModuleSupply{
 	On = 1,
 	Off = 2,
@@ -83,6 +105,7 @@ pub enum InputModuleSupply{
 
 #[allow(unused)]
 #[repr(u8)]
+#[derive(Debug,Copy,Clone,PartialEq,Eq)]
 pub enum InputModuleChannel {
 	Channel1 = 0u8,
 	Channel2 = 1u8,
@@ -92,22 +115,44 @@ pub enum InputModuleChannel {
 	Channel6 = 5u8,
 }
 
-const MODULEID:u8 =11;
+/// The module id in the header of the messages of this module
+pub const MODULEID:u8 =11;
+/// The firmware versions using the message layout of this driver
+const SUPPORTEDFIRMWARE: RangeInclusive<FirmwareVersion> = FirmwareVersion::new(0,0,0)..=FirmwareVersion::new(1,255,255);
 const MESSAGELENGTH:usize = 55;
 
-#[allow(unused)]
-#[derive(Debug)]
-pub struct InputModule6Ch {
-	slot: ModuleSlot,
-	pulse_counter_reset: [u8; 6],
-	sync_counter: [u32; 6],
-	tx: [u8;56],
-	rx: [u8;56],
-	spidev: Option<Arc<Mutex<Spidev>>>,
+impl From<InputModuleChannel> for usize {
+	fn from(channel: InputModuleChannel) -> usize {
+		channel as usize
+	}
+}
+
+#[derive(Debug,Copy,Clone)]
+/// The channel functions and message layout of the 6 channel input module
+pub struct InputModule6ChLayout;
+
+impl InputModuleLayout<6> for InputModule6ChLayout {
+	type Function = InputModule6ChFunction;
+	type Channel = InputModuleChannel;
+
+	const MODULEID: u8 = MODULEID;
+	const SUPPORTEDFIRMWARE: RangeInclusive<FirmwareVersion> = SUPPORTEDFIRMWARE;
+	const MESSAGELENGTH: usize = MESSAGELENGTH;
+	const VALUESTRIDE: usize = 8;
+
+	fn value(function: InputModule6ChFunction, raw: i32) -> InputModuleValue {
+		function.value(raw)
+	}
+
+	fn counts_pulses(function: InputModule6ChFunction) -> bool {
+		matches!(function, InputModule6ChFunction::PulseCounter)
+	}
 }
 
+pub type InputModule6Ch<T = SpidevTransport> = InputModule<InputModule6ChLayout, 6, T>;
+
 #[allow(unused)]
-impl InputModule6Ch {
+impl<T: ModuleTransport> InputModule<InputModule6ChLayout, 6, T> {
 	/// Create a new 6 channel input module object
 	/// 
 	/// # Arguments
@@ -119,6 +164,7 @@ impl InputModule6Ch {
 	/// # Examples
 	/// 
 	/// ```
+	/// # use gocontroll_platform::gocontroll::{inputmodule6ch::*,module::ModuleSlot};
 	/// let mut input_module: InputModule6Ch = InputModule6Ch::new( ModuleSlot::Moduleslot1,
 	/// [
 	///     Some(InputModule6ChConfig::new(InputModule6ChFunction::AnalogmV, InputModule6ChPullDown::PullDown10k, InputModule6ChPullUp::PulUpnNone, InputModule6ChVoltageRange::Voltage0_5V,0u8,10u16)),
@@ -131,155 +177,58 @@ impl InputModule6Ch {
 	/// Inputmodule6chSupplyConfig::new(InputModuleSupply::On, InputModuleSupply::On, InputModuleSupply::On));
 	/// ```
 	pub const fn new(slot: ModuleSlot, channels: [Option<InputModule6ChConfig>;6],
-	supply: Inputmodule6chSupplyConfig ) -> InputModule6Ch {
-		let mut tx_data = [0u8;56];
+	supply: Inputmodule6chSupplyConfig ) -> InputModule6Ch<T> {
+		let mut configuration = ModuleFrame::new(1, CommunicationDirection::ToModule, MODULEID, MessageType::Configuration, 1, MESSAGELENGTH);
+		let mut functions = [None;6];
 		let mut index = 0;
 		while index < 6 {
-			match channels[index] {
-				Some(config)=> {
-					tx_data[index*6] = config.function as u8;
-					tx_data[index*6+1] = config.pull_up as u8 | (config.pull_down as u8) << 2 | (config.input_voltage as u8) << 6;
-					match config.function {
-						InputModule6ChFunction::None => (),
-						InputModule6ChFunction::Adc12Bit | InputModule6ChFunction::AnalogmV => {
-							tx_data[index*6+2] = (config.analog_filter_samples >> 8) as u8;
-							tx_data[index*6+3] = config.analog_filter_samples as u8;
-						},
-						_ => {
-							tx_data[index*6+2] = config.pulses_per_rotation;
-						}
-					}
-					index +=1;
-				},
-				None => {index+=1;}
+			if let Some(config) = channels[index] {
+				functions[index] = Some(config.function);
 			}
+			Self::put_channel(&mut configuration, index, channels[index]);
+			index += 1;
 		}
 
-		tx_data[42] = supply.sensor_supplies[0] as u8;
-		tx_data[43] = supply.sensor_supplies[1] as u8;
-		tx_data[44] = supply.sensor_supplies[2] as u8;
-		InputModule6Ch {
-			slot,
-			pulse_counter_reset : [0u8; 6],
-			sync_counter : [0u32;6],
-			tx: tx_data,
-			rx: [0u8;56],
-			spidev: None,
-		}
-	}
-
-	#[cfg(feature="async")]
-	pub async fn get_values_async(&self) -> io::Result<[i32;6]> {
-		let mut result: [i32;6] = [0;6];
-		let mut tx:[u8;56] = [0;56];
-		let mut rx:[u8;56] = [0;56];
-		MainBoard::send_receive_module_spi(
-			self.get_spidev(),
-			1,
-			CommunicationDirection::FromModule,
-			MODULEID,
-			MessageType::Data,
-			1,
-			&mut tx,
-			&mut rx,
-			MESSAGELENGTH
-		)?;
-		for i in 0..5 {
-			result[i] = i32::from_le_bytes(rx[i*8+6..i*8+10].try_into().unwrap());
-		}
-		Ok(result)
-	}
-
-	pub fn get_values_sync(&self) -> io::Result<[i32;6]> {
-		let mut result: [i32;6] = [0;6];
-		let mut tx:[u8;56] = [0;56];
-		let mut rx:[u8;56] = [0;56];
-		MainBoard::send_receive_module_spi(
-			self.get_spidev(),
-			1,
-			CommunicationDirection::FromModule,
-			MODULEID,
-			MessageType::Data,
-			1,
-			&mut tx,
-			&mut rx,
-			MESSAGELENGTH
-		)?;
-		for i in 0..5 {
-			result[i] = i32::from_le_bytes(rx[i*8+6..i*8+10].try_into().unwrap());
-		}
-		Ok(result)
+		configuration.seek(36)
+			.put_u8(supply.sensor_supplies[0] as u8)
+			.put_u8(supply.sensor_supplies[1] as u8)
+			.put_u8(supply.sensor_supplies[2] as u8);
+		InputModule::from_configuration(slot, configuration, functions)
 	}
 
-	pub fn get_values(&self) -> io::Result<[i32;6]> {
-		let mut result: [i32;6] = [0;6];
-		let mut tx:[u8;56] = [0;56];
-		let mut rx:[u8;56] = [0;56];
-		MainBoard::send_receive_module_spi(
-			self.get_spidev(),
-			1,
-			CommunicationDirection::FromModule,
-			MODULEID,
-			MessageType::Data,
-			1,
-			&mut tx,
-			&mut rx,
-			MESSAGELENGTH
-		)?;
-		for i in 0..5 {
-			result[i] = i32::from_le_bytes(rx[i*8+6..i*8+10].try_into().unwrap());
+	/// Write the configuration of a channel into the configuration message, the bytes of an unused channel are cleared
+	const fn put_channel(configuration: &mut ModuleFrame, index: usize, channel: Option<InputModule6ChConfig>) {
+		configuration.seek(index*6).put_u32(0).put_u16(0);
+		if let Some(config) = channel {
+			configuration.seek(index*6)
+				.put_u8(config.function as u8)
+				.put_u8(config.pull_up as u8 | (config.pull_down as u8) << 2 | (config.input_voltage as u8) << 6);
+			match config.function {
+				InputModule6ChFunction::None => (),
+				InputModule6ChFunction::Adc12Bit | InputModule6ChFunction::AnalogmV => {
+					configuration.put_u8((config.analog_filter_samples >> 8) as u8).put_u8(config.analog_filter_samples as u8);
+				},
+				_ => {
+					configuration.put_u8(config.pulses_per_rotation);
+				}
+			}
 		}
-		Ok(result)
 	}
 
-	pub fn reset_pulse_counter(&self, channel: InputModuleChannel, value: i32) -> io::Result<()> {
-		let mut tx:[u8;56] = [0;56];
-		tx[6] = channel as u8;
-		tx[7] = value as u8;
-		tx[8] = {value >> 8} as u8;
-		tx[9] = {value >> 16} as u8;
-		tx[10] = {value >> 24} as u8;
-		MainBoard::send_module_spi(
-			self.get_spidev(),
-			1,
-			CommunicationDirection::ToModule,
-			MODULEID,
-			MessageType::Data,
-			2,
-			&mut tx,
-			MESSAGELENGTH
-		)
+	/// Change the configuration of a channel at runtime, None makes the channel unused.
+	/// See [GOcontrollModule](super::module::GOcontrollModule) for how the module gets it
+	pub fn reconfigure_channel(&mut self, channel: InputModuleChannel, config: Option<InputModule6ChConfig>) -> error::Result<()> {
+		let index = channel as usize;
+		self.reconfigure(index, config.map(|config| config.function), |configuration| Self::put_channel(configuration, index, config))
 	}
-}
 
-impl GOcontrollModule for InputModule6Ch {
-	fn put_configuration(&mut self, mainboard: &mut MainBoard) -> io::Result<()>{
-
-		mainboard.check_module(self)?;
-
-		self.spidev = Some(Arc::new(Mutex::new(MainBoard::create_spi(self.slot as usize)?)));
-		
-		MainBoard::send_module_spi(
-			self.get_spidev(),
-			1,
-			CommunicationDirection::ToModule,
-			MODULEID,
-			MessageType::Configuration,
-			1,
-			&mut self.tx,
-			MESSAGELENGTH
-		)
-	}
-	fn get_slot(&self) -> ModuleSlot {
-		self.slot
-	}
-
-	fn get_spidev(&self) -> Arc<Mutex<Spidev>> {
-		self.spidev.as_ref().unwrap().clone()
+	pub fn get_values_sync(&self) -> error::Result<[InputModuleValue;6]> {
+		self.get_values()
 	}
 }
 
 #[derive(Debug,Copy, Clone)]
+#[cfg_attr(feature = "serde", derive(Serialize,Deserialize), serde(try_from = "InputModule6ChConfigBuilder", into = "InputModule6ChConfigBuilder"))]
 pub struct InputModule6ChConfig {
 	function: InputModule6ChFunction,
 	pull_down: InputModule6ChPullDown,
@@ -291,13 +240,134 @@ pub struct InputModule6ChConfig {
 
 #[allow(unused)]
 impl InputModule6ChConfig {
+	/// Create a channel configuration without any checks, `pulses_per_rotation` is only used by the pulse based functions and `analog_filter_samples` by the analog functions.
+	/// [InputModule6ChConfig::builder] rejects settings that don't fit the function.
 	pub const fn new(function: InputModule6ChFunction, pull_down: InputModule6ChPullDown, pull_up: InputModule6ChPullUp,
 	input_voltage: InputModule6ChVoltageRange, pulses_per_rotation: u8, analog_filter_samples: u16) -> InputModule6ChConfig {
 		InputModule6ChConfig {function, pull_down, pull_up, input_voltage, pulses_per_rotation, analog_filter_samples}
 	}
+
+	/// Start a channel configuration with `function`, without pulls on a 0-5V range
+	///
+	/// # Examples
+	///
+	/// ```
+	/// # use gocontroll_platform::gocontroll::inputmodule6ch::*;
+	/// const SPEED: InputModule6ChConfig = match InputModule6ChConfig::builder(InputModule6ChFunction::Rpm)
+	///     .pull_up(InputModule6ChPullUp::PullUp10k)
+	///     .pulses_per_rotation(4)
+	///     .build() {
+	///     Ok(config) => config,
+	///     Err(_) => panic!("invalid speed sensor configuration"),
+	/// };
+	/// assert!(InputModule6ChConfig::builder(InputModule6ChFunction::DigitalIn).analog_filter_samples(10).build().is_err());
+	/// ```
+	pub const fn builder(function: InputModule6ChFunction) -> InputModule6ChConfigBuilder {
+		InputModule6ChConfigBuilder {
+			function,
+			pull_down: InputModule6ChPullDown::PullDownNone,
+			pull_up: InputModule6ChPullUp::PulUpnNone,
+			input_voltage: InputModule6ChVoltageRange::Voltage0_5V,
+			pulses_per_rotation: None,
+			analog_filter_samples: None,
+		}
+	}
+}
+
+#[derive(Debug,Copy, Clone)]
+#[cfg_attr(feature = "serde", derive(Serialize,Deserialize))]
+/// Builds an [InputModule6ChConfig], checking the settings against the function of the channel.
+/// A deserialized [InputModule6ChConfig] goes through the builder, the settings the builder doesn't need can be left out.
+pub struct InputModule6ChConfigBuilder {
+	function: InputModule6ChFunction,
+	#[cfg_attr(feature = "serde", serde(default))]
+	pull_down: InputModule6ChPullDown,
+	#[cfg_attr(feature = "serde", serde(default))]
+	pull_up: InputModule6ChPullUp,
+	#[cfg_attr(feature = "serde", serde(default, rename = "voltage_range"))]
+	input_voltage: InputModule6ChVoltageRange,
+	#[cfg_attr(feature = "serde", serde(default, skip_serializing_if = "Option::is_none"))]
+	pulses_per_rotation: Option<u8>,
+	#[cfg_attr(feature = "serde", serde(default, skip_serializing_if = "Option::is_none"))]
+	analog_filter_samples: Option<u16>,
+}
+
+#[allow(unused)]
+impl InputModule6ChConfigBuilder {
+	pub const fn pull_down(mut self, pull_down: InputModule6ChPullDown) -> InputModule6ChConfigBuilder {
+		self.pull_down = pull_down;
+		self
+	}
+
+	pub const fn pull_up(mut self, pull_up: InputModule6ChPullUp) -> InputModule6ChConfigBuilder {
+		self.pull_up = pull_up;
+		self
+	}
+
+	pub const fn voltage_range(mut self, input_voltage: InputModule6ChVoltageRange) -> InputModule6ChConfigBuilder {
+		self.input_voltage = input_voltage;
+		self
+	}
+
+	/// The number of pulses per rotation, only for Rpm which needs it
+	pub const fn pulses_per_rotation(mut self, pulses_per_rotation: u8) -> InputModule6ChConfigBuilder {
+		self.pulses_per_rotation = Some(pulses_per_rotation);
+		self
+	}
+
+	/// The number of samples averaged, only for Adc12Bit and AnalogmV
+	pub const fn analog_filter_samples(mut self, analog_filter_samples: u16) -> InputModule6ChConfigBuilder {
+		self.analog_filter_samples = Some(analog_filter_samples);
+		self
+	}
+
+	pub const fn build(self) -> Result<InputModule6ChConfig, ConfigError> {
+		if !matches!(self.pull_up, InputModule6ChPullUp::PulUpnNone) && !matches!(self.pull_down, InputModule6ChPullDown::PullDownNone) {
+			return Err(ConfigError::PullUpAndPullDown);
+		}
+		let analog = matches!(self.function, InputModule6ChFunction::Adc12Bit | InputModule6ChFunction::AnalogmV);
+		let rpm = matches!(self.function, InputModule6ChFunction::Rpm);
+		let analog_filter_samples = match self.analog_filter_samples {
+			Some(_) if !analog => return Err(ConfigError::NotApplicable("analog_filter_samples")),
+			Some(samples) => samples,
+			None => 0,
+		};
+		let pulses_per_rotation = match self.pulses_per_rotation {
+			Some(_) if !rpm => return Err(ConfigError::NotApplicable("pulses_per_rotation")),
+			Some(0) | None if rpm => return Err(ConfigError::MissingSetting("pulses_per_rotation")),
+			Some(pulses) => pulses,
+			None => 0,
+		};
+		Ok(InputModule6ChConfig::new(self.function, self.pull_down, self.pull_up, self.input_voltage, pulses_per_rotation, analog_filter_samples))
+	}
+}
+
+impl TryFrom<InputModule6ChConfigBuilder> for InputModule6ChConfig {
+	type Error = ConfigError;
+
+	fn try_from(builder: InputModule6ChConfigBuilder) -> Result<InputModule6ChConfig, ConfigError> {
+		builder.build()
+	}
+}
+
+impl From<InputModule6ChConfig> for InputModule6ChConfigBuilder {
+	/// The builder a configuration is made with, the pulses per rotation and filter samples are only kept for the functions using them
+	fn from(config: InputModule6ChConfig) -> InputModule6ChConfigBuilder {
+		let analog = matches!(config.function, InputModule6ChFunction::Adc12Bit | InputModule6ChFunction::AnalogmV);
+		let rpm = matches!(config.function, InputModule6ChFunction::Rpm);
+		InputModule6ChConfigBuilder {
+			function: config.function,
+			pull_down: config.pull_down,
+			pull_up: config.pull_up,
+			input_voltage: config.input_voltage,
+			pulses_per_rotation: if rpm { Some(config.pulses_per_rotation) } else { None },
+			analog_filter_samples: if analog && config.analog_filter_samples != 0 { Some(config.analog_filter_samples) } else { None },
+		}
+	}
 }
 
 #[derive(Debug,Copy, Clone)]
+#[cfg_attr(feature = "serde", derive(Serialize,Deserialize), serde(transparent))]
 pub struct Inputmodule6chSupplyConfig {
 	sensor_supplies: [InputModuleSupply; 3],
 }
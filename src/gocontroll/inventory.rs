@@ -0,0 +1,55 @@
+use super::module::{ModuleSlot,ModuleIdentity};
+use super::mainboard::ModuleLayout;
+
+/// What was found in a module slot:
+/// Empty       -> Nothing answered the bootloader exchange\
+/// Bootloader  -> A module answered but didn't start its firmware\
+/// Module      -> A module running its firmware
+#[derive(Debug,Copy,Clone,PartialEq,Eq)]
+pub enum SlotContents {
+    Empty,
+    Bootloader,
+    Module(ModuleIdentity),
+}
+
+/// The modules fitted in a controller, as found by [MainBoard::discover_modules](super::mainboard::MainBoard::discover_modules)
+#[derive(Debug,Clone)]
+pub struct ModuleInventory {
+    layout: ModuleLayout,
+    slots: [SlotContents;8],
+}
+
+#[allow(unused)]
+impl ModuleInventory {
+    pub(crate) fn new(layout: ModuleLayout) -> ModuleInventory {
+        ModuleInventory { layout, slots: [SlotContents::Empty;8] }
+    }
+
+    pub(crate) fn set(&mut self, slot: ModuleSlot, contents: SlotContents) {
+        self.slots[slot as usize] = contents;
+    }
+
+    /// The module layout of the controller the inventory was taken of
+    pub fn layout(&self) -> ModuleLayout {
+        self.layout
+    }
+
+    pub fn get(&self, slot: ModuleSlot) -> SlotContents {
+        self.slots[slot as usize]
+    }
+
+    /// All slots of the controller with their contents
+    pub fn slots(&self) -> impl Iterator<Item = (ModuleSlot, SlotContents)> + '_ {
+        (0..=self.layout as usize)
+            .filter_map(ModuleSlot::from_index)
+            .map(|slot| (slot, self.slots[slot as usize]))
+    }
+
+    /// All slots holding a module running its firmware
+    pub fn modules(&self) -> impl Iterator<Item = (ModuleSlot, ModuleIdentity)> + '_ {
+        self.slots().filter_map(|(slot, contents)| match contents {
+            SlotContents::Module(module) => Some((slot, module)),
+            _ => None,
+        })
+    }
+}
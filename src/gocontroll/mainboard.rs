@@ -1,14 +1,46 @@
-use std::{io::{self, prelude::*},fs, path::PathBuf,sync::{Arc,Mutex}};
+use std::{fs,path::{Path,PathBuf},sync::OnceLock};
+#[cfg(feature = "serde")]
+use serde::{Serialize,Deserialize};
+use super::error;
+use super::board::{BoardTable,BoardDefinition};
+#[cfg(feature = "adcs")]
+use super::board::{AdcType,DEFAULT_ADC_CHANNELS};
+#[cfg(feature = "adcs")]
+use super::calibration::{AdcCalibration,CalibrationPoint,Millivolts,SupplyCalibration};
+#[cfg(any(feature = "modules", feature = "adcs", feature = "leds", feature = "async"))]
+use super::error::PlatformError;
+#[cfg(feature = "modules")]
+use std::io::prelude::*;
+#[cfg(any(feature = "modules", feature = "adcs", feature = "async"))]
+use std::io;
+#[cfg(any(feature = "modules", feature = "adcs"))]
+use std::sync::Mutex;
+#[cfg(feature = "adcs")]
+use std::sync::Arc;
+#[cfg(feature = "adcs")]
+use super::sampler::{SamplerConfig,SupplyReadings,SupplySampler};
+#[cfg(feature = "adcs")]
+use super::ignition::{IgnitionConfig,IgnitionMonitor};
+#[cfg(feature = "adcs")]
+use super::battery::{BatteryConfig,BatterySupervisor};
+#[cfg(feature = "adcs")]
+use super::ads1015::{Ads1015,Ads1015Config,Ads1015Input};
+#[cfg(feature = "modules")]
+use super::module::{GOcontrollModule,ModuleTransport,lock_transport,EscapeBootloaderResponse,ModuleIdentity,BOOTMESSAGELENGTH,BOOTMESSAGELENGTHCHECK};
+#[cfg(feature = "modules")]
+use super::frame::{ModuleFrame,MAXMESSAGELENGTH};
 #[cfg(feature = "modules")]
-use super::module::{GOcontrollModule,EscapeBootloaderResponse,BOOTMESSAGELENGTH,BOOTMESSAGELENGTHCHECK,CommunicationDirection,MessageType};
+use super::inventory::{ModuleInventory,SlotContents};
+#[cfg(feature = "modules")]
+use super::module::{ModuleSlot,SpidevTransport};
 #[cfg(feature = "modules")]
 use spidev::{Spidev, SpidevOptions,SpiModeFlags};
-#[cfg(any(feature = "leds", feature = "adcs"))]
+#[cfg(feature = "leds")]
 use i2c_linux::I2c;
 
-#[cfg(feature = "leds")]
 #[allow(unused)]
-#[derive(Debug,Clone)]
+#[derive(Debug,Clone,Copy,PartialEq,Eq)]
+#[cfg_attr(feature = "serde", derive(Serialize,Deserialize))]
 pub enum LedControl {
     None,
     Rukr,
@@ -18,6 +50,7 @@ pub enum LedControl {
 #[cfg(feature = "adcs")]
 #[allow(unused)]
 #[repr(u8)]
+#[derive(Debug,Copy,Clone,PartialEq,Eq)]
 pub enum AdcChannel {
     K30=0xf3,
     K15A=0xc3,
@@ -25,6 +58,22 @@ pub enum AdcChannel {
     K15C=0xe3,
 }
 
+#[cfg(feature = "adcs")]
+impl AdcChannel {
+    /// All supply measurements in the order of [BoardDefinition::adc_channels]
+    pub const ALL: [AdcChannel;4] = [AdcChannel::K30, AdcChannel::K15A, AdcChannel::K15B, AdcChannel::K15C];
+
+    /// The position of the channel in [BoardDefinition::adc_channels]
+    pub(crate) const fn index(self) -> usize {
+        match self {
+            AdcChannel::K30 => 0,
+            AdcChannel::K15A => 1,
+            AdcChannel::K15B => 2,
+            AdcChannel::K15C => 3,
+        }
+    }
+}
+
 #[cfg(feature = "leds")]
 #[allow(unused)]
 #[repr(u8)]
@@ -36,6 +85,24 @@ pub enum EnclosureLed {
     Led4,
 }
 
+#[cfg(feature = "modules")]
+#[allow(unused)]
+#[derive(Debug,Clone,Copy,PartialEq,Eq)]
+/// What happens when a module runs firmware its driver doesn't support:
+/// Refuse  -> Configuring the module fails with [PlatformError::UnsupportedFirmware]\
+/// Warn    -> The error is kept in [MainBoard::warnings] and the module is configured anyway
+pub enum FirmwareCheck {
+    Refuse,
+    Warn,
+}
+
+#[cfg(feature = "modules")]
+const SPIDUMMY: [u8;6] = [1,2,3,4,5,6];
+
+#[cfg(feature = "modules")]
+/// The first byte of the answer to the bootloader escape of a module that is still in its bootloader
+const BOOTLOADERRESPONSE: u8 = 9;
+
 #[cfg(feature = "modules")]
 #[allow(unused)]
 #[repr(u8)]
@@ -46,16 +113,20 @@ enum ModuleResetState {
 
 #[cfg(feature = "adcs")]
 #[allow(unused)]
-#[derive(Debug)]
+#[derive(Debug,Clone)]
+/// The adc of the controller, with the input of every [AdcChannel]:
+/// Mcp3004 -> the sysfs node of every channel\
+/// Ads1015 -> the multiplexer input of every channel and the adc, opened once and shared with the async reads
 pub enum AdcConverter {
     None,
     Mcp3004([Option<PathBuf>;4]),
-    Ads1015,
+    Ads1015([u8;4], Arc<Mutex<Ads1015>>),
 }
 
 #[allow(unused)]
 #[repr(u8)]
-#[derive(Debug,Clone,Copy)]
+#[derive(Debug,Clone,Copy,PartialEq,Eq)]
+#[cfg_attr(feature = "serde", derive(Serialize,Deserialize))]
 pub enum ModuleLayout {
     None=0,
     ModulineIV=7,
@@ -69,15 +140,34 @@ pub struct MainBoard {
     led_control: LedControl,
     #[cfg(feature = "adcs")]
     adc: AdcConverter,
+    #[cfg(feature = "adcs")]
+    adc_calibration: SupplyCalibration,
+    #[cfg(feature = "adcs")]
+    supply_sampler: Option<SupplySampler>,
     pub module_layout: ModuleLayout,
     #[cfg(feature = "modules")]
     pub modules: [Option<usize>;8],
     #[cfg(feature = "modules")]
     resets: [Option<fs::File>;8],
+    #[cfg(feature = "modules")]
+    identities: [Option<ModuleIdentity>;8],
+    #[cfg(feature = "modules")]
+    firmware_check: FirmwareCheck,
+    #[cfg(feature = "modules")]
+    warnings: Vec<PlatformError>,
+    /// None until the paths are changed, so [MainBoard::new] can stay `const`
+    paths: Option<PlatformPaths>,
+    /// None for the builtin board definitions
+    boards: Option<BoardTable>,
+    board: Option<BoardDefinition>,
+}
+
+/// The default paths, shared by all MainBoards that didn't change theirs
+fn default_paths() -> &'static PlatformPaths {
+    static DEFAULT_PATHS: OnceLock<PlatformPaths> = OnceLock::new();
+    DEFAULT_PATHS.get_or_init(PlatformPaths::default)
 }
 
-#[cfg(feature = "modules")]
-#[allow(unused)]
 const SPIDEVS: [&str;8] = [
     "/dev/spidev1.0",
     "/dev/spidev1.1",
@@ -89,8 +179,6 @@ const SPIDEVS: [&str;8] = [
     "/dev/spidev0.1"    
 ];
 
-#[cfg(feature = "modules")]
-#[allow(unused)]
 const RESETS: [&str;8] = [
     "/sys/class/leds/ResetM-1/brightness",
     "/sys/class/leds/ResetM-2/brightness",
@@ -102,8 +190,6 @@ const RESETS: [&str;8] = [
     "/sys/class/leds/ResetM-8/brightness",
 ];
 
-#[cfg(feature = "leds")]
-#[allow(unused)]
 const GPIO_LEDS: [&str;12] = [
     "/sys/class/leds/Status1-r/brightness",
     "/sys/class/leds/Status1-g/brightness",
@@ -119,14 +205,115 @@ const GPIO_LEDS: [&str;12] = [
     "/sys/class/leds/Status4-b/brightness",
 ];
 
-#[cfg(feature = "leds")]
-#[allow(unused)]
 const RUKR_LEDS: &str = "/dev/i2c-2";
 
-#[cfg(feature = "adcs")]
-#[allow(unused)]
 const ADS_ADC: &str = "/dev/i2c-2";
 
+const HARDWARE: &str = "/sys/firmware/devicetree/base/hardware";
+
+const IIO_DEVICES: &str = "/sys/bus/iio/devices/";
+
+const ADC_CALIBRATION: &str = "/etc/gocontroll/adc-calibration";
+
+#[cfg(feature = "adcs")]
+/// The number of raw readings averaged into a [CalibrationPoint]
+const CALIBRATIONSAMPLES: u32 = 16;
+
+/// The locations of all device and sysfs nodes the MainBoard uses.
+/// The defaults match the Moduline controllers, change them to run on a carrier board whose device nodes are numbered differently,
+/// or relocate all of them with [PlatformPaths::with_root] to run against a fake device tree.
+#[derive(Debug,Clone)]
+pub struct PlatformPaths {
+    /// The spidev of every module slot
+    pub spidevs: [PathBuf;8],
+    /// The reset line of every module slot
+    pub resets: [PathBuf;8],
+    /// The red, green and blue brightness of every enclosure led on controllers with gpio leds
+    pub gpio_leds: [PathBuf;12],
+    /// The i2c bus of the Rukr led driver
+    pub rukr_leds: PathBuf,
+    /// The i2c bus of the ADS1015 adc
+    pub ads_adc: PathBuf,
+    /// The devicetree node holding the hardware version of the controller
+    pub hardware: PathBuf,
+    /// The directory holding the IIO devices, in which the MCP3004 adc is searched
+    pub iio_devices: PathBuf,
+    /// The calibration of the supply measurements of this particular controller, see [SupplyCalibration::with_overrides](super::calibration::SupplyCalibration::with_overrides)
+    pub adc_calibration: PathBuf,
+    /// The directory all paths are relocated to, the paths of a board definition are relocated to it too
+    root: PathBuf,
+}
+
+impl Default for PlatformPaths {
+    fn default() -> Self {
+        PlatformPaths {
+            spidevs: SPIDEVS.map(PathBuf::from),
+            resets: RESETS.map(PathBuf::from),
+            gpio_leds: GPIO_LEDS.map(PathBuf::from),
+            rukr_leds: PathBuf::from(RUKR_LEDS),
+            ads_adc: PathBuf::from(ADS_ADC),
+            hardware: PathBuf::from(HARDWARE),
+            iio_devices: PathBuf::from(IIO_DEVICES),
+            adc_calibration: PathBuf::from(ADC_CALIBRATION),
+            root: PathBuf::from("/"),
+        }
+    }
+}
+
+impl PlatformPaths {
+    /// The default paths, relocated to below `root`
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use gocontroll_platform::gocontroll::mainboard::PlatformPaths;
+    /// let paths = PlatformPaths::with_root("/tmp/fake-moduline");
+    /// assert_eq!(paths.hardware.to_str(), Some("/tmp/fake-moduline/sys/firmware/devicetree/base/hardware"));
+    /// ```
+    pub fn with_root<P: AsRef<Path>>(root: P) -> PlatformPaths {
+        PlatformPaths::default().relocate(root)
+    }
+
+    /// Move all paths to below `root`
+    pub fn relocate<P: AsRef<Path>>(self, root: P) -> PlatformPaths {
+        let root = root.as_ref();
+        let relocate = |path: PathBuf| root.join(path.strip_prefix("/").unwrap_or(&path));
+        PlatformPaths {
+            spidevs: self.spidevs.map(relocate),
+            resets: self.resets.map(relocate),
+            gpio_leds: self.gpio_leds.map(relocate),
+            rukr_leds: relocate(self.rukr_leds),
+            ads_adc: relocate(self.ads_adc),
+            hardware: relocate(self.hardware),
+            iio_devices: relocate(self.iio_devices),
+            adc_calibration: relocate(self.adc_calibration),
+            root: relocate(self.root),
+        }
+    }
+
+    /// Use the spidevs and resets of `board`, for the slots it defines them
+    pub(crate) fn apply_board(&mut self, board: &BoardDefinition) {
+        let root = &self.root;
+        let relocate = |path: &PathBuf| root.join(path.strip_prefix("/").unwrap_or(path));
+        if let Some(spidevs) = &board.spidevs {
+            for (spidev, path) in self.spidevs.iter_mut().zip(spidevs) {
+                *spidev = relocate(path);
+            }
+        }
+        if let Some(resets) = &board.resets {
+            for (reset, path) in self.resets.iter_mut().zip(resets) {
+                *reset = relocate(path);
+            }
+        }
+    }
+}
+
+impl Default for MainBoard {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
 #[allow(unused)]
 impl MainBoard {
     /// Create a new MainBoard object
@@ -137,157 +324,129 @@ impl MainBoard {
     /// use gocontroll_platform::gocontroll::mainboard::MainBoard;
     /// let mut mainboard = MainBoard::new();
     /// ```
+    ///
+    /// It can initialize a static
+    ///
+    /// ```
+    /// use std::sync::Mutex;
+    /// use gocontroll_platform::gocontroll::mainboard::MainBoard;
+    /// static MAINBOARD: Mutex<MainBoard> = Mutex::new(MainBoard::new());
+    /// ```
     pub const fn new() -> MainBoard {
         MainBoard {
             #[cfg(feature = "leds")]
             led_control: LedControl::None,
             #[cfg(feature = "adcs")]
             adc: AdcConverter::None,
+            #[cfg(feature = "adcs")]
+            adc_calibration: SupplyCalibration::default_for(AdcType::None),
+            #[cfg(feature = "adcs")]
+            supply_sampler: None,
             module_layout: ModuleLayout::None, 
             #[cfg(feature = "modules")]
             modules: [None,None,None,None,None,None,None,None],
             #[cfg(feature = "modules")]
             resets: [None,None,None,None,None,None,None,None],
+            #[cfg(feature = "modules")]
+            identities: [None;8],
+            #[cfg(feature = "modules")]
+            firmware_check: FirmwareCheck::Refuse,
+            #[cfg(feature = "modules")]
+            warnings: Vec::new(),
+            paths: None,
+            boards: None,
+            board: None,
         }
     }
 
-    pub fn get_hardware_config(&mut self) -> io::Result<()> {
-        let hw = fs::read_to_string("/sys/firmware/devicetree/base/hardware")?;
-        match hw.as_str() {
-            "Moduline IV V3.06" => {
-                self.module_layout = ModuleLayout::ModulineIV;
-                #[cfg(feature = "leds")]{
-                self.led_control = LedControl::Rukr;}
-                #[cfg(feature = "adcs")]{
-                self.adc = AdcConverter::Mcp3004([None,None,None,None]);}
-            },
-            "Moduline Mini V1.11" => {
-                self.module_layout = ModuleLayout::ModulineMini;
-                #[cfg(feature = "leds")]{
-                self.led_control = LedControl::Rukr;}
-                #[cfg(feature = "adcs")]{
-                self.adc = AdcConverter::Mcp3004([None,None,None,None]);}
-            },
-            "Moduline Screen V1.04" => {
-                self.module_layout = ModuleLayout::ModulineDisplay;
-                #[cfg(feature = "leds")]{
-                self.led_control = LedControl::Rukr;}
-                #[cfg(feature = "adcs")]{
-                self.adc = AdcConverter::Mcp3004([None,None,None,None]);}
-            },
-            "Moduline IV V3.00" => {
-                self.module_layout = ModuleLayout::ModulineIV;
-                #[cfg(feature = "leds")]{
-                self.led_control = LedControl::Gpio;}
-                #[cfg(feature = "adcs")]{
-                self.adc = AdcConverter::Ads1015;}
-            },
-            "Moduline IV V3.01" => {
-                self.module_layout = ModuleLayout::ModulineIV;
-                #[cfg(feature = "leds")]{
-                self.led_control = LedControl::Gpio;}
-                #[cfg(feature = "adcs")]{
-                self.adc = AdcConverter::Ads1015;}
-            },
-            "Moduline IV V3.02" => {
-                self.module_layout = ModuleLayout::ModulineIV;
-                #[cfg(feature = "leds")]{
-                self.led_control = LedControl::Rukr;}
-                #[cfg(feature = "adcs")]{
-                self.adc = AdcConverter::Ads1015;}
-            },
-            "Moduline IV V3.03" => {
-                self.module_layout = ModuleLayout::ModulineIV;
-                #[cfg(feature = "leds")]{
-                self.led_control = LedControl::Rukr;}
-                #[cfg(feature = "adcs")]{
-                self.adc = AdcConverter::Ads1015;}
-            },
-            "Moduline IV V3.04" => {
-                self.module_layout = ModuleLayout::ModulineIV;
-                #[cfg(feature = "leds")]{
-                self.led_control = LedControl::Rukr;}
-                #[cfg(feature = "adcs")]{
-                self.adc = AdcConverter::Ads1015;}
-            },
-            "Moduline IV V3.05" => {
-                self.module_layout = ModuleLayout::ModulineIV;
-                #[cfg(feature = "leds")]{
-                self.led_control = LedControl::Rukr;}
-                #[cfg(feature = "adcs")]{
-                self.adc = AdcConverter::Ads1015;}
-            },
-            "Moduline Mini V1.03" => {
-                self.module_layout = ModuleLayout::ModulineMini;
-                #[cfg(feature = "leds")]{
-                self.led_control = LedControl::Rukr;}
-                #[cfg(feature = "adcs")]{
-                self.adc = AdcConverter::Ads1015;}
-            },
-            "Moduline Mini V1.05" => {
-                self.module_layout = ModuleLayout::ModulineMini;
-                #[cfg(feature = "leds")]{
-                self.led_control = LedControl::Rukr;}
-                #[cfg(feature = "adcs")]{
-                self.adc = AdcConverter::Mcp3004([None,None,None,None]);}
-            },
-            "Moduline Mini V1.06" => {
-                self.module_layout = ModuleLayout::ModulineMini;
-                #[cfg(feature = "leds")]{
-                self.led_control = LedControl::Rukr;}
-                #[cfg(feature = "adcs")]{
-                self.adc = AdcConverter::Mcp3004([None,None,None,None]);}
-            },
-            "Moduline Mini V1.07" => {
-                self.module_layout = ModuleLayout::ModulineMini;
-                #[cfg(feature = "leds")]{
-                self.led_control = LedControl::Rukr;}
-                #[cfg(feature = "adcs")]{
-                self.adc = AdcConverter::Mcp3004([None,None,None,None]);}
-            },
-            "Moduline Mini V1.10" => {
-                self.module_layout = ModuleLayout::ModulineMini;
-                #[cfg(feature = "leds")]{
-                self.led_control = LedControl::None;}
-                #[cfg(feature = "adcs")]{
-                self.adc = AdcConverter::Mcp3004([None,None,None,None]);}
-            },
-            "Moduline Screen V1.02" => {
-                self.module_layout = ModuleLayout::ModulineDisplay;
-                #[cfg(feature = "leds")]{
-                self.led_control = LedControl::None;}
-                #[cfg(feature = "adcs")]{
-                self.adc = AdcConverter::Mcp3004([None,None,None,None]);}
-            },
-            "Moduline Screen V1.03" => {
-                self.module_layout = ModuleLayout::ModulineDisplay;
-                #[cfg(feature = "leds")]{
-                self.led_control = LedControl::None;}
-                #[cfg(feature = "adcs")]{
-                self.adc = AdcConverter::Mcp3004([None,None,None,None]);}
-            },
-            "Moduline Screen V1.04" => {
-                self.module_layout = ModuleLayout::ModulineDisplay;
-                #[cfg(feature = "leds")]{
-                self.led_control = LedControl::None;}
-                #[cfg(feature = "adcs")]{
-                self.adc = AdcConverter::Mcp3004([None,None,None,None]);}
-            },
-            "Moduline Screen V1.05" => {
-                self.module_layout = ModuleLayout::ModulineDisplay;
-                #[cfg(feature = "leds")]{
-                self.led_control = LedControl::None;}
-                #[cfg(feature = "adcs")]{
-                self.adc = AdcConverter::Mcp3004([None,None,None,None]);}
-            },
-            _ => {
-                self.module_layout = ModuleLayout::ModulineIV;
-                #[cfg(feature = "leds")]{
-                self.led_control = LedControl::Rukr;}
-                #[cfg(feature = "adcs")]{
-                self.adc = AdcConverter::Mcp3004([None,None,None,None]);}
-            }
+    /// Create a new MainBoard object that uses the device and sysfs nodes in `paths`
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use gocontroll_platform::gocontroll::mainboard::{MainBoard,PlatformPaths};
+    /// let mut paths = PlatformPaths::default();
+    /// paths.spidevs[0] = "/dev/spidev3.0".into();
+    /// let mut mainboard = MainBoard::with_paths(paths);
+    /// ```
+    pub fn with_paths(paths: PlatformPaths) -> MainBoard {
+        let mut mainboard = Self::new();
+        mainboard.paths = Some(paths);
+        mainboard
+    }
+
+    /// Pick the board definition of the controller from `boards` instead of the builtin definitions
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use gocontroll_platform::gocontroll::{board::*,mainboard::*};
+    /// let mut boards = BoardTable::builtin();
+    /// boards.insert(BoardDefinition::new("Moduline IV V4.*", ModuleLayout::ModulineIV, LedControl::Rukr, AdcType::Mcp3004));
+    /// let mut mainboard = MainBoard::new().with_boards(boards);
+    /// ```
+    pub fn with_boards(mut self, boards: BoardTable) -> MainBoard {
+        self.boards = Some(boards);
+        self
+    }
+
+    #[cfg(feature = "modules")]
+    /// Choose what happens when a module runs firmware its driver doesn't support, the default is [FirmwareCheck::Refuse]
+    pub fn with_firmware_check(mut self, firmware_check: FirmwareCheck) -> MainBoard {
+        self.firmware_check = firmware_check;
+        self
+    }
+
+    #[cfg(feature = "modules")]
+    /// The problems that were accepted instead of failing, like a firmware version allowed by [FirmwareCheck::Warn]
+    pub fn warnings(&self) -> &[PlatformError] {
+        &self.warnings
+    }
+
+    #[cfg(feature = "modules")]
+    /// Take the warnings, so the next call of [MainBoard::warnings] only holds the new ones
+    pub fn take_warnings(&mut self) -> Vec<PlatformError> {
+        std::mem::take(&mut self.warnings)
+    }
+
+    #[cfg(feature = "modules")]
+    /// The identification the module in `slot` gave when it was initialized
+    pub fn module_identity(&self, slot: ModuleSlot) -> Option<&ModuleIdentity> {
+        self.identities[slot as usize].as_ref()
+    }
+
+    /// The board definition of this controller, None until the hardware configuration is read
+    pub fn board(&self) -> Option<&BoardDefinition> {
+        self.board.as_ref()
+    }
+
+    /// The device and sysfs nodes used by this MainBoard
+    pub fn paths(&self) -> &PlatformPaths {
+        match &self.paths {
+            Some(paths) => paths,
+            None => default_paths(),
+        }
+    }
+
+    /// Reads the hardware string from the devicetree and applies the matching board definition.
+    /// Returns [PlatformError::UnknownHardware] when no definition matches, add one with [MainBoard::with_boards] to support a new controller.
+    pub fn get_hardware_config(&mut self) -> error::Result<()> {
+        let hw = fs::read_to_string(&self.paths().hardware)?;
+        // devicetree strings are terminated by a NUL
+        let hw = hw.trim_end_matches(['\0', '\n']);
+        let board = match &self.boards {
+            Some(boards) => boards.find(hw)?.clone(),
+            None => BoardTable::builtin().find(hw)?.clone(),
+        };
+        self.module_layout = board.module_layout;
+        #[cfg(feature = "leds")] {
+            self.led_control = board.leds;
+        }
+        #[cfg(feature = "adcs")] {
+            self.adc_calibration = Self::load_adc_calibration(&self.paths().adc_calibration, board.supply_calibration())?;
         }
+        self.paths.get_or_insert_with(PlatformPaths::default).apply_board(&board);
+        self.board = Some(board);
         #[cfg(feature = "adcs")] {
             self.adc = self.get_adcs()?;
         }
@@ -300,6 +459,7 @@ impl MainBoard {
     #[cfg(feature = "modules")]
     /// Initializes the MainBoard object.
     /// Gets the hardware configuration, puts the configuration in the provided modules, initializes the adcs and leds
+    /// Every module is checked against the module id it reports when it leaves its bootloader, a mismatch returns [PlatformError::ModuleMismatch] before any module is configured.
     /// 
     /// # Arguments
     /// 
@@ -322,99 +482,244 @@ impl MainBoard {
     /// Inputmodule6chSupplyConfig::new(InputModuleSupply::On, InputModuleSupply::On, InputModuleSupply::On));
     /// mainboard.init(&mut [&mut input_module]);
     /// ```
-    pub fn init(&mut self, modules: &mut [&mut dyn GOcontrollModule]) -> io::Result<()>{
+    pub fn init(&mut self, modules: &mut [&mut dyn GOcontrollModule]) -> error::Result<()>{
         self.get_hardware_config()?;
 
-        if modules.len() > self.module_layout as usize +1 { panic!("Cannot initialize more than {} modules on this controller", modules.len());}
-        for i in 0..self.module_layout as usize {
-            self.resets[i] = Some(Self::create_reset(i)?);
-        }
+        self.validate_slots(modules)?;
+        self.open_resets()?;
+
+        modules.iter_mut().try_for_each(|module| module.open_transport(self.paths()))?;
 
         self.init_modules(modules)?;
 
-        modules.iter_mut().try_for_each(|module| -> io::Result<()>{
+        modules.iter_mut().try_for_each(|module| {
             module.put_configuration(self)
         })?;
 
         Ok(())
     }
+    #[cfg(all(feature = "modules", feature = "async"))]
+    /// Initializes the MainBoard object like [MainBoard::init], without stalling the other tasks of the tokio runtime.
+    /// The initialization borrows the modules so it can't be moved to the blocking thread pool, instead the worker thread is handed over to the other tasks while it runs.
+    /// This needs a multi-thread runtime, on any other runtime there is no worker to take over and an error is returned without touching the hardware,
+    /// use [MainBoard::init] there.
+    pub async fn init_async(&mut self, modules: &mut [&mut dyn GOcontrollModule]) -> error::Result<()> {
+        match tokio::runtime::Handle::try_current().map(|handle| handle.runtime_flavor()) {
+            Ok(tokio::runtime::RuntimeFlavor::MultiThread) => tokio::task::block_in_place(|| self.init(modules)),
+            _ => Err(io::Error::new(io::ErrorKind::Unsupported, "init_async needs a multi-thread tokio runtime, use init on other runtimes").into()),
+        }
+    }
     #[cfg(feature = "modules")]
-    pub fn check_module(&mut self, module: &dyn GOcontrollModule) -> io::Result<()> {
-        if module.get_slot() as u8 > self.module_layout as u8 {
-            println!("Could not initialize module in {}, it doesn't exist on this controller.", module.get_slot());
-            return Err(io::Error::from(io::ErrorKind::AddrNotAvailable))
+    /// Checks that all modules fit on this controller and that no two of them share a slot, before any of them is touched
+    fn validate_slots(&self, modules: &[&mut dyn GOcontrollModule]) -> error::Result<()> {
+        let mut occupied = [false;8];
+        for module in modules {
+            let slot = module.get_slot();
+            if slot as u8 > self.module_layout as u8 {
+                return Err(PlatformError::UnsupportedSlot { slot, layout: self.module_layout });
+            }
+            if occupied[slot as usize] || self.modules[slot as usize].is_some() {
+                return Err(PlatformError::SlotConflict(slot));
+            }
+            occupied[slot as usize] = true;
+        }
+        Ok(())
+    }
+    #[cfg(feature = "modules")]
+    pub fn check_module(&mut self, module: &dyn GOcontrollModule) -> error::Result<()> {
+        let slot = module.get_slot();
+        if slot as u8 > self.module_layout as u8 {
+            return Err(PlatformError::UnsupportedSlot { slot, layout: self.module_layout });
         }
 
-        if self.modules[module.get_slot() as usize].is_none() {
-            self.modules[module.get_slot() as usize] = Some(module.get_slot() as usize);
-        } else {
-            panic!("{} is trying to be occupied by 2 or more modules, check your module initialisation.", module.get_slot())
+        if self.modules[slot as usize].is_some() {
+            return Err(PlatformError::SlotConflict(slot));
         }
+        if let Some(identity) = self.identities[slot as usize] {
+            if !module.supported_firmware().contains(&identity.firmware) {
+                match self.firmware_check {
+                    FirmwareCheck::Refuse => return Err(PlatformError::UnsupportedFirmware { slot, firmware: identity.firmware }),
+                    FirmwareCheck::Warn => self.warnings.push(PlatformError::UnsupportedFirmware { slot, firmware: identity.firmware }),
+                }
+            }
+        }
+        self.modules[slot as usize] = Some(slot as usize);
         Ok(())
     }
     #[cfg(feature = "modules")]
-    fn init_modules(&mut self, modules: & [&mut dyn GOcontrollModule]) -> io::Result<()> {
-        
-        let mut module_state: [u8;8] = [0;8];
+    fn open_resets(&mut self) -> error::Result<()> {
+        for i in 0..=self.module_layout as usize {
+            if self.resets[i].is_none() {
+                self.resets[i] = Some(self.create_reset(i)?);
+            }
+        }
+        Ok(())
+    }
+    #[cfg(feature = "modules")]
+    /// Resets every module slot of the controller and identifies the module in it through the bootloader exchange.
+    /// Slots without a spidev or without an answering module are reported as empty.
+    /// The hardware configuration is read first if that didn't happen yet.
+    ///
+    /// # Examples
+    ///
+    /// ```no_run
+    /// use gocontroll_platform::gocontroll::mainboard::MainBoard;
+    /// let mut mainboard = MainBoard::new();
+    /// for (slot, contents) in mainboard.discover_modules().unwrap().slots() {
+    ///     println!("{}: {:?}", slot, contents);
+    /// }
+    /// ```
+    pub fn discover_modules(&mut self) -> error::Result<ModuleInventory> {
+        let paths = self.paths().clone();
+        self.discover_modules_with(|slot| SpidevTransport::open(&paths, slot))
+    }
+    #[cfg(feature = "modules")]
+    /// Like [MainBoard::discover_modules], with the transport of every slot opened by `open`
+    pub fn discover_modules_with<T: ModuleTransport>(&mut self, mut open: impl FnMut(ModuleSlot) -> io::Result<T>) -> error::Result<ModuleInventory> {
+        if self.board.is_none() {
+            self.get_hardware_config()?;
+        }
+        self.open_resets()?;
+        let mut inventory = ModuleInventory::new(self.module_layout);
+        let mut transports: Vec<(ModuleSlot, T)> = Vec::new();
+        for slot in (0..=self.module_layout as usize).filter_map(ModuleSlot::from_index) {
+            match open(slot) {
+                Ok(transport) => transports.push((slot, transport)),
+                Err(err) if err.kind() == io::ErrorKind::NotFound => (),
+                Err(err) => return Err(err.into()),
+            }
+        }
+
+        for (slot, _) in &transports {
+            self.reset_module_state(*slot as usize, ModuleResetState::High)?;
+        }
+        for (_, transport) in &mut transports {
+            transport.transfer(&SPIDUMMY, &mut [0u8;6])?;
+        }
+        std::thread::sleep(std::time::Duration::from_millis(5));
+        for (slot, _) in &transports {
+            self.reset_module_state(*slot as usize, ModuleResetState::Low)?;
+        }
+        std::thread::sleep(std::time::Duration::from_millis(10));
+        for (_, transport) in &mut transports {
+            // the first escape starts the firmware, only the answer to the second one identifies the module
+            match Self::escape_bootloader(|tx, rx| transport.transfer(tx, rx)) {
+                Err(PlatformError::ChecksumMismatch { .. }) | Ok(_) => (),
+                Err(err) => return Err(err),
+            }
+        }
+        std::thread::sleep(std::time::Duration::from_millis(5));
+        for (slot, transport) in &mut transports {
+            let contents = match Self::escape_bootloader(|tx, rx| transport.transfer(tx, rx)) {
+                Ok(rx) => match ModuleIdentity::decode(&rx) {
+                    Some(module) => SlotContents::Module(module),
+                    None if rx[0] == BOOTLOADERRESPONSE => SlotContents::Bootloader,
+                    None => SlotContents::Empty,
+                },
+                Err(PlatformError::ChecksumMismatch { .. }) => SlotContents::Empty,
+                Err(err) => return Err(err),
+            };
+            inventory.set(*slot, contents);
+        }
+        Ok(inventory)
+    }
+    #[cfg(feature = "modules")]
+    /// Checks that every module in `modules` is fitted in its slot according to `inventory`
+    pub fn verify_modules(inventory: &ModuleInventory, modules: &[&mut dyn GOcontrollModule]) -> error::Result<()> {
+        for module in modules {
+            let slot = module.get_slot();
+            let found = match inventory.get(slot) {
+                SlotContents::Module(found) => Some(found.module_id()),
+                _ => None,
+            };
+            if found != Some(module.module_id()) {
+                return Err(PlatformError::ModuleMismatch { slot, expected: module.module_id(), found });
+            }
+        }
+        Ok(())
+    }
+    #[cfg(feature = "modules")]
+    fn init_modules(&mut self, modules: & [&mut dyn GOcontrollModule]) -> error::Result<()> {
         for module in modules {
-            self.reset_module_state(&(module.get_slot() as usize), ModuleResetState::High)?;
+            self.reset_module_state(module.get_slot() as usize, ModuleResetState::High)?;
         }
         for module in modules {
             Self::spi_dummy_send(*module)?;
         }
         std::thread::sleep(std::time::Duration::from_millis(5));
         for module in modules {
-            self.reset_module_state(&(module.get_slot() as usize), ModuleResetState::Low)?;
+            self.reset_module_state(module.get_slot() as usize, ModuleResetState::Low)?;
         }
         std::thread::sleep(std::time::Duration::from_millis(10));
         for module in modules{
-        let escape_res = Self::escape_module_bootloader(*module)?;
-            if escape_res.bootloader == 9 {
-                module_state[module.get_slot() as usize] = escape_res.firmware;
-            }
+            Self::escape_module_bootloader(*module)?;
         }
         std::thread::sleep(std::time::Duration::from_millis(5));
-        let mut module: usize = 0;
-        let mut fault_counter: u8 = 0;
-        while module < modules.len() {
-            if fault_counter > 5 {
-                panic!("module in slot {} is unable to escape the bootloader", module + 1);
-            }
-            if Self::escape_module_bootloader(modules[module])?.firmware == 20 {
-                module += 1;
-                fault_counter = 0;
-            } else if module_state[module] == 20 {
-                self.reset_module_state(&module, ModuleResetState::High)?;
+        for module in modules {
+            let slot = module.get_slot();
+            let mut fault_counter: u8 = 0;
+            loop {
+                let response = Self::escape_module_bootloader(*module)?;
+                if let Some(identity) = response.identity {
+                    if identity.module_id() != module.module_id() {
+                        return Err(PlatformError::ModuleMismatch { slot, expected: module.module_id(), found: Some(identity.module_id()) });
+                    }
+                    self.identities[slot as usize] = Some(identity);
+                    break;
+                }
+                if fault_counter >= 5 {
+                    return Err(PlatformError::BootloaderEscape { slot, response });
+                }
+                self.reset_module_state(slot as usize, ModuleResetState::High)?;
                 std::thread::sleep(std::time::Duration::from_millis(5));
-                self.reset_module_state(&module, ModuleResetState::Low)?;
+                self.reset_module_state(slot as usize, ModuleResetState::Low)?;
                 std::thread::sleep(std::time::Duration::from_millis(5));
-                Self::escape_module_bootloader(modules[module])?;
+                Self::escape_module_bootloader(*module)?;
                 fault_counter += 1;
             }
         }
         Ok(())
     }
+    #[cfg(feature = "experimental")]
+    /// Toggles the reset line of a module so it starts in its bootloader, reads the hardware configuration first if that didn't happen yet
+    pub(crate) fn reset_module(&mut self, slot: ModuleSlot) -> error::Result<()> {
+        if self.board.is_none() {
+            self.get_hardware_config()?;
+        }
+        if slot as u8 > self.module_layout as u8 {
+            return Err(PlatformError::UnsupportedSlot { slot, layout: self.module_layout });
+        }
+        self.open_resets()?;
+        self.reset_module_state(slot as usize, ModuleResetState::High)?;
+        std::thread::sleep(std::time::Duration::from_millis(5));
+        self.reset_module_state(slot as usize, ModuleResetState::Low)?;
+        std::thread::sleep(std::time::Duration::from_millis(10));
+        Ok(())
+    }
     #[cfg(feature = "modules")]
-    fn reset_module_state(&mut self, slot: &usize, state: ModuleResetState) -> io::Result<()> {
+    fn reset_module_state(&mut self, slot: usize, state: ModuleResetState) -> error::Result<()> {
         const STATE: [&str;2] = ["0", "1"];
-        self.resets[*slot].as_mut().expect("Incorrectly initialized module reset").write_all(STATE[state as usize].as_bytes())?;
+        self.resets[slot].as_mut().ok_or(PlatformError::NotInitialized)?.write_all(STATE[state as usize].as_bytes())?;
         Ok(())
     }
     #[cfg(feature = "modules")]
-    pub fn module_checksum(data:&[u8], length:usize) -> io::Result<u8> {
-        let mut check_sum:u8 = 0;
-        for item in data.iter().take(length-1) {
-            check_sum = check_sum.wrapping_add(*item);
-        }
+    /// Verifies the checksum in the last byte of a message of `length` bytes
+    pub fn module_checksum(data:&[u8], length:usize) -> error::Result<u8> {
+        let check_sum = Self::calculate_checksum(data, length);
         if check_sum == data[length-1] {
             Ok(check_sum)
         } else {
-            Err(io::Error::from(io::ErrorKind::InvalidData))
+            Err(PlatformError::ChecksumMismatch { expected: check_sum, actual: data[length-1] })
         }
     }
     #[cfg(feature = "modules")]
-    pub fn create_spi(slot: usize) -> io::Result<Spidev> {
-        let mut spi = Spidev::open(SPIDEVS[slot])?;
+    /// Calculates the additive checksum of a message of `length` bytes, which is stored in its last byte
+    pub fn calculate_checksum(data:&[u8], length:usize) -> u8 {
+        data.iter().take(length-1).fold(0u8, |check_sum, item| check_sum.wrapping_add(*item))
+    }
+    #[cfg(feature = "modules")]
+    pub fn create_spi<P: AsRef<Path>>(path: P) -> error::Result<Spidev> {
+        let mut spi = Spidev::open(path)?;
         let options = SpidevOptions::new()
             .bits_per_word(8)
             .max_speed_hz(2_000_000)
@@ -424,43 +729,45 @@ impl MainBoard {
         Ok(spi)
     }
     #[cfg(feature = "modules")]
-    fn create_reset(slot: usize) -> io::Result<fs::File> {
+    fn create_reset(&self, slot: usize) -> io::Result<fs::File> {
         fs::File::options()
             .read(false)
             .write(true)
-            .open(RESETS[slot])
+            .open(&self.paths().resets[slot])
     }
     #[cfg(feature = "adcs")]
-    fn get_adcs(&self) -> io::Result<AdcConverter> {
-        match &self.adc {
-            AdcConverter::Ads1015 => {
-                Ok(AdcConverter::Ads1015)
+    fn get_adcs(&self) -> error::Result<AdcConverter> {
+        let (adc, inputs) = self.board.as_ref().map_or((AdcType::None, DEFAULT_ADC_CHANNELS), |board| (board.adc, board.adc_channels));
+        match adc {
+            AdcType::Ads1015 => {
+                let ads = Ads1015::open(&self.paths().ads_adc, Ads1015Config::default())?;
+                Ok(AdcConverter::Ads1015(inputs, Arc::new(Mutex::new(ads))))
             },
-            AdcConverter::Mcp3004(adcs) => {
-                for device in fs::read_dir("/sys/bus/iio/devices/")? {  
+            AdcType::Mcp3004 => {
+                for device in fs::read_dir(&self.paths().iio_devices)? {  
                     let mut dev = device?;   
                     let mut dev_path = dev.path();
-                    dev_path.push(&dev.file_name());
+                    dev_path.push(dev.file_name());
                     let mut adcs_temp: [Option<PathBuf>;4] = [None,None,None,None];
                     dev_path.set_file_name("name");
-                    if fs::read_to_string(&dev_path).unwrap().contains("mcp3004") {
-                        for (index, adc) in adcs_temp.iter_mut().enumerate() {
-                            dev_path.set_file_name(format!("in_voltage{}_raw",index));
+                    if fs::read_to_string(&dev_path)?.contains("mcp3004") {
+                        for (adc, input) in adcs_temp.iter_mut().zip(inputs) {
+                            dev_path.set_file_name(format!("in_voltage{}_raw",input));
                             *adc = Some(dev_path.clone());
                         }
                         return Ok(AdcConverter::Mcp3004(adcs_temp));
                     }
                 }
-                Err(io::Error::from(io::ErrorKind::NotFound))
+                Err(PlatformError::MissingHardware("MCP3004 adc"))
             },
-            AdcConverter::None => {
-                panic!("get_adcs was called before the main board was initialized, this is not allowed to happen, exitting...");
+            AdcType::None => {
+                Ok(AdcConverter::None)
             }
         }
     }
 
     #[cfg(feature = "adcs")]
-    /// Reads from one of the 4 ADC channels
+    /// Reads the voltage of one of the 4 supply measurements, converted with the calibration of the controller
     /// 
     /// # Arguments
     /// 
@@ -468,70 +775,245 @@ impl MainBoard {
     /// 
     /// # Examples
     /// 
+    /// ```no_run
+    /// use gocontroll_platform::gocontroll::mainboard::{MainBoard,AdcChannel};
+    /// let mut mainboard = MainBoard::new();
+    /// mainboard.get_hardware_config().unwrap();
+    /// println!("Battery voltage: {}", mainboard.read_adc_channel(AdcChannel::K30).unwrap());
     /// ```
+    pub fn read_adc_channel(&self, channel: AdcChannel) -> error::Result<Millivolts> {
+        let calibration = self.adc_calibration.channels[channel.index()];
+        self.read_adc_raw(channel).map(|raw| calibration.millivolts(raw))
+    }
+
+    #[cfg(feature = "adcs")]
+    /// Reads the raw adc value of one of the 4 supply measurements, without calibration
+    pub fn read_adc_raw(&self, channel: AdcChannel) -> error::Result<u16> {
+        Self::read_adc(self.get_adc()?, channel)
+    }
+
+    #[cfg(all(feature = "adcs", feature = "async"))]
+    /// Reads the voltage of one of the 4 supply measurements on the blocking thread pool of the tokio runtime
+    ///
+    /// # Examples
+    ///
+    /// ```no_run
+    /// # async fn example() {
     /// use gocontroll_platform::gocontroll::mainboard::{MainBoard,AdcChannel};
-    /// use futures::future::join;
     /// let mut mainboard = MainBoard::new();
-    /// mainboard.init(&mut []);
-    /// join(mainboard.read_adc_channel(AdcChannel::K30)).unwrap();
+    /// mainboard.get_hardware_config().unwrap();
+    /// println!("Battery voltage: {}", mainboard.read_adc_channel_async(AdcChannel::K30).await.unwrap());
+    /// # }
+    /// ```
+    pub async fn read_adc_channel_async(&self, channel: AdcChannel) -> error::Result<Millivolts> {
+        let adc = self.get_adc()?.clone();
+        let calibration = self.adc_calibration.channels[channel.index()];
+        Self::run_blocking(move || Self::read_adc(&adc, channel)).await.map(|raw| calibration.millivolts(raw))
+    }
+
+    #[cfg(feature = "adcs")]
+    /// The calibration of the supply measurements: the calibration of the board definition with the calibration file of the controller applied
+    pub fn adc_calibration(&self) -> &SupplyCalibration {
+        &self.adc_calibration
+    }
+
+    #[cfg(feature = "adcs")]
+    /// Use `calibration` for a supply measurement until the hardware configuration is read again, [MainBoard::save_adc_calibration] makes it permanent
+    pub fn set_adc_calibration(&mut self, channel: AdcChannel, calibration: AdcCalibration) {
+        self.adc_calibration.channels[channel.index()] = calibration;
+        if let Some(sampler) = &self.supply_sampler {
+            sampler.set_calibration(self.adc_calibration);
+        }
+    }
+
+    #[cfg(feature = "adcs")]
+    /// Store the calibration of all supply measurements in the calibration file of the controller
+    pub fn save_adc_calibration(&self) -> error::Result<()> {
+        if let Some(directory) = self.paths().adc_calibration.parent() {
+            fs::create_dir_all(directory)?;
+        }
+        Ok(fs::write(&self.paths().adc_calibration, self.adc_calibration.to_string())?)
+    }
+
+    #[cfg(feature = "adcs")]
+    /// Average a number of raw readings of a supply measurement, with the `reference` voltage applied to its input
+    pub fn calibration_point(&self, channel: AdcChannel, reference: Millivolts) -> error::Result<CalibrationPoint> {
+        let mut sum: u32 = 0;
+        for _ in 0..CALIBRATIONSAMPLES {
+            sum += self.read_adc_raw(channel)? as u32;
+        }
+        Ok(CalibrationPoint { raw: ((sum + CALIBRATIONSAMPLES/2) / CALIBRATIONSAMPLES) as u16, reference })
+    }
+
+    #[cfg(feature = "adcs")]
+    /// Calibrate a supply measurement from two [MainBoard::calibration_point]s taken with different reference voltages
+    ///
+    /// # Examples
+    ///
+    /// ```no_run
+    /// use gocontroll_platform::gocontroll::{mainboard::{MainBoard,AdcChannel},calibration::Millivolts};
+    /// let mut mainboard = MainBoard::new();
+    /// mainboard.get_hardware_config().unwrap();
+    /// // apply 12 V to K30
+    /// let low = mainboard.calibration_point(AdcChannel::K30, Millivolts(12000)).unwrap();
+    /// // apply 24 V to K30
+    /// let high = mainboard.calibration_point(AdcChannel::K30, Millivolts(24000)).unwrap();
+    /// mainboard.calibrate_adc_channel(AdcChannel::K30, low, high).unwrap();
+    /// mainboard.save_adc_calibration().unwrap();
+    /// ```
+    pub fn calibrate_adc_channel(&mut self, channel: AdcChannel, first: CalibrationPoint, second: CalibrationPoint) -> error::Result<AdcCalibration> {
+        let calibration = AdcCalibration::from_points(first, second).ok_or(PlatformError::InvalidCalibration)?;
+        self.set_adc_calibration(channel, calibration);
+        Ok(calibration)
+    }
+
+    #[cfg(feature = "adcs")]
+    /// Apply the calibration file of the controller to `calibration`, a controller without a calibration file uses it as is
+    fn load_adc_calibration(path: &Path, calibration: SupplyCalibration) -> error::Result<SupplyCalibration> {
+        match fs::read_to_string(path) {
+            Ok(file) => Ok(calibration.with_overrides(&file)?),
+            Err(err) if err.kind() == io::ErrorKind::NotFound => Ok(calibration),
+            Err(err) => Err(err.into()),
+        }
+    }
+
+    #[cfg(feature = "adcs")]
+    /// Reads the 4 supply measurements on a background thread at the rate of `config`. A sampler that is already running is restarted with `config`
+    /// and keeps its readings, so the monitors on them keep running. The returned readings can be cloned to every thread that watches the supply voltages.
+    ///
+    /// # Examples
+    ///
+    /// ```no_run
+    /// use gocontroll_platform::gocontroll::{mainboard::{MainBoard,AdcChannel},sampler::SamplerConfig};
+    /// let mut mainboard = MainBoard::new();
+    /// mainboard.init(&mut []).unwrap();
+    /// let readings = mainboard.start_supply_sampler(SamplerConfig::default()).unwrap();
+    /// std::thread::sleep(std::time::Duration::from_millis(200));
+    /// if let Some(statistics) = readings.statistics(AdcChannel::K30) {
+    ///     println!("Battery voltage: {} (min {}, max {})", statistics.average, statistics.min.voltage, statistics.max.voltage);
+    /// }
+    /// ```
+    pub fn start_supply_sampler(&mut self, config: SamplerConfig) -> error::Result<SupplyReadings> {
+        let adc = self.get_adc()?.clone();
+        let read = move |channel| Self::read_adc(&adc, channel);
+        let sampler = match self.supply_sampler.take() {
+            Some(sampler) => sampler.restart(config, self.adc_calibration, read)?,
+            None => SupplySampler::start(config, self.adc_calibration, read)?,
+        };
+        let readings = sampler.readings();
+        self.supply_sampler = Some(sampler);
+        Ok(readings)
+    }
+
+    #[cfg(feature = "adcs")]
+    /// The readings of the running supply sampler
+    pub fn supply_readings(&self) -> Option<SupplyReadings> {
+        self.supply_sampler.as_ref().map(SupplySampler::readings)
+    }
+
+    #[cfg(feature = "adcs")]
+    /// Stops the supply sampler, the readings taken so far stay available through the handles already given out.
+    /// The monitors on the readings stop with it, a sampler started after this records into new readings
+    pub fn stop_supply_sampler(&mut self) {
+        self.supply_sampler = None;
+    }
+
+    #[cfg(feature = "adcs")]
+    /// Watches the ignition lines of `config` through the supply sampler, which is started with its default settings when it isn't running yet
+    ///
+    /// # Examples
+    ///
+    /// ```no_run
+    /// use gocontroll_platform::gocontroll::{mainboard::MainBoard,ignition::{IgnitionConfig,IgnitionEvent}};
+    /// let mut mainboard = MainBoard::new();
+    /// mainboard.init(&mut []).unwrap();
+    /// let ignition = mainboard.start_ignition_monitor(IgnitionConfig::default()).unwrap();
+    /// for event in ignition.subscribe() {
+    ///     match event {
+    ///         IgnitionEvent::IgnitionOn { off_for: Some(off_for), .. } => println!("Key on after {:?} off", off_for),
+    ///         IgnitionEvent::IgnitionOn { off_for: None, .. } => println!("Key on"),
+    ///         IgnitionEvent::IgnitionOff { on_for, .. } => println!("Key off after {:?} on", on_for),
+    ///     }
+    /// }
     /// ```
-    pub fn read_adc_channel(&self, channel: AdcChannel) -> io::Result<u16> {
+    pub fn start_ignition_monitor(&mut self, config: IgnitionConfig) -> error::Result<IgnitionMonitor> {
+        let readings = match self.supply_readings() {
+            Some(readings) => readings,
+            None => self.start_supply_sampler(SamplerConfig::default())?,
+        };
+        Ok(IgnitionMonitor::start(&readings, config))
+    }
+
+    #[cfg(feature = "adcs")]
+    /// Supervises the K30 supply against the limits of `config` through the supply sampler, which is started with its default settings when it isn't running yet
+    ///
+    /// # Examples
+    ///
+    /// ```no_run
+    /// use gocontroll_platform::gocontroll::{mainboard::MainBoard,battery::{BatteryConfig,BatteryAlarm}};
+    /// let mut mainboard = MainBoard::new();
+    /// mainboard.init(&mut []).unwrap();
+    /// let battery = mainboard.start_battery_supervisor(BatteryConfig::nominal_24v()).unwrap();
+    /// battery.on_alarm(|alarm| if let BatteryAlarm::Cleared(event) = alarm {
+    ///     eprintln!("{:?} for {:?}, lowest voltage {}", event.condition, event.duration, event.min);
+    /// });
+    /// ```
+    pub fn start_battery_supervisor(&mut self, config: BatteryConfig) -> error::Result<BatterySupervisor> {
+        let readings = match self.supply_readings() {
+            Some(readings) => readings,
+            None => self.start_supply_sampler(SamplerConfig::default())?,
+        };
+        Ok(BatterySupervisor::start(&readings, config))
+    }
+
+    #[cfg(feature = "adcs")]
+    /// The ADS1015 of the controller, for continuous conversions or the ALERT pin. A reading of a supply measurement stops a continuous conversion,
+    /// and the calibration of the supply measurements only holds for the gain of [Ads1015Config::default].
+    pub fn ads1015(&self) -> Option<Arc<Mutex<Ads1015>>> {
         match &self.adc {
-            AdcConverter::Mcp3004(adcs) => {
-                let mut buffer = String::with_capacity(5);
-                // let mut adc_file;
-                match channel {
-                    AdcChannel::K30 => {
-                        return Ok(Self::convert_mcp(fs::read_to_string(adcs[3].as_ref().unwrap())?.trim_end()));
-                    },
-                    AdcChannel::K15A => {
-                        return Ok(Self::convert_mcp(fs::read_to_string(adcs[0].as_ref().unwrap())?.trim_end()));
-                    },
-                    AdcChannel::K15B => {
-                        return Ok(Self::convert_mcp(fs::read_to_string(adcs[1].as_ref().unwrap())?.trim_end()));
-                    },
-                    AdcChannel::K15C => {
-                        return Ok(Self::convert_mcp(fs::read_to_string(adcs[2].as_ref().unwrap())?.trim_end()));
-                    }
-                }
-            },
-            AdcConverter::Ads1015 => {
-                let mut rx: [u8;2] = [0;2];
-                let mut tx_config: [u8;3] = [0x01, channel as u8, 0xe3]; //address of k30 is 0xf3
-                let convert: u8 = 0x00;
-                let config: u8 = 0x01;
-                let mut adc_temp = I2c::from_path(ADS_ADC)?;
-                adc_temp.smbus_set_slave_address(0x48, false)?;
-                adc_temp.write_all(&tx_config)?;
-                adc_temp.write_all(&[convert])?;
-                adc_temp.read_exact(&mut rx)?;
-                Ok(Self::convert_ads(rx))
-            },
-            AdcConverter::None => { panic!("Can't read adc because main board is not initialized yet")}
+            AdcConverter::Ads1015(_, ads) => Some(ads.clone()),
+            _ => None,
         }
     }
 
     #[cfg(feature = "adcs")]
-    fn convert_mcp(string_val: &str) -> u16 {
-        if string_val.eq("") {
-            0
-        } else {
-            (string_val.parse::<f32>().unwrap()*25.54f32) as u16
+    fn get_adc(&self) -> error::Result<&AdcConverter> {
+        match (&self.adc, &self.board) {
+            (AdcConverter::None, None) => Err(PlatformError::NotInitialized),
+            (AdcConverter::None, Some(_)) => Err(PlatformError::MissingHardware("adc")),
+            (adc, _) => Ok(adc),
+        }
+    }
+
+    #[cfg(feature = "adcs")]
+    fn read_adc(adc: &AdcConverter, channel: AdcChannel) -> error::Result<u16> {
+        match adc {
+            AdcConverter::Mcp3004(adcs) => {
+                let adc = adcs[channel.index()].as_ref().ok_or(PlatformError::MissingHardware("MCP3004 adc"))?;
+                Self::parse_mcp(fs::read_to_string(adc)?.trim_end())
+            },
+            AdcConverter::Ads1015(inputs, ads) => {
+                let mut ads = ads.lock().map_err(|_| io::Error::other("ADS1015 lock poisoned"))?;
+                // a single ended input can't go below ground, a negative conversion is noise around 0 V
+                Ok(ads.read_single(Ads1015Input::single(inputs[channel.index()]))?.max(0) as u16)
+            },
+            AdcConverter::None => Err(PlatformError::NotInitialized)
         }
     }
+
     #[cfg(feature = "adcs")]
-    fn convert_ads(read_buff:[u8;2]) -> u16 {
-        if (read_buff[0] & 0x80) >> 7 == 1 {
-            0
+    fn parse_mcp(string_val: &str) -> error::Result<u16> {
+        if string_val.is_empty() {
+            Ok(0)
         } else {
-            ((((read_buff[0] as u16) << 4) | ((read_buff[1] as u16 & 0xf0) >> 4)) as f32 * 15.608f32) as u16
+            Ok(string_val.parse::<u16>().map_err(|err| io::Error::new(io::ErrorKind::InvalidData, err))?)
         }
     }
     #[cfg(feature = "leds")]
-    fn initialize_leds(&self) -> io::Result<()> {
+    fn initialize_leds(&self) -> error::Result<()> {
         match &self.led_control {
             LedControl::Rukr => {
-                let mut led_temp = I2c::from_path(RUKR_LEDS)?;
+                let mut led_temp = I2c::from_path(&self.paths().rukr_leds)?;
                 led_temp.smbus_set_slave_address(0x14, false)?;
                 led_temp.smbus_write_byte_data(23, 255)?;
                 led_temp.smbus_write_byte_data(0, 64)?;
@@ -543,10 +1025,22 @@ impl MainBoard {
         }
     }
     #[cfg(feature = "leds")]
-    pub fn set_led(&self, led: EnclosureLed, red: u8, green: u8, blue: u8) -> io::Result<()> {
-        match &self.led_control {
+    pub fn set_led(&self, led: EnclosureLed, red: u8, green: u8, blue: u8) -> error::Result<()> {
+        Self::write_led(&self.led_control, self.paths(), self.module_layout, led, red, green, blue)
+    }
+    #[cfg(all(feature = "leds", feature = "async"))]
+    /// Sets the color of an enclosure led on the blocking thread pool of the tokio runtime
+    pub async fn set_led_async(&self, led: EnclosureLed, red: u8, green: u8, blue: u8) -> error::Result<()> {
+        let led_control = self.led_control;
+        let paths = self.paths().clone();
+        let module_layout = self.module_layout;
+        Self::run_blocking(move || Self::write_led(&led_control, &paths, module_layout, led, red, green, blue)).await
+    }
+    #[cfg(feature = "leds")]
+    fn write_led(led_control: &LedControl, paths: &PlatformPaths, module_layout: ModuleLayout, led: EnclosureLed, red: u8, green: u8, blue: u8) -> error::Result<()> {
+        match led_control {
             LedControl::Rukr => {
-                let mut led_temp = I2c::from_path(RUKR_LEDS)?;
+                let mut led_temp = I2c::from_path(&paths.rukr_leds)?;
                 led_temp.smbus_set_slave_address(0x14, false)?;
 
                 led_temp.smbus_write_byte_data(0x0B+(&(led as u8))*3, red)?;
@@ -554,65 +1048,291 @@ impl MainBoard {
                 led_temp.smbus_write_byte_data(0x0B+(&(led as u8))*3+2, blue)?;
             },
             LedControl::Gpio => {
-                fs::write(GPIO_LEDS[&(led as usize)*3], if red>0 {"1"} else {"0"})?;
-                fs::write(GPIO_LEDS[&(led as usize)*3+1], if green>0 {"1"} else {"0"})?;
-                fs::write(GPIO_LEDS[&(led as usize)*3+2], if blue>0 {"1"} else {"0"})?;
+                fs::write(&paths.gpio_leds[&(led as usize)*3], if red>0 {"1"} else {"0"})?;
+                fs::write(&paths.gpio_leds[&(led as usize)*3+1], if green>0 {"1"} else {"0"})?;
+                fs::write(&paths.gpio_leds[&(led as usize)*3+2], if blue>0 {"1"} else {"0"})?;
             },
             LedControl::None => {
-                panic!("Cannot set led, either MainBoard::initialize_main_board was not executed yet, or this controller has no available leds.")
+                return Err(if module_layout == ModuleLayout::None {
+                    PlatformError::NotInitialized
+                } else {
+                    PlatformError::MissingHardware("enclosure leds")
+                });
             }
         }
         Ok(())
     }
+    #[cfg(feature = "async")]
+    /// Runs blocking device I/O on the blocking thread pool of the tokio runtime, a panic in `f` is passed on to the caller
+    pub(crate) async fn run_blocking<F, R>(f: F) -> error::Result<R>
+    where
+        F: FnOnce() -> error::Result<R> + Send + 'static,
+        R: Send + 'static,
+    {
+        match tokio::task::spawn_blocking(f).await {
+            Ok(result) => result,
+            Err(err) if err.is_panic() => std::panic::resume_unwind(err.into_panic()),
+            Err(err) => Err(PlatformError::Io(io::Error::other(err))),
+        }
+    }
     #[cfg(feature = "modules")]
-    pub fn send_module_spi(spidev: Arc<Mutex<Spidev>>, command: u8, direction: CommunicationDirection, module_id: u8, message_type: MessageType, message_index: u8, tx:&mut [u8], length:usize) -> io::Result<()> {
-        tx[0] = command;
-        tx[1] = {length-1} as u8;
-        tx[2] = direction as u8;
-        tx[3] = module_id;
-        tx[4] = message_type as u8;
-        tx[5] = message_index;
-        tx[length-1] = MainBoard::module_checksum(&tx, length)?;
-        let mut transfer = spidev::SpidevTransfer::write(&tx);
-        spidev.lock().as_mut().unwrap().transfer(&mut transfer)?;
+    /// Sends a message to a module, whatever the module sends back is ignored
+    pub fn send_module_frame<T: ModuleTransport>(transport: &Mutex<T>, frame: &ModuleFrame) -> error::Result<()> {
+        let mut tx = [0u8;MAXMESSAGELENGTH];
+        let length = frame.encode(&mut tx);
+        lock_transport(transport)?.write(&tx[..length])?;
         Ok(())
     }
     #[cfg(feature = "modules")]
-    pub fn send_receive_module_spi(spidev: Arc<Mutex<Spidev>>, command: u8, direction: CommunicationDirection, module_id: u8, message_type: MessageType, message_index: u8, tx:&mut [u8], rx:&mut [u8], length:usize) -> io::Result<()> {
-        tx[0] = command;
-        tx[1] = {length-1} as u8;
-        tx[2] = direction as u8;
-        tx[3] = module_id;
-        tx[4] = message_type as u8;
-        tx[5] = message_index;
-        tx[length-1] = MainBoard::module_checksum(&tx, length)?;
-        rx[0] = 0;
-        rx[length-1] = 0;
-
-        let mut transfer = spidev::SpidevTransfer::read_write(&tx,rx);
-        spidev.lock().as_mut().unwrap().transfer(&mut transfer)?;
-        MainBoard::module_checksum(&rx, length)?;
-        Ok(())        
+    /// Sends a message to a module and decodes the message the module sends back in the same transfer, which has the same length
+    pub fn exchange_module_frame<T: ModuleTransport>(transport: &Mutex<T>, frame: &ModuleFrame) -> error::Result<ModuleFrame> {
+        let mut tx = [0u8;MAXMESSAGELENGTH];
+        let mut rx = [0u8;MAXMESSAGELENGTH];
+        let length = frame.encode(&mut tx);
+        lock_transport(transport)?.transfer(&tx[..length], &mut rx[..length])?;
+        ModuleFrame::decode(&rx, length)
     }
     #[cfg(feature = "modules")]
-    pub fn spi_dummy_send(module: &dyn GOcontrollModule) -> io::Result<()> {
-        const SPIDUMMY: [u8;6] = [1,2,3,4,5,6];
-        let mut transfer = spidev::SpidevTransfer::write(&SPIDUMMY);
-        module.get_spidev().lock().as_mut().unwrap().transfer(&mut transfer)?;
+    pub fn spi_dummy_send(module: &dyn GOcontrollModule) -> error::Result<()> {
+        module.transfer(&SPIDUMMY, &mut [0u8;6])?;
         Ok(())
     }
     #[cfg(feature = "modules")]
-    pub fn escape_module_bootloader(module: &dyn GOcontrollModule) ->io::Result<EscapeBootloaderResponse> {
+    /// Escapes the bootloader of `module`, a module that answers from its bootloader was reset and is told through [GOcontrollModule::firmware_started]
+    pub fn escape_module_bootloader(module: &dyn GOcontrollModule) -> error::Result<EscapeBootloaderResponse> {
+        let rx = Self::escape_bootloader(|tx, rx| module.transfer(tx, rx))?;
+        if rx[0] == BOOTLOADERRESPONSE {
+            module.firmware_started();
+        }
+        Ok(EscapeBootloaderResponse{ bootloader: rx[0], firmware: rx[6], identity: ModuleIdentity::decode(&rx) })
+    }
+    #[cfg(feature = "modules")]
+    /// Sends the bootloader escape message through `transfer` and returns the response after checking its checksum
+    fn escape_bootloader(transfer: impl FnOnce(&[u8], &mut [u8]) -> io::Result<()>) -> error::Result<[u8;BOOTMESSAGELENGTHCHECK]> {
         let mut tx: [u8;BOOTMESSAGELENGTHCHECK] = [0;BOOTMESSAGELENGTHCHECK];
         let mut rx: [u8;BOOTMESSAGELENGTHCHECK] = [0;BOOTMESSAGELENGTHCHECK];
         tx[0] = 19;
         tx[1] = {BOOTMESSAGELENGTH -1} as u8;
         tx[2] = 19;
-        tx[BOOTMESSAGELENGTH-1] = MainBoard::module_checksum(&tx, BOOTMESSAGELENGTH)?;
-        let mut transfer = spidev::SpidevTransfer::read_write(&tx, &mut rx);
-        
-        module.get_spidev().lock().as_mut().unwrap().transfer(&mut transfer)?;
+        tx[BOOTMESSAGELENGTH-1] = MainBoard::calculate_checksum(&tx, BOOTMESSAGELENGTH);
+
+        transfer(&tx, &mut rx)?;
         MainBoard::module_checksum(&rx, BOOTMESSAGELENGTH)?;
-        Ok(EscapeBootloaderResponse{ bootloader: rx[0], firmware: rx[6]})
+        Ok(rx)
+    }
+}
+
+#[cfg(all(test, any(feature = "adcs", feature = "simulator")))]
+mod tests {
+    use super::*;
+
+    /// Builds a fake device tree of a Moduline Screen, which has gpio leds and an MCP3004 adc, below the temp directory
+    fn fake_platform(name: &str) -> PathBuf {
+        let root = std::env::temp_dir().join(format!("gocontroll-{}-{}", name, std::process::id()));
+        let paths = PlatformPaths::with_root(&root);
+        fs::create_dir_all(paths.hardware.parent().unwrap()).unwrap();
+        fs::write(&paths.hardware, "Moduline Screen V1.05\0").unwrap();
+        let iio = paths.iio_devices.join("iio:device0");
+        fs::create_dir_all(&iio).unwrap();
+        fs::write(iio.join("name"), "mcp3004\n").unwrap();
+        for channel in 0..4 {
+            fs::write(iio.join(format!("in_voltage{}_raw", channel)), format!("{}\n", 100*(channel+1))).unwrap();
+        }
+        for path in paths.resets.iter().chain(paths.gpio_leds.iter()) {
+            fs::create_dir_all(path.parent().unwrap()).unwrap();
+            fs::write(path, "0").unwrap();
+        }
+        root
+    }
+
+    #[test]
+    #[cfg(feature = "adcs")]
+    fn hardware_config_from_fake_tree() {
+        let root = fake_platform("hardware");
+        let mut mainboard = MainBoard::with_paths(PlatformPaths::with_root(&root));
+        mainboard.get_hardware_config().unwrap();
+        assert!(matches!(mainboard.module_layout, ModuleLayout::ModulineDisplay));
+        assert_eq!(mainboard.read_adc_channel(AdcChannel::K30).unwrap(), Millivolts(10216));
+        assert_eq!(mainboard.read_adc_channel(AdcChannel::K15A).unwrap(), Millivolts(2554));
+
+        let readings = mainboard.start_supply_sampler(SamplerConfig::new(std::time::Duration::from_millis(1))).unwrap();
+        while readings.latest(AdcChannel::K15A).is_none() {
+            std::thread::sleep(std::time::Duration::from_millis(1));
+        }
+        mainboard.stop_supply_sampler();
+        assert!(mainboard.supply_readings().is_none());
+        assert_eq!(readings.latest(AdcChannel::K30).unwrap().voltage, Millivolts(10216));
+        assert_eq!(readings.average(AdcChannel::K15A), Some(Millivolts(2554)));
+
+        let mut mainboard = MainBoard::with_paths(PlatformPaths::with_root(&root)).with_boards(BoardTable::empty());
+        assert!(matches!(mainboard.get_hardware_config(), Err(PlatformError::UnknownHardware(hardware)) if hardware == "Moduline Screen V1.05"));
+        assert!(matches!(mainboard.read_adc_channel(AdcChannel::K30), Err(PlatformError::NotInitialized)));
+        fs::remove_dir_all(root).unwrap();
+    }
+
+    #[test]
+    #[cfg(feature = "adcs")]
+    fn supply_calibration() {
+        let root = fake_platform("calibration");
+        let paths = PlatformPaths::with_root(&root);
+        fs::create_dir_all(paths.adc_calibration.parent().unwrap()).unwrap();
+        fs::write(&paths.adc_calibration, "K15A 25 -100\n").unwrap();
+        let mut mainboard = MainBoard::with_paths(paths.clone());
+        mainboard.get_hardware_config().unwrap();
+        assert_eq!(mainboard.read_adc_raw(AdcChannel::K15A).unwrap(), 100);
+        assert_eq!(mainboard.read_adc_channel(AdcChannel::K15A).unwrap(), Millivolts(2400));
+        assert_eq!(mainboard.read_adc_channel(AdcChannel::K30).unwrap(), Millivolts(10216));
+
+        let k30 = paths.iio_devices.join("iio:device0").join("in_voltage3_raw");
+        fs::write(&k30, "470\n").unwrap();
+        let low = mainboard.calibration_point(AdcChannel::K30, Millivolts(12000)).unwrap();
+        assert!(matches!(mainboard.calibrate_adc_channel(AdcChannel::K30, low, low), Err(PlatformError::InvalidCalibration)));
+        fs::write(&k30, "940\n").unwrap();
+        let high = mainboard.calibration_point(AdcChannel::K30, Millivolts(24000)).unwrap();
+        mainboard.calibrate_adc_channel(AdcChannel::K30, low, high).unwrap();
+        assert_eq!(mainboard.read_adc_channel(AdcChannel::K30).unwrap(), Millivolts(24000));
+        mainboard.save_adc_calibration().unwrap();
+
+        let mut mainboard = MainBoard::with_paths(paths);
+        mainboard.get_hardware_config().unwrap();
+        assert_eq!(mainboard.read_adc_channel(AdcChannel::K30).unwrap(), Millivolts(24000));
+        assert_eq!(mainboard.read_adc_channel(AdcChannel::K15A).unwrap(), Millivolts(2400));
+        fs::remove_dir_all(root).unwrap();
     }
-}
\ No newline at end of file
+
+    #[test]
+    #[cfg(feature = "simulator")]
+    fn init_with_simulated_modules() {
+        use crate::gocontroll::{module::ModuleSlot,inputmodule6ch::InputModuleSupply,inputmodule10ch::*,outputmodule6ch::*,simulator::*};
+        let root = fake_platform("init");
+        let mut mainboard = MainBoard::with_paths(PlatformPaths::with_root(&root));
+
+        let input = SimulatedModule::new(SimulatedModuleType::InputModule10Ch);
+        let output = SimulatedModule::new(SimulatedModuleType::OutputModule6Ch);
+        let mut input_module: InputModule10Ch<SimulatedTransport> = InputModule10Ch::new(ModuleSlot::Moduleslot1, [None;10], InputModuleSupply::On)
+            .with_transport(input.transport());
+        let mut output_module: OutputModule6Ch<SimulatedTransport> = OutputModule6Ch::new(ModuleSlot::Moduleslot2, [None;6],
+            OutputModule6ChFrequecyConfig::new(OutputModule6ChFrequency::Freq1KHz, OutputModule6ChFrequency::Freq1KHz, OutputModule6ChFrequency::Freq1KHz))
+            .with_transport(output.transport());
+
+        mainboard.init(&mut [&mut input_module, &mut output_module]).unwrap();
+        assert!(!input.in_bootloader());
+        assert!(!output.in_bootloader());
+        assert!(input.configuration(1).is_some());
+        assert!(output.configuration(1).is_some());
+        assert!(output.configuration(2).is_some());
+        assert_eq!(output.checksum_errors(), 0);
+        assert_eq!(fs::read_to_string(&PlatformPaths::with_root(&root).resets[1]).unwrap(), "10");
+        fs::remove_dir_all(root).unwrap();
+    }
+
+    #[test]
+    #[cfg(feature = "simulator")]
+    fn discover_simulated_modules() {
+        use crate::gocontroll::{module::ModuleSlot,inputmodule6ch::*,inventory::*,simulator::*};
+        let root = fake_platform("discover");
+        let mut mainboard = MainBoard::with_paths(PlatformPaths::with_root(&root));
+        let running = SimulatedModule::new(SimulatedModuleType::InputModule10Ch);
+        running.set_firmware_version(2, 1, 0);
+        let stuck = SimulatedModule::new(SimulatedModuleType::OutputModule6Ch);
+        stuck.inject_fault(SimulatedFault::StuckInBootloader);
+
+        let inventory = mainboard.discover_modules_with(|slot| match slot {
+            ModuleSlot::Moduleslot1 => Ok(running.transport()),
+            ModuleSlot::Moduleslot2 => Ok(stuck.transport()),
+            _ => Err(io::Error::from(io::ErrorKind::NotFound)),
+        }).unwrap();
+        assert_eq!(inventory.slots().count(), 2);
+        let SlotContents::Module(identity) = inventory.get(ModuleSlot::Moduleslot1) else { panic!("no module discovered in slot 1") };
+        assert_eq!(identity.to_string(), "20-10-2 revision 1 firmware 2.1.0");
+        assert_eq!(identity.module_id(), 12);
+        assert_eq!(inventory.get(ModuleSlot::Moduleslot2), SlotContents::Bootloader);
+
+        let mut input_module: InputModule6Ch<SimulatedTransport> = InputModule6Ch::new(ModuleSlot::Moduleslot1, [None;6],
+            Inputmodule6chSupplyConfig::new(InputModuleSupply::On, InputModuleSupply::On, InputModuleSupply::On))
+            .with_transport(running.transport());
+        let result = MainBoard::verify_modules(&inventory, &[&mut input_module]);
+        assert!(matches!(result, Err(PlatformError::ModuleMismatch { slot: ModuleSlot::Moduleslot1, expected: 11, found: Some(12) })));
+        let result = mainboard.init(&mut [&mut input_module]);
+        assert!(matches!(result, Err(PlatformError::ModuleMismatch { slot: ModuleSlot::Moduleslot1, expected: 11, found: Some(12) })));
+        assert!(running.configuration(1).is_none());
+        fs::remove_dir_all(root).unwrap();
+    }
+
+    #[test]
+    #[cfg(all(feature = "simulator", feature = "experimental"))]
+    fn flash_simulated_module() {
+        use crate::gocontroll::{module::{ModuleSlot,FirmwareVersion},flasher::*,simulator::*};
+        let root = fake_platform("flash");
+        let mut mainboard = MainBoard::with_paths(PlatformPaths::with_root(&root));
+        let firmware: Vec<u8> = (0..70).collect();
+        let image: String = firmware.chunks(16).enumerate().map(|(index, data)| {
+            let mut record = vec![data.len() as u8 + 5, 0x08, 0x00, 0x00, (index*16) as u8];
+            record.extend_from_slice(data);
+            record.push(0xff - record.iter().fold(0u8, |sum, byte| sum.wrapping_add(*byte)));
+            format!("S3{}\n", record.iter().map(|byte| format!("{:02X}", byte)).collect::<String>())
+        }).collect();
+        let image = FirmwareImage::parse(&image).unwrap();
+        assert_eq!(image.len(), 70);
+
+        let simulated = SimulatedModule::new(SimulatedModuleType::InputModule6Ch);
+        simulated.set_firmware_version(1, 2, 0);
+        let mut flasher = ModuleFlasher::new(ModuleSlot::Moduleslot1, &image, SIMULATEDBOOTLOADER, simulated.transport()).unwrap();
+        simulated.inject_fault(SimulatedFault::ProgramError);
+        let result = flasher.flash(&mut mainboard, |_| ());
+        assert!(matches!(result, Err(PlatformError::FlashFailed { stage: FlashStage::Program, address: 0x0800_0000, .. })));
+        assert_eq!(flasher.stage(), FlashStage::Program);
+
+        simulated.clear_faults();
+        let mut stages = Vec::new();
+        let identity = flasher.flash(&mut mainboard, |progress| stages.push((progress.stage, progress.done))).unwrap();
+        assert_eq!(identity.firmware, FirmwareVersion::new(1, 2, 0));
+        assert_eq!(stages.first(), Some(&(FlashStage::Program, 0)));
+        assert_eq!(stages.last(), Some(&(FlashStage::Done, 70)));
+        assert!(stages.contains(&(FlashStage::Verify, 64)));
+        assert_eq!(simulated.flash_contents(0x0800_0000, 72), [firmware, vec![0xff, 0xff]].concat());
+        assert!(!simulated.in_bootloader());
+        fs::remove_dir_all(root).unwrap();
+    }
+
+    #[test]
+    #[cfg(feature = "simulator")]
+    fn init_reports_module_errors() {
+        use crate::gocontroll::{module::{ModuleSlot,FirmwareVersion},inputmodule6ch::InputModuleSupply,inputmodule10ch::*,simulator::*};
+        let root = fake_platform("errors");
+        let new_module = |slot: ModuleSlot, simulated: &SimulatedModule| -> InputModule10Ch<SimulatedTransport> {
+            InputModule10Ch::new(slot, [None;10], InputModuleSupply::On).with_transport(simulated.transport())
+        };
+        let simulated = SimulatedModule::new(SimulatedModuleType::InputModule10Ch);
+
+        let mut mainboard = MainBoard::with_paths(PlatformPaths::with_root(&root));
+        let result = mainboard.init(&mut [&mut new_module(ModuleSlot::Moduleslot3, &simulated)]);
+        assert!(matches!(result, Err(PlatformError::UnsupportedSlot { slot: ModuleSlot::Moduleslot3, layout: ModuleLayout::ModulineDisplay })));
+
+        let mut mainboard = MainBoard::with_paths(PlatformPaths::with_root(&root));
+        let result = mainboard.init(&mut [&mut new_module(ModuleSlot::Moduleslot1, &simulated), &mut new_module(ModuleSlot::Moduleslot1, &simulated)]);
+        assert!(matches!(result, Err(PlatformError::SlotConflict(ModuleSlot::Moduleslot1))));
+
+        simulated.set_firmware_version(2, 0, 0);
+        simulated.set_serial(4242);
+        let mut mainboard = MainBoard::with_paths(PlatformPaths::with_root(&root));
+        let result = mainboard.init(&mut [&mut new_module(ModuleSlot::Moduleslot1, &simulated)]);
+        assert!(matches!(result, Err(PlatformError::UnsupportedFirmware { slot: ModuleSlot::Moduleslot1, firmware: FirmwareVersion { major: 2, minor: 0, patch: 0 } })));
+        assert_eq!(mainboard.module_identity(ModuleSlot::Moduleslot1).unwrap().serial, Some(4242));
+        assert!(simulated.configuration(1).is_none());
+        let mut mainboard = MainBoard::with_paths(PlatformPaths::with_root(&root)).with_firmware_check(FirmwareCheck::Warn);
+        assert!(mainboard.warnings().is_empty());
+        mainboard.init(&mut [&mut new_module(ModuleSlot::Moduleslot1, &simulated)]).unwrap();
+        assert!(simulated.configuration(1).is_some());
+        assert!(matches!(mainboard.take_warnings()[..], [PlatformError::UnsupportedFirmware { slot: ModuleSlot::Moduleslot1, .. }]));
+        assert!(mainboard.warnings().is_empty());
+
+        // the reset lines of the fake tree aren't connected to the simulated module
+        simulated.reset();
+        simulated.inject_fault(SimulatedFault::StuckInBootloader);
+        let mut mainboard = MainBoard::with_paths(PlatformPaths::with_root(&root));
+        let result = mainboard.init(&mut [&mut new_module(ModuleSlot::Moduleslot2, &simulated)]);
+        assert!(matches!(result, Err(PlatformError::BootloaderEscape { slot: ModuleSlot::Moduleslot2, response: EscapeBootloaderResponse { bootloader: 9, firmware: 0, identity: None } })));
+        fs::remove_dir_all(root).unwrap();
+    }
+}
@@ -1,9 +1,40 @@
+pub mod error;
 pub mod mainboard;
+pub mod board;
+pub mod calibration;
+#[cfg(feature = "adcs")]
+pub mod ads1015;
+#[cfg(feature = "adcs")]
+pub mod sampler;
+#[cfg(feature = "adcs")]
+pub mod ignition;
+#[cfg(feature = "adcs")]
+pub mod battery;
 #[cfg(feature = "modules")]
 pub mod module;
 #[cfg(feature = "modules")]
+pub mod frame;
+#[cfg(feature = "modules")]
+pub mod inventory;
+#[cfg(feature = "experimental")]
+pub mod flasher;
+#[cfg(feature = "modules")]
+pub mod inputmodule;
+#[cfg(feature = "modules")]
 pub mod inputmodule6ch;
 #[cfg(feature = "modules")]
 pub mod inputmodule10ch;
 #[cfg(feature = "modules")]
+pub mod inputmodule420ma;
+#[cfg(feature = "modules")]
+pub mod outputmodule;
+#[cfg(feature = "modules")]
 pub mod outputmodule6ch;
+#[cfg(feature = "modules")]
+pub mod outputmodule10ch;
+#[cfg(feature = "modules")]
+pub mod registry;
+#[cfg(all(feature = "modules", feature = "serde"))]
+pub mod config;
+#[cfg(feature = "simulator")]
+pub mod simulator;
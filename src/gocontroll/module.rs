@@ -1,9 +1,15 @@
-use core::fmt::{Debug,Display};
+use core::fmt::Display;
+use std::any::Any;
 use std::io;
-use std::sync::{Arc,Mutex};
+use std::ops::RangeInclusive;
+use std::sync::{Arc,Mutex,MutexGuard};
 use spidev::Spidev;
+#[cfg(feature = "serde")]
+use serde::{Serialize,Deserialize};
 
-use super::mainboard::MainBoard;
+use super::mainboard::{MainBoard,PlatformPaths};
+use super::frame::ModuleFrame;
+use super::error::{self,PlatformError};
 
 
 ///////////////////////////////////////////////////////
@@ -19,8 +25,9 @@ pub const SPIERRORMESSAGE: &str = "Incorrectly initialized spi device";
 
 #[allow(unused)]
 #[repr(u8)]
-#[derive(Copy,Clone,Debug)]
-/// All potentially available module slots, code that tries to configure a module on a slot that doesn't exist (slot 5 on a mini for example) will panic upon initialization.
+#[derive(Copy,Clone,Debug,PartialEq,Eq)]
+#[cfg_attr(feature = "serde", derive(Serialize,Deserialize))]
+/// All potentially available module slots, code that tries to configure a module on a slot that doesn't exist (slot 5 on a mini for example) will get an error upon initialization.
 pub enum ModuleSlot {
     Moduleslot1 = 0u8,
     Moduleslot2 = 1u8,
@@ -34,6 +41,7 @@ pub enum ModuleSlot {
 
 #[allow(unused)]
 #[repr(u8)]
+#[derive(Copy,Clone,Debug,PartialEq,Eq)]
 pub enum CommunicationDirection {
     ToModule = 1u8,
     FromModule = 2u8,
@@ -41,6 +49,7 @@ pub enum CommunicationDirection {
 
 #[allow(unused)]
 #[repr(u8)]
+#[derive(Copy,Clone,Debug,PartialEq,Eq)]
 pub enum MessageType {
     ModuleId = 1u8,
     Configuration = 2u8,
@@ -48,9 +57,123 @@ pub enum MessageType {
     Feedback = 4u8,
 }
 
+/// The firmware of a module puts this in the first byte of its identification
+pub const FIRMWARERESPONSE: u8 = 20;
+
+#[derive(Debug,Copy,Clone)]
 pub struct EscapeBootloaderResponse {
     pub bootloader: u8,
-    pub firmware:u8
+    pub firmware:u8,
+    /// The identification of the module, None if it didn't start its firmware
+    pub identity: Option<ModuleIdentity>,
+}
+
+/// The version of the firmware running on a module
+#[derive(Debug,Copy,Clone,PartialEq,Eq,PartialOrd,Ord,Hash)]
+pub struct FirmwareVersion {
+    pub major: u8,
+    pub minor: u8,
+    pub patch: u8,
+}
+
+/// How a module identifies itself in its answer to the bootloader escape
+#[derive(Debug,Copy,Clone,PartialEq,Eq)]
+pub struct ModuleIdentity {
+    /// The article number of the module, 20-10-1 for a 6 channel input module
+    pub article: [u8;3],
+    pub hardware_revision: u8,
+    pub firmware: FirmwareVersion,
+    /// The serial number of the module, None on firmware that doesn't report it
+    pub serial: Option<u32>,
+}
+
+impl FirmwareVersion {
+    pub const fn new(major: u8, minor: u8, patch: u8) -> FirmwareVersion {
+        FirmwareVersion { major, minor, patch }
+    }
+}
+
+impl Display for FirmwareVersion {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{}.{}.{}", self.major, self.minor, self.patch)
+    }
+}
+
+#[allow(unused)]
+impl ModuleIdentity {
+    /// Decode the identification from the answer to the bootloader escape, None if the module didn't start its firmware.
+    /// The answer holds [FIRMWARERESPONSE], the article number, the hardware revision and the firmware version from byte 6 on,
+    /// firmware that reports a serial number puts it in the 4 bytes after that, older firmware leaves them zero.
+    pub fn decode(rx: &[u8]) -> Option<ModuleIdentity> {
+        if rx.len() < 17 || rx[6] != FIRMWARERESPONSE {
+            return None;
+        }
+        let serial = u32::from_le_bytes([rx[13], rx[14], rx[15], rx[16]]);
+        Some(ModuleIdentity {
+            article: [rx[6], rx[7], rx[8]],
+            hardware_revision: rx[9],
+            firmware: FirmwareVersion::new(rx[10], rx[11], rx[12]),
+            serial: if serial == 0 { None } else { Some(serial) },
+        })
+    }
+
+    /// The module id in the header of the messages of the module, the sum of the last two parts of the article number
+    pub const fn module_id(&self) -> u8 {
+        self.article[1].wrapping_add(self.article[2])
+    }
+}
+
+impl Display for ModuleIdentity {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{}-{}-{} revision {} firmware {}", self.article[0], self.article[1], self.article[2], self.hardware_revision, self.firmware)?;
+        if let Some(serial) = self.serial {
+            write!(f, " serial {}", serial)?;
+        }
+        Ok(())
+    }
+}
+
+impl TryFrom<u8> for CommunicationDirection {
+    type Error = PlatformError;
+
+    fn try_from(value: u8) -> Result<Self, Self::Error> {
+        match value {
+            1 => Ok(CommunicationDirection::ToModule),
+            2 => Ok(CommunicationDirection::FromModule),
+            _ => Err(PlatformError::Io(io::Error::new(io::ErrorKind::InvalidData, "invalid communication direction in module message"))),
+        }
+    }
+}
+
+impl TryFrom<u8> for MessageType {
+    type Error = PlatformError;
+
+    fn try_from(value: u8) -> Result<Self, Self::Error> {
+        match value {
+            1 => Ok(MessageType::ModuleId),
+            2 => Ok(MessageType::Configuration),
+            3 => Ok(MessageType::Data),
+            4 => Ok(MessageType::Feedback),
+            _ => Err(PlatformError::Io(io::Error::new(io::ErrorKind::InvalidData, "invalid message type in module message"))),
+        }
+    }
+}
+
+impl ModuleSlot {
+    /// The slot with `index`, counting from 0 like the discriminant of the slot
+    pub const fn from_index(index: usize) -> Option<ModuleSlot> {
+        match index {
+            0 => Some(ModuleSlot::Moduleslot1),
+            1 => Some(ModuleSlot::Moduleslot2),
+            2 => Some(ModuleSlot::Moduleslot3),
+            3 => Some(ModuleSlot::Moduleslot4),
+            4 => Some(ModuleSlot::Moduleslot5),
+            5 => Some(ModuleSlot::Moduleslot6),
+            6 => Some(ModuleSlot::Moduleslot7),
+            7 => Some(ModuleSlot::Moduleslot8),
+            _ => None,
+        }
+    }
 }
 
 impl Display for ModuleSlot {
@@ -59,12 +182,172 @@ impl Display for ModuleSlot {
     }
 }
 
+/// The link between the controller and a module, the module drivers are generic over this trait so they can run on anything that can exchange frames with a module.
+/// A driver uses the transport given to its `with_transport`, without one it opens the transport of its slot with [ModuleTransport::open] during initialization.
+pub trait ModuleTransport: Send + Sized + 'static {
+    /// Opens the transport belonging to a module slot, used when no transport was attached to a module before it gets initialized.
+    fn open(paths: &PlatformPaths, slot: ModuleSlot) -> io::Result<Self>;
+
+    /// Full duplex transfer of a frame, `tx` is clocked out to the module while the response of the module is clocked into `rx`.
+    fn transfer(&mut self, tx: &[u8], rx: &mut [u8]) -> io::Result<()>;
+
+    /// Sends a frame to the module, discarding whatever the module sends back.
+    fn write(&mut self, tx: &[u8]) -> io::Result<()> {
+        let mut rx = vec![0u8; tx.len()];
+        self.transfer(tx, &mut rx)
+    }
+}
+
+/// The default transport, the spidev device of a module slot on a Moduline controller.
+#[derive(Debug)]
+pub struct SpidevTransport {
+    spidev: Spidev,
+}
+
+impl SpidevTransport {
+    /// Wrap an already opened and configured spidev
+    pub fn new(spidev: Spidev) -> SpidevTransport {
+        SpidevTransport { spidev }
+    }
+}
+
+impl ModuleTransport for SpidevTransport {
+    fn open(paths: &PlatformPaths, slot: ModuleSlot) -> io::Result<Self> {
+        Ok(SpidevTransport { spidev: MainBoard::create_spi(&paths.spidevs[slot as usize])? })
+    }
+
+    fn transfer(&mut self, tx: &[u8], rx: &mut [u8]) -> io::Result<()> {
+        let mut transfer = spidev::SpidevTransfer::read_write(tx, rx);
+        self.spidev.transfer(&mut transfer)
+    }
+
+    fn write(&mut self, tx: &[u8]) -> io::Result<()> {
+        let mut transfer = spidev::SpidevTransfer::write(tx);
+        self.spidev.transfer(&mut transfer)
+    }
+}
+
+/// Locks the transport of a module, a poisoned lock is reported as an error instead of a panic.
+pub fn lock_transport<T: ModuleTransport>(transport: &Mutex<T>) -> io::Result<MutexGuard<'_, T>> {
+    transport.lock().map_err(|_| io::Error::other("module transport lock poisoned"))
+}
+
+/// The transport of a module driver, empty until one is attached or opened for the slot of the module
+#[derive(Debug)]
+pub(crate) struct ModuleLink<T: ModuleTransport> {
+    transport: Option<Arc<Mutex<T>>>,
+}
+
+impl<T: ModuleTransport> ModuleLink<T> {
+    pub(crate) const fn new() -> ModuleLink<T> {
+        ModuleLink { transport: None }
+    }
+
+    pub(crate) fn attach(&mut self, transport: T) {
+        self.transport = Some(Arc::new(Mutex::new(transport)));
+    }
+
+    pub(crate) fn get(&self) -> error::Result<&Arc<Mutex<T>>> {
+        self.transport.as_ref().ok_or(PlatformError::NotInitialized)
+    }
+
+    /// Opens the transport of `slot` if none was attached yet
+    pub(crate) fn open(&mut self, paths: &PlatformPaths, slot: ModuleSlot) -> io::Result<()> {
+        if self.transport.is_none() {
+            self.attach(T::open(paths, slot)?);
+        }
+        Ok(())
+    }
+
+    pub(crate) fn transfer(&self, tx: &[u8], rx: &mut [u8]) -> io::Result<()> {
+        lock_transport(self.get()?)?.transfer(tx, rx)
+    }
+
+    /// Sends the configuration messages of a module in order, `false` without a transport
+    pub(crate) fn send_configuration(&self, configuration: &[ModuleFrame]) -> error::Result<bool> {
+        let Some(transport) = &self.transport else {
+            return Ok(false);
+        };
+        for (index, frame) in configuration.iter().enumerate() {
+            if index > 0 {
+                std::thread::sleep(std::time::Duration::from_micros(500));
+            }
+            MainBoard::send_module_frame(transport, frame)?;
+        }
+        Ok(true)
+    }
+
+    /// Sends the changed configuration messages of a module and exchanges `confirmation` right after them, the answer of the module is returned.
+    /// None without a transport
+    pub(crate) fn reconfigure(&self, configuration: &[ModuleFrame], confirmation: &ModuleFrame) -> error::Result<Option<ModuleFrame>> {
+        if !self.send_configuration(configuration)? {
+            return Ok(None);
+        }
+        std::thread::sleep(std::time::Duration::from_micros(500));
+        MainBoard::exchange_module_frame(self.get()?, confirmation).map(Some)
+    }
+}
+
 /// The trait for modules, if you have designed your own module and wish to use it, implement this trait for it and it will be accepted.
-pub trait GOcontrollModule: Send + Sync {
+///
+/// The drivers in this crate can change the configuration of a channel at runtime with their `reconfigure_channel`.
+/// A module with a transport gets the new configuration messages right away, followed by a data message the module has to answer with a valid message.
+/// The driver only takes the new configuration after that answer, without a transport the configuration messages are sent by [GOcontrollModule::put_configuration].
+/// The firmware has no documented answer that reports the configuration back, so the answer shows the module took the messages and is still running, not which values it applied.
+/// The data message of an output module switches the reconfigured channel off, an input module is read and a reconfigured pulse counter starts from the value the module reports.
+///
+/// A boxed driver, like the ones created by the [ModuleRegistry](super::registry::ModuleRegistry), gets its own type back with [downcast_ref](#method.downcast_ref),
+/// [downcast_mut](#method.downcast_mut) or [downcast](#method.downcast).
+pub trait GOcontrollModule: Any + Send + Sync {
     /// Initializes the module.
-    fn put_configuration(&mut self, mainboard: &mut MainBoard) -> io::Result<()>;
+    fn put_configuration(&mut self, mainboard: &mut MainBoard) -> error::Result<()>;
 
     fn get_slot(&self) -> ModuleSlot;
 
-    fn get_spidev(&self) -> Arc<Mutex<Spidev>>;
-}
\ No newline at end of file
+    /// The module id in the header of the messages of the module, which is also how the module identifies itself to [MainBoard::discover_modules]
+    fn module_id(&self) -> u8;
+
+    /// The firmware versions the driver can talk to, checked against the firmware of the module before it gets configured
+    fn supported_firmware(&self) -> RangeInclusive<FirmwareVersion> {
+        FirmwareVersion::new(0,0,0)..=FirmwareVersion::new(255,255,255)
+    }
+
+    /// Opens the transport of the module if none was attached to it yet.
+    fn open_transport(&mut self, paths: &PlatformPaths) -> io::Result<()>;
+
+    /// Full duplex transfer of a frame over the transport of the module.
+    fn transfer(&self, tx: &[u8], rx: &mut [u8]) -> io::Result<()>;
+
+    /// Called by [MainBoard::escape_module_bootloader] when the module answered from its bootloader, so the firmware it starts next counts from 0 again.
+    fn firmware_started(&self) {}
+}
+
+impl std::fmt::Debug for dyn GOcontrollModule {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("GOcontrollModule").field("slot", &self.get_slot()).field("module_id", &self.module_id()).finish()
+    }
+}
+
+impl dyn GOcontrollModule {
+    pub fn is<M: GOcontrollModule>(&self) -> bool {
+        (self as &dyn Any).is::<M>()
+    }
+
+    /// The driver as `M`, None when it is another driver
+    pub fn downcast_ref<M: GOcontrollModule>(&self) -> Option<&M> {
+        (self as &dyn Any).downcast_ref()
+    }
+
+    pub fn downcast_mut<M: GOcontrollModule>(&mut self) -> Option<&mut M> {
+        (self as &mut dyn Any).downcast_mut()
+    }
+
+    /// Take the driver out of the box as `M`, the box is given back when it holds another driver
+    pub fn downcast<M: GOcontrollModule>(self: Box<Self>) -> Result<Box<M>, Box<dyn GOcontrollModule>> {
+        if self.is::<M>() {
+            Ok((self as Box<dyn Any>).downcast().unwrap_or_else(|_| unreachable!("the type was checked")))
+        } else {
+            Err(self)
+        }
+    }
+}
@@ -0,0 +1,276 @@
+use std::fmt::Debug;
+use std::io;
+use std::marker::PhantomData;
+use std::ops::RangeInclusive;
+use std::sync::Mutex;
+
+use super::{module::{GOcontrollModule,ModuleSlot,ModuleTransport,SpidevTransport,ModuleLink,MessageType,CommunicationDirection,FirmwareVersion},
+    frame::ModuleFrame,
+    mainboard::{MainBoard,PlatformPaths},
+    error::{self,PlatformError}};
+
+#[allow(unused)]
+#[repr(u8)]
+#[derive(Debug,Copy,Clone,PartialEq,Eq)]
+pub enum HalfBridgeState {
+    Float = 0,
+    Low = 1,
+    High = 2,
+}
+
+#[allow(unused)]
+#[derive(Debug,Copy,Clone,PartialEq,Eq)]
+/// A command for an output channel, a channel only accepts the commands that fit the function it is configured with:
+/// Off                 -> Switch the channel off, accepted by every channel\
+/// On                  -> Switch the channel on, LowSideSwitch and HighSideSwitch\
+/// Duty                -> Duty cycle in permille from 0 to 1000, LowSideDutyCycle and HighSideDutyCycle\
+/// HalfBridge          -> Drive the output high, low or let it float, HalfBridge\
+/// PeakAndHoldTrigger  -> Start a peak and hold cycle, PeakAndHold\
+/// Frequency           -> Output frequency in Hz, FrequencyOut
+pub enum OutputCommand {
+    Off,
+    On,
+    Duty(u16),
+    HalfBridge(HalfBridgeState),
+    PeakAndHoldTrigger,
+    Frequency(u16),
+}
+
+#[derive(Debug,Copy,Clone,PartialEq,Eq,Default)]
+/// The feedback of a single output channel
+pub struct OutputChannelFeedback {
+    /// The current through the channel in mA
+    pub current: i16,
+}
+
+#[derive(Debug,Copy,Clone,PartialEq,Eq)]
+/// The feedback of an output module with `N` channels
+pub struct OutputFeedback<const N: usize> {
+    /// The temperature of the module in °C
+    pub temperature: i16,
+    /// The difference between the ground of the module and the ground of the controller in mV
+    pub ground_shift: u16,
+    pub channels: [OutputChannelFeedback;N],
+    /// The fault codes as the module reports them, this crate doesn't decode them as their layout isn't confirmed by the module firmware
+    pub fault_codes: u32,
+    /// The response of the module had an incorrect checksum
+    pub checksum_error: bool,
+    /// The feedback wasn't refreshed by the last exchange and holds the last valid feedback, or zeroes if there never was one
+    pub stale: bool,
+}
+
+impl<const N: usize> OutputFeedback<N> {
+    const fn none() -> OutputFeedback<N> {
+        OutputFeedback { temperature: 0, ground_shift: 0, channels: [OutputChannelFeedback { current: 0 };N], fault_codes: 0, checksum_error: false, stale: true }
+    }
+
+    /// The module reports its temperature, ground shift and the current of every channel, followed by the fault codes
+    fn parse(mut response: ModuleFrame) -> OutputFeedback<N> {
+        let temperature = response.get_i16();
+        let ground_shift = response.get_u16();
+        let mut channels = [OutputChannelFeedback::default();N];
+        for channel in channels.iter_mut() {
+            channel.current = response.get_i16();
+        }
+        let fault_codes = response.get_u32();
+        OutputFeedback { temperature, ground_shift, channels, fault_codes, checksum_error: false, stale: false }
+    }
+}
+
+/// What sets the output modules apart, the channel functions and the layout of their messages.
+/// Implemented by the layout types of the modules, [OutputModule6ChLayout](super::outputmodule6ch::OutputModule6ChLayout) and [OutputModule10ChLayout](super::outputmodule10ch::OutputModule10ChLayout)
+pub trait OutputModuleLayout<const N: usize>: Send + Sync + 'static {
+    type Function: Copy + Debug + Send + Sync;
+    type Channel: Copy + Into<usize>;
+    /// The configuration messages of the module, sent in order
+    type Configuration: Copy + Debug + Send + Sync + AsRef<[ModuleFrame]>;
+    /// The pwm frequencies of the module, which the channel configuration refers to
+    type Frequencies: Copy + Debug + Send + Sync;
+
+    /// The module id in the header of the messages of this module
+    const MODULEID: u8;
+    /// The firmware versions using this layout
+    const SUPPORTEDFIRMWARE: RangeInclusive<FirmwareVersion>;
+    const MESSAGELENGTH: usize;
+    /// The distance in bytes between the values of two channels in the data message
+    const OUTPUTSTRIDE: usize;
+
+    /// The value sent to the module for `command` on a channel with `function`, None if the channel can't execute the command
+    fn command_value(function: Option<Self::Function>, command: OutputCommand) -> Option<u16>;
+}
+
+#[allow(unused)]
+#[derive(Debug)]
+/// An output module with `N` channels, the modules are [OutputModule6Ch](super::outputmodule6ch::OutputModule6Ch) and [OutputModule10Ch](super::outputmodule10ch::OutputModule10Ch)
+pub struct OutputModule<L: OutputModuleLayout<N>, const N: usize, T: ModuleTransport = SpidevTransport> {
+    slot: ModuleSlot,
+    configuration: L::Configuration,
+    frequencies: L::Frequencies,
+    functions: [Option<L::Function>;N],
+    outputs: Mutex<[u16;N]>,
+    last_feedback: Mutex<OutputFeedback<N>>,
+    link: ModuleLink<T>,
+    layout: PhantomData<L>,
+}
+
+#[allow(unused)]
+impl<L: OutputModuleLayout<N>, const N: usize, T: ModuleTransport> OutputModule<L,N,T> {
+    /// Create the module from the configuration messages its layout built
+    pub(crate) const fn from_configuration(slot: ModuleSlot, configuration: L::Configuration, frequencies: L::Frequencies, functions: [Option<L::Function>;N]) -> OutputModule<L,N,T> {
+        OutputModule { slot, configuration, frequencies, functions, outputs: Mutex::new([0;N]), last_feedback: Mutex::new(OutputFeedback::none()), link: ModuleLink::new(), layout: PhantomData }
+    }
+
+    /// Change the configuration of the channel at `index` through `put`, which writes it into a copy of the configuration messages, and switch the channel off
+    pub(crate) fn reconfigure(&mut self, index: usize, function: Option<L::Function>, put: impl FnOnce(&mut L::Configuration, L::Frequencies)) -> error::Result<()> {
+        let mut configuration = self.configuration;
+        put(&mut configuration, self.frequencies);
+        let mut values = *self.lock_outputs();
+        values[index] = 0;
+        let response = self.link.reconfigure(configuration.as_ref(), &Self::outputs_request(values))?;
+        self.configuration = configuration;
+        self.functions[index] = function;
+        match response {
+            Some(response) => {
+                self.feedback(Ok(response), values, &mut self.lock_outputs())?;
+            },
+            None => self.lock_outputs()[index] = 0,
+        }
+        Ok(())
+    }
+
+    /// Attach a transport to the module, see [ModuleTransport]
+    pub fn with_transport(mut self, transport: T) -> OutputModule<L,N,T> {
+        self.link.attach(transport);
+        self
+    }
+
+    /// Execute `command` on a single channel, the other channels keep their last command
+    pub fn set_output(&self, channel: L::Channel, command: OutputCommand) -> error::Result<OutputFeedback<N>> {
+        self.exchange_outputs(|mut outputs| {
+            outputs[channel.into()] = self.command_value(channel.into(), command)?;
+            Ok(outputs)
+        })
+    }
+
+    #[cfg(feature="async")]
+    /// Execute `command` on a single channel on the blocking thread pool of the tokio runtime
+    pub async fn set_output_async(&self, channel: L::Channel, command: OutputCommand) -> error::Result<OutputFeedback<N>> {
+        self.exchange_outputs_async(|mut outputs| {
+            outputs[channel.into()] = self.command_value(channel.into(), command)?;
+            Ok(outputs)
+        }).await
+    }
+
+    /// Execute a command on every channel, nothing is sent if one of the commands doesn't fit its channel
+    pub fn set_outputs(&self, commands: [OutputCommand;N]) -> error::Result<OutputFeedback<N>> {
+        self.exchange_outputs(|_| self.command_values(commands))
+    }
+
+    #[cfg(feature="async")]
+    /// Execute a command on every channel on the blocking thread pool of the tokio runtime
+    pub async fn set_outputs_async(&self, commands: [OutputCommand;N]) -> error::Result<OutputFeedback<N>> {
+        self.exchange_outputs_async(|_| self.command_values(commands)).await
+    }
+
+    /// Send the values of all channels as they are and get the feedback
+    pub(crate) fn exchange_values(&self, values: [u16;N]) -> error::Result<OutputFeedback<N>> {
+        self.exchange_outputs(|_| Ok(values))
+    }
+
+    #[cfg(feature="async")]
+    pub(crate) async fn exchange_values_async(&self, values: [u16;N]) -> error::Result<OutputFeedback<N>> {
+        self.exchange_outputs_async(|_| Ok(values)).await
+    }
+
+    /// Send the values `update` makes of a copy of the values of the channels and get the feedback.
+    /// The copy only replaces the values once the message went out, the values are locked meanwhile so no other update gets in between
+    fn exchange_outputs(&self, update: impl FnOnce([u16;N]) -> error::Result<[u16;N]>) -> error::Result<OutputFeedback<N>> {
+        let mut outputs = self.lock_outputs();
+        let values = update(*outputs)?;
+        let response = MainBoard::exchange_module_frame(self.link.get()?, &Self::outputs_request(values));
+        self.feedback(response, values, &mut outputs)
+    }
+
+    #[cfg(feature="async")]
+    /// Like [OutputModule::exchange_outputs] with the exchange on the blocking thread pool of the tokio runtime, the values aren't locked during the exchange
+    async fn exchange_outputs_async(&self, update: impl FnOnce([u16;N]) -> error::Result<[u16;N]>) -> error::Result<OutputFeedback<N>> {
+        let values = update(*self.lock_outputs())?;
+        let transport = self.link.get()?.clone();
+        let request = Self::outputs_request(values);
+        let response = MainBoard::run_blocking(move || Ok(MainBoard::exchange_module_frame(&transport, &request))).await?;
+        self.feedback(response, values, &mut self.lock_outputs())
+    }
+
+    fn command_value(&self, channel: usize, command: OutputCommand) -> error::Result<u16> {
+        L::command_value(self.functions[channel], command).ok_or(PlatformError::InvalidOutputCommand { channel, command })
+    }
+
+    fn command_values(&self, commands: [OutputCommand;N]) -> error::Result<[u16;N]> {
+        let mut values = [0;N];
+        for (channel, command) in commands.into_iter().enumerate() {
+            values[channel] = self.command_value(channel, command)?;
+        }
+        Ok(values)
+    }
+
+    fn lock_outputs(&self) -> std::sync::MutexGuard<'_, [u16;N]> {
+        self.outputs.lock().unwrap_or_else(|poisoned| poisoned.into_inner())
+    }
+
+    fn outputs_request(channels: [u16;N]) -> ModuleFrame {
+        let mut request = ModuleFrame::new(1, CommunicationDirection::ToModule, L::MODULEID, MessageType::Data, 1, L::MESSAGELENGTH);
+        for (channel, value) in channels.into_iter().enumerate() {
+            request.seek(channel*L::OUTPUTSTRIDE).put_u16(value);
+        }
+        request
+    }
+
+    /// Take the response to the message carrying `values`, which replace `outputs` when the message went out.
+    /// A response with an incorrect checksum still went through the transfer, so the module got the message
+    fn feedback(&self, response: error::Result<ModuleFrame>, values: [u16;N], outputs: &mut [u16;N]) -> error::Result<OutputFeedback<N>> {
+        let mut last_feedback = self.last_feedback.lock().unwrap_or_else(|poisoned| poisoned.into_inner());
+        match response {
+            Ok(response) => {
+                *outputs = values;
+                *last_feedback = OutputFeedback::parse(response);
+                Ok(*last_feedback)
+            },
+            Err(PlatformError::ChecksumMismatch { .. }) => {
+                *outputs = values;
+                Ok(OutputFeedback { checksum_error: true, stale: true, ..*last_feedback })
+            },
+            Err(err) => Err(err),
+        }
+    }
+}
+
+impl<L: OutputModuleLayout<N>, const N: usize, T: ModuleTransport> GOcontrollModule for OutputModule<L,N,T> {
+    fn put_configuration(&mut self, mainboard: &mut MainBoard) -> error::Result<()> {
+        mainboard.check_module(self)?;
+
+        self.open_transport(mainboard.paths())?;
+
+        self.link.send_configuration(self.configuration.as_ref())?;
+        Ok(())
+    }
+
+    fn get_slot(&self) -> ModuleSlot {
+        self.slot
+    }
+
+    fn module_id(&self) -> u8 {
+        L::MODULEID
+    }
+
+    fn supported_firmware(&self) -> RangeInclusive<FirmwareVersion> {
+        L::SUPPORTEDFIRMWARE
+    }
+
+    fn open_transport(&mut self, paths: &PlatformPaths) -> io::Result<()> {
+        self.link.open(paths, self.slot)
+    }
+
+    fn transfer(&self, tx: &[u8], rx: &mut [u8]) -> io::Result<()> {
+        self.link.transfer(tx, rx)
+    }
+}
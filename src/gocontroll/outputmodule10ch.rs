@@ -0,0 +1,188 @@
+use std::ops::RangeInclusive;
+#[cfg(feature = "serde")]
+use serde::{Serialize,Deserialize};
+
+use super::{module::{ModuleSlot,ModuleTransport,SpidevTransport,MessageType,CommunicationDirection,FirmwareVersion},
+    frame::ModuleFrame,
+    outputmodule::{OutputModule,OutputModuleLayout,OutputFeedback,OutputCommand},
+    error};
+
+#[allow(unused)]
+#[repr(u8)]
+#[derive(Debug,Copy, Clone)]
+#[cfg_attr(feature = "serde", derive(Serialize,Deserialize))]
+pub enum OutputModule10ChFunction {
+    None = 1,
+    LowSideDutyCycle = 3,
+    HighSideDutyCycle = 4,
+    LowSideSwitch = 5,
+    HighSideSwitch = 6,
+    FrequencyOut = 8,
+}
+
+impl OutputModule10ChFunction {
+    /// The value sent to the module for `command` on a channel with this function, None if the function can't execute the command
+    pub const fn command_value(self, command: OutputCommand) -> Option<u16> {
+        match (self, command) {
+            (_, OutputCommand::Off) => Some(0),
+            (OutputModule10ChFunction::LowSideSwitch | OutputModule10ChFunction::HighSideSwitch, OutputCommand::On) => Some(1),
+            (OutputModule10ChFunction::LowSideDutyCycle | OutputModule10ChFunction::HighSideDutyCycle, OutputCommand::Duty(duty)) if duty <= 1000 => Some(duty),
+            (OutputModule10ChFunction::FrequencyOut, OutputCommand::Frequency(frequency)) => Some(frequency),
+            _ => None,
+        }
+    }
+}
+
+#[allow(unused)]
+#[repr(u8)]
+#[derive(Debug,Copy,Clone,PartialEq,Eq)]
+pub enum OutputModuleChannel {
+    Channel1 = 0u8,
+    Channel2 = 1u8,
+    Channel3 = 2u8,
+    Channel4 = 3u8,
+    Channel5 = 4u8,
+    Channel6 = 5u8,
+    Channel7 = 6u8,
+    Channel8 = 7u8,
+    Channel9 = 8u8,
+    Channel10 = 9u8,
+}
+
+#[allow(unused)]
+#[repr(u8)]
+#[derive(Debug,Copy, Clone)]
+#[cfg_attr(feature = "serde", derive(Serialize,Deserialize))]
+pub enum OutputModule10ChFrequency {
+    Freq100Hz = 1,
+    Freq200Hz = 2,
+    Freq500Hz = 3,
+    Freq1KHz = 4,
+    Freq2KHz = 5,
+    Freq5KHz = 6,
+    Freq10KHz = 7,
+}
+
+#[allow(unused)]
+#[derive(Debug,Copy, Clone)]
+#[cfg_attr(feature = "serde", derive(Serialize,Deserialize))]
+pub struct OutputModule10ChConfig {
+    function: OutputModule10ChFunction,
+    #[cfg_attr(feature = "serde", serde(default, skip_serializing_if = "Option::is_none"))]
+    max_current: Option<u16>,
+}
+
+#[allow(unused)]
+impl OutputModule10ChConfig {
+    /// `max_current` is the current limit of the channel in mA, None leaves the channel unlimited
+    pub const fn new(function: OutputModule10ChFunction, max_current: Option<u16>) -> OutputModule10ChConfig {
+        OutputModule10ChConfig {function, max_current}
+    }
+}
+
+#[allow(unused)]
+#[derive(Debug,Copy, Clone)]
+#[cfg_attr(feature = "serde", derive(Serialize,Deserialize), serde(transparent))]
+/// The pwm frequencies of the module, each frequency is shared by a pair of channels
+pub struct OutputModule10ChFrequencyConfig {
+    frequencies: [OutputModule10ChFrequency;5],
+}
+
+#[allow(unused)]
+impl OutputModule10ChFrequencyConfig {
+    pub const fn new(channel1_2: OutputModule10ChFrequency, channel3_4: OutputModule10ChFrequency, channel5_6: OutputModule10ChFrequency, channel7_8: OutputModule10ChFrequency, channel9_10: OutputModule10ChFrequency) -> OutputModule10ChFrequencyConfig {
+        OutputModule10ChFrequencyConfig {frequencies: [channel1_2,channel3_4,channel5_6,channel7_8,channel9_10]}
+    }
+}
+
+impl From<OutputModuleChannel> for usize {
+    fn from(channel: OutputModuleChannel) -> usize {
+        channel as usize
+    }
+}
+
+/// The module id in the header of the messages of this module, unverified like the rest of the layout, see [OutputModule10ChLayout]
+pub const MODULEID:u8 =23;
+/// The firmware versions using the message layout of this driver
+const SUPPORTEDFIRMWARE: RangeInclusive<FirmwareVersion> = FirmwareVersion::new(0,0,0)..=FirmwareVersion::new(1,255,255);
+const MESSAGELENGTH:usize = 50;
+
+#[derive(Debug,Copy,Clone)]
+/// The channel functions and message layout of the 10 channel output module.
+///
+/// Unverified: the module id, the message length and the positions below follow the 6 channel output module,
+/// they have not been checked against the module firmware or a message recorded from a real module.
+/// The configuration message holds the function and frequency of channel n in byte n and its current limit as u16 in bytes 10+2n, 0xffff for no limit.
+/// The data message holds the value of channel n as u16 in bytes 2n.
+pub struct OutputModule10ChLayout;
+
+impl OutputModuleLayout<10> for OutputModule10ChLayout {
+    type Function = OutputModule10ChFunction;
+    type Channel = OutputModuleChannel;
+    type Configuration = [ModuleFrame;1];
+    type Frequencies = OutputModule10ChFrequencyConfig;
+
+    const MODULEID: u8 = MODULEID;
+    const SUPPORTEDFIRMWARE: RangeInclusive<FirmwareVersion> = SUPPORTEDFIRMWARE;
+    const MESSAGELENGTH: usize = MESSAGELENGTH;
+    const OUTPUTSTRIDE: usize = 2;
+
+    fn command_value(function: Option<OutputModule10ChFunction>, command: OutputCommand) -> Option<u16> {
+        function.unwrap_or(OutputModule10ChFunction::None).command_value(command)
+    }
+}
+
+pub type OutputModule10Ch<T = SpidevTransport> = OutputModule<OutputModule10ChLayout, 10, T>;
+pub type OutputModule10ChFeedback = OutputFeedback<10>;
+
+#[allow(unused)]
+impl<T: ModuleTransport> OutputModule<OutputModule10ChLayout, 10, T> {
+    /// Create a new 10 channel output module object, the message layout of this driver is unverified, see [OutputModule10ChLayout]
+    pub const fn new(slot: ModuleSlot, channels: [Option<OutputModule10ChConfig>;10], frequency_channels: OutputModule10ChFrequencyConfig) -> OutputModule10Ch<T> {
+        let mut configuration = ModuleFrame::new(1, CommunicationDirection::ToModule, MODULEID, MessageType::Configuration, 1, MESSAGELENGTH);
+        let mut functions = [None;10];
+        let mut index = 0;
+        while index < 10 {
+            if let Some(config) = channels[index] {
+                functions[index] = Some(config.function);
+            }
+            Self::put_channel(&mut configuration, index, channels[index], frequency_channels);
+            index += 1;
+        }
+        OutputModule::from_configuration(slot, [configuration], frequency_channels, functions)
+    }
+
+    /// Write the configuration of a channel into the configuration message, the bytes of an unused channel are cleared
+    const fn put_channel(configuration: &mut ModuleFrame, index: usize, channel: Option<OutputModule10ChConfig>, frequency_channels: OutputModule10ChFrequencyConfig) {
+        configuration.seek(index).put_u8(0);
+        configuration.seek(10+index*2).put_u16(0);
+        if let Some(config) = channel {
+            configuration.seek(index).put_u8((config.function as u8) << 4 | frequency_channels.frequencies[index/2] as u8);
+            configuration.seek(10+index*2).put_u16(match config.max_current {
+                Some(max) => max,
+                None => 0xffff,
+            });
+        }
+    }
+
+    /// Change the configuration of a channel at runtime, None makes the channel unused, the channel is switched off.
+    /// See [GOcontrollModule](super::module::GOcontrollModule) for how the module gets it
+    pub fn reconfigure_channel(&mut self, channel: OutputModuleChannel, config: Option<OutputModule10ChConfig>) -> error::Result<()> {
+        let index = channel as usize;
+        self.reconfigure(index, config.map(|config| config.function), |[configuration], frequencies| {
+            Self::put_channel(configuration, index, config, frequencies);
+        })
+    }
+
+    /// Set the duty cycle or state of all channels and get the feedback of the module, a response with an incorrect checksum gives the last valid feedback marked as stale.
+    /// The values are sent to the module as they are, use [OutputModule::set_outputs] to have them checked against the function of the channels
+    pub fn set_outputs_get_feedback(&self, channels: [u16;10]) -> error::Result<OutputModule10ChFeedback> {
+        self.exchange_values(channels)
+    }
+
+    #[cfg(feature="async")]
+    /// Set the outputs and get the feedback of all channels on the blocking thread pool of the tokio runtime
+    pub async fn set_outputs_get_feedback_async(&self, channels: [u16;10]) -> error::Result<OutputModule10ChFeedback> {
+        self.exchange_values_async(channels).await
+    }
+}
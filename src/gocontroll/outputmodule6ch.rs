@@ -1,14 +1,18 @@
-use std::io;
-use std::sync::{Arc,Mutex};
+use std::ops::RangeInclusive;
+#[cfg(feature = "serde")]
+use serde::{Serialize,Deserialize};
 
-use spidev::Spidev;
+use super::{module::{ModuleSlot,ModuleTransport,SpidevTransport,MessageType,CommunicationDirection,FirmwareVersion},
+    frame::ModuleFrame,
+    outputmodule::{OutputModule,OutputModuleLayout,OutputFeedback},
+    error::{self,ConfigError}};
 
-use super::{module::{GOcontrollModule,ModuleSlot,MessageType,CommunicationDirection},
-    mainboard::MainBoard};
+pub use super::outputmodule::{OutputCommand,HalfBridgeState,OutputChannelFeedback};
 
     #[allow(unused)]
 #[repr(u8)]
 #[derive(Debug,Copy, Clone)]
+#[cfg_attr(feature = "serde", derive(Serialize,Deserialize))]
 pub enum OutputModule6ChFunction {
     None = 1,
     HalfBridge = 2,
@@ -23,6 +27,7 @@ pub enum OutputModule6ChFunction {
 #[allow(unused)]
 #[repr(u8)]
 #[derive(Debug,Copy, Clone)]
+#[cfg_attr(feature = "serde", derive(Serialize,Deserialize))]
 pub enum OutputModule6ChFrequency {
     Freq100Hz = 1,
     Freq200Hz = 2,
@@ -33,8 +38,36 @@ pub enum OutputModule6ChFrequency {
     Freq10KHz = 7,
 }
 
+#[allow(unused)]
+#[repr(u8)]
+#[derive(Debug,Copy,Clone,PartialEq,Eq)]
+pub enum OutputModuleChannel {
+    Channel1 = 0u8,
+    Channel2 = 1u8,
+    Channel3 = 2u8,
+    Channel4 = 3u8,
+    Channel5 = 4u8,
+    Channel6 = 5u8,
+}
+
+impl OutputModule6ChFunction {
+    /// The value sent to the module for `command` on a channel with this function, None if the function can't execute the command
+    pub const fn command_value(self, command: OutputCommand) -> Option<u16> {
+        match (self, command) {
+            (_, OutputCommand::Off) => Some(0),
+            (OutputModule6ChFunction::LowSideSwitch | OutputModule6ChFunction::HighSideSwitch, OutputCommand::On) => Some(1),
+            (OutputModule6ChFunction::LowSideDutyCycle | OutputModule6ChFunction::HighSideDutyCycle, OutputCommand::Duty(duty)) if duty <= 1000 => Some(duty),
+            (OutputModule6ChFunction::HalfBridge, OutputCommand::HalfBridge(state)) => Some(state as u16),
+            (OutputModule6ChFunction::PeakAndHold, OutputCommand::PeakAndHoldTrigger) => Some(1),
+            (OutputModule6ChFunction::FrequencyOut, OutputCommand::Frequency(frequency)) => Some(frequency),
+            _ => None,
+        }
+    }
+}
+
 #[allow(unused)]
 #[derive(Debug,Copy, Clone)]
+#[cfg_attr(feature = "serde", derive(Serialize,Deserialize), serde(try_from = "OutputModule6ChConfigBuilder", into = "OutputModule6ChConfigBuilder"))]
 pub struct OutputModule6ChConfig {
     function: OutputModule6ChFunction,
     peak_current: Option<u16>,
@@ -44,13 +77,97 @@ pub struct OutputModule6ChConfig {
 
 #[allow(unused)]
 impl OutputModule6ChConfig {
+    /// Create a channel configuration without any checks, [OutputModule6ChConfig::builder] rejects peak settings on a channel that isn't PeakAndHold
     pub const fn new(function: OutputModule6ChFunction, max_current: Option<u16>, peak_current: Option<u16>, peak_time: Option<u16>)-> OutputModule6ChConfig {
         OutputModule6ChConfig {function, max_current, peak_current, peak_time}
     }
+
+    /// Start a channel configuration with `function` without a current limit
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// # use gocontroll_platform::gocontroll::outputmodule6ch::*;
+    /// const VALVE: OutputModule6ChConfig = match OutputModule6ChConfig::builder(OutputModule6ChFunction::PeakAndHold)
+    ///     .max_current(2000)
+    ///     .peak_current(4000)
+    ///     .peak_time(30)
+    ///     .build() {
+    ///     Ok(config) => config,
+    ///     Err(_) => panic!("invalid valve configuration"),
+    /// };
+    /// assert!(OutputModule6ChConfig::builder(OutputModule6ChFunction::HighSideSwitch).peak_current(4000).build().is_err());
+    /// ```
+    pub const fn builder(function: OutputModule6ChFunction) -> OutputModule6ChConfigBuilder {
+        OutputModule6ChConfigBuilder { function, max_current: None, peak_current: None, peak_time: None }
+    }
+}
+
+#[derive(Debug,Copy, Clone)]
+#[cfg_attr(feature = "serde", derive(Serialize,Deserialize))]
+/// Builds an [OutputModule6ChConfig], checking the settings against the function of the channel.
+/// A deserialized [OutputModule6ChConfig] goes through the builder, the settings the function doesn't use can be left out.
+pub struct OutputModule6ChConfigBuilder {
+    function: OutputModule6ChFunction,
+    #[cfg_attr(feature = "serde", serde(default, skip_serializing_if = "Option::is_none"))]
+    max_current: Option<u16>,
+    #[cfg_attr(feature = "serde", serde(default, skip_serializing_if = "Option::is_none"))]
+    peak_current: Option<u16>,
+    #[cfg_attr(feature = "serde", serde(default, skip_serializing_if = "Option::is_none"))]
+    peak_time: Option<u16>,
+}
+
+#[allow(unused)]
+impl OutputModule6ChConfigBuilder {
+    /// The current limit of the channel in mA
+    pub const fn max_current(mut self, max_current: u16) -> OutputModule6ChConfigBuilder {
+        self.max_current = Some(max_current);
+        self
+    }
+
+    /// The current of the peak phase in mA, only for PeakAndHold which needs it
+    pub const fn peak_current(mut self, peak_current: u16) -> OutputModule6ChConfigBuilder {
+        self.peak_current = Some(peak_current);
+        self
+    }
+
+    /// The duration of the peak phase, only for PeakAndHold which needs it
+    pub const fn peak_time(mut self, peak_time: u16) -> OutputModule6ChConfigBuilder {
+        self.peak_time = Some(peak_time);
+        self
+    }
+
+    pub const fn build(self) -> Result<OutputModule6ChConfig, ConfigError> {
+        let config = OutputModule6ChConfig::new(self.function, self.max_current, self.peak_current, self.peak_time);
+        match (config.function, config.peak_current, config.peak_time) {
+            (OutputModule6ChFunction::PeakAndHold, None, _) => Err(ConfigError::MissingSetting("peak_current")),
+            (OutputModule6ChFunction::PeakAndHold, _, None) => Err(ConfigError::MissingSetting("peak_time")),
+            (OutputModule6ChFunction::PeakAndHold, _, _) => Ok(config),
+            (_, Some(_), _) => Err(ConfigError::NotApplicable("peak_current")),
+            (_, _, Some(_)) => Err(ConfigError::NotApplicable("peak_time")),
+            (OutputModule6ChFunction::None, _, _) if config.max_current.is_some() => Err(ConfigError::NotApplicable("max_current")),
+            _ => Ok(config),
+        }
+    }
+}
+
+impl TryFrom<OutputModule6ChConfigBuilder> for OutputModule6ChConfig {
+    type Error = ConfigError;
+
+    fn try_from(builder: OutputModule6ChConfigBuilder) -> Result<OutputModule6ChConfig, ConfigError> {
+        builder.build()
+    }
+}
+
+impl From<OutputModule6ChConfig> for OutputModule6ChConfigBuilder {
+    fn from(config: OutputModule6ChConfig) -> OutputModule6ChConfigBuilder {
+        OutputModule6ChConfigBuilder { function: config.function, max_current: config.max_current, peak_current: config.peak_current, peak_time: config.peak_time }
+    }
 }
 
 #[allow(unused)]
 #[derive(Debug,Copy, Clone)]
+#[cfg_attr(feature = "serde", derive(Serialize,Deserialize), serde(transparent))]
 pub struct OutputModule6ChFrequecyConfig {
     frequencies: [OutputModule6ChFrequency;3],
 }
@@ -62,163 +179,99 @@ impl OutputModule6ChFrequecyConfig {
     }
 }
 
-#[allow(unused)]
-pub struct OutputModule6ChFeedback {
-    pub temperature: i16,
-    pub groundshift: u16,
-    pub channel1_current: i16,
-    pub channel2_current: i16,
-    pub channel3_current: i16,
-    pub channel4_current: i16,
-    pub channel5_current: i16,
-    pub channel6_current: i16,
-    pub fault_codes: u32,
+impl From<OutputModuleChannel> for usize {
+    fn from(channel: OutputModuleChannel) -> usize {
+        channel as usize
+    }
 }
 
-const MODULEID:u8 =22;
+/// The module id in the header of the messages of this module
+pub const MODULEID:u8 =22;
+/// The firmware versions using the message layout of this driver
+const SUPPORTEDFIRMWARE: RangeInclusive<FirmwareVersion> = FirmwareVersion::new(0,0,0)..=FirmwareVersion::new(1,255,255);
 const MESSAGELENGTH:usize = 44;
 
-#[allow(unused)]
-#[derive(Debug)]
-pub struct OutputModule6Ch {
-    slot: ModuleSlot,
-    tx_data: [u8;50],
-    tx_data_2: [u8;50],
-    rx_data: [u8;50],
-    spidev: Option<Arc<Mutex<Spidev>>>,
+#[derive(Debug,Copy,Clone)]
+/// The channel functions and message layout of the 6 channel output module
+pub struct OutputModule6ChLayout;
+
+impl OutputModuleLayout<6> for OutputModule6ChLayout {
+    type Function = OutputModule6ChFunction;
+    type Channel = OutputModuleChannel;
+    type Configuration = [ModuleFrame;2];
+    type Frequencies = OutputModule6ChFrequecyConfig;
+
+    const MODULEID: u8 = MODULEID;
+    const SUPPORTEDFIRMWARE: RangeInclusive<FirmwareVersion> = SUPPORTEDFIRMWARE;
+    const MESSAGELENGTH: usize = MESSAGELENGTH;
+    const OUTPUTSTRIDE: usize = 6;
+
+    fn command_value(function: Option<OutputModule6ChFunction>, command: OutputCommand) -> Option<u16> {
+        function.unwrap_or(OutputModule6ChFunction::None).command_value(command)
+    }
 }
 
+pub type OutputModule6Ch<T = SpidevTransport> = OutputModule<OutputModule6ChLayout, 6, T>;
+pub type OutputModule6ChFeedback = OutputFeedback<6>;
+
 #[allow(unused)]
-impl OutputModule6Ch {
-    pub const fn new(slot: ModuleSlot, channels: [Option<OutputModule6ChConfig>;6], frequency_channels: OutputModule6ChFrequecyConfig) -> OutputModule6Ch {
-        let mut tx_data = [0u8;50];
-        let mut tx_data_2 = [0u8;50];
+impl<T: ModuleTransport> OutputModule<OutputModule6ChLayout, 6, T> {
+    pub const fn new(slot: ModuleSlot, channels: [Option<OutputModule6ChConfig>;6], frequency_channels: OutputModule6ChFrequecyConfig) -> OutputModule6Ch<T> {
+        let mut configuration = ModuleFrame::new(1, CommunicationDirection::ToModule, MODULEID, MessageType::Configuration, 1, MESSAGELENGTH);
+        let mut configuration_2 = ModuleFrame::new(1, CommunicationDirection::ToModule, MODULEID, MessageType::Configuration, 2, MESSAGELENGTH);
+        let mut functions = [None;6];
         let mut index = 0;
         while index < 6 {
-            match channels[index] {
-                Some(config) => {
-                    tx_data[index + 6] = {config.function as u8} << 4 | frequency_channels.frequencies[index/2] as u8;
-                    match config.max_current {
-                        Some(max) => {
-                            tx_data[index*2+12] = max as u8;
-                            tx_data[index*2+13] = {max >> 8} as u8;
-                        },
-                        None => {
-                            tx_data[index*2+12] = 255;
-                            tx_data[index*2+13] = 255;
-                        }
-                    }
-                    match config.peak_current {
-                        Some(peak_curr) => {
-                            tx_data_2[index*2+6] = peak_curr as u8;
-                            tx_data_2[index*2+7] = {peak_curr >> 8} as u8;
-                        },
-                        None => {
-                            tx_data_2[index*2+6] = 0;
-                            tx_data_2[index*2+7] = 0;
-                        }
-                    }
-                    match config.peak_time {
-                        Some(peak_t) => {
-                            tx_data_2[index*2+18] = peak_t as u8;
-                            tx_data_2[index*2+19] = {peak_t >> 8} as u8;
-                        },
-                        None => {
-                            tx_data_2[index*2+18] = 0;
-                            tx_data_2[index*2+19] = 0;
-                        }
-                    }
-                    index += 1;
-                },
-                None => {index +=1}
+            if let Some(config) = channels[index] {
+                functions[index] = Some(config.function);
             }
+            Self::put_channel(&mut configuration, &mut configuration_2, index, channels[index], frequency_channels);
+            index += 1;
         }
-        OutputModule6Ch {slot, tx_data, tx_data_2, rx_data: [0u8;50], spidev: None}
-    }
-
-    pub fn set_outputs_get_feedback(&self, channel1: u16, channel2: u16, channel3: u16, channel4: u16, channel5: u16, channel6:u16) -> io::Result<OutputModule6ChFeedback> {
-        let mut feedback: OutputModule6ChFeedback = OutputModule6ChFeedback { temperature: 0, groundshift: 0, channel1_current: 0, channel2_current: 0, channel3_current: 0, channel4_current: 0, channel5_current: 0, channel6_current: 0, fault_codes: 0x10000000 };
-        let mut potential_err: Option<io::Error> = None;
-        let mut tx: [u8;50] = [0;50];
-        let mut rx: [u8;50] = [0;50];
-        tx[6] = channel1 as u8;
-        tx[7] = {channel1 >> 8} as u8;
-        tx[12] = channel2 as u8;
-        tx[13] = {channel2 >> 8} as u8;
-        tx[18] = channel3 as u8;
-        tx[19] = {channel3 >> 8} as u8;
-        tx[24] = channel4 as u8;
-        tx[25] = {channel4 >> 8} as u8;
-        tx[30] = channel5 as u8;
-        tx[31] = {channel5 >> 8} as u8;
-        tx[36] = channel6 as u8;
-        tx[37] = {channel6 >> 8} as u8;
-
-        MainBoard::send_receive_module_spi(
-            self.get_spidev(),
-            1, 
-            CommunicationDirection::ToModule,
-            MODULEID,
-            MessageType::Data,
-            1,
-            &mut tx,
-            &mut rx,
-            MESSAGELENGTH
-        ).is_err_and(|err| if err.kind() == io::ErrorKind::InvalidData {
-            feedback.fault_codes |= 0x20000000;
-            true
-        } else {
-            potential_err = Some(err);
-            false
-        });
-        if let Some(error) = potential_err {
-            return Err(error);
+        OutputModule::from_configuration(slot, [configuration, configuration_2], frequency_channels, functions)
+    }
+
+    /// Write the configuration of a channel into both configuration messages, the bytes of an unused channel are cleared
+    const fn put_channel(configuration: &mut ModuleFrame, configuration_2: &mut ModuleFrame, index: usize, channel: Option<OutputModule6ChConfig>, frequency_channels: OutputModule6ChFrequecyConfig) {
+        configuration.seek(index).put_u8(0);
+        configuration.seek(6+index*2).put_u16(0);
+        configuration_2.seek(index*2).put_u16(0);
+        configuration_2.seek(12+index*2).put_u16(0);
+        if let Some(config) = channel {
+            configuration.seek(index).put_u8((config.function as u8) << 4 | frequency_channels.frequencies[index/2] as u8);
+            configuration.seek(6+index*2).put_u16(match config.max_current {
+                Some(max) => max,
+                None => 0xffff,
+            });
+            configuration_2.seek(index*2).put_u16(match config.peak_current {
+                Some(peak_curr) => peak_curr,
+                None => 0,
+            });
+            configuration_2.seek(12+index*2).put_u16(match config.peak_time {
+                Some(peak_t) => peak_t,
+                None => 0,
+            });
         }
-        feedback.temperature = i16::from_le_bytes(rx[6..7].try_into().unwrap());
-        feedback.groundshift = u16::from_le_bytes(rx[8..9].try_into().unwrap());
-        feedback.fault_codes = u32::from_le_bytes(rx[22..25].try_into().unwrap());
-        feedback.channel1_current = i16::from_le_bytes(rx[10..11].try_into().unwrap());
-        feedback.channel2_current = i16::from_le_bytes(rx[12..13].try_into().unwrap());
-        feedback.channel3_current = i16::from_le_bytes(rx[14..15].try_into().unwrap());
-        feedback.channel4_current = i16::from_le_bytes(rx[16..17].try_into().unwrap());
-        feedback.channel5_current = i16::from_le_bytes(rx[18..19].try_into().unwrap());
-        feedback.channel6_current = i16::from_le_bytes(rx[20..21].try_into().unwrap());
-        Ok(feedback)
     }
-}
 
-impl GOcontrollModule for OutputModule6Ch {
-    fn put_configuration(&mut self, mainboard: &mut MainBoard) -> io::Result<()> {
-        mainboard.check_module(self)?;
-
-        MainBoard::send_module_spi(
-            self.get_spidev(),
-            1,
-            CommunicationDirection::ToModule,
-            MODULEID,
-            MessageType::Configuration,
-            1,
-            &mut self.tx_data,
-            MESSAGELENGTH
-        )?;
-        std::thread::sleep(std::time::Duration::from_micros(500));
-        MainBoard::send_module_spi(
-            self.get_spidev(),
-            1,
-            CommunicationDirection::ToModule,
-            MODULEID,
-            MessageType::Configuration,
-            2,
-            &mut self.tx_data_2,
-            MESSAGELENGTH
-        )        
-    }
-
-    fn get_slot(&self) -> ModuleSlot {
-        self.slot
-    }
-
-    fn get_spidev(&self) -> Arc<Mutex<Spidev>> {
-        self.spidev.as_ref().unwrap().clone()
-    }
-}
\ No newline at end of file
+    /// Change the configuration of a channel at runtime, None makes the channel unused, the channel is switched off.
+    /// See [GOcontrollModule](super::module::GOcontrollModule) for how the module gets it
+    pub fn reconfigure_channel(&mut self, channel: OutputModuleChannel, config: Option<OutputModule6ChConfig>) -> error::Result<()> {
+        let index = channel as usize;
+        self.reconfigure(index, config.map(|config| config.function), |[configuration, configuration_2], frequencies| {
+            Self::put_channel(configuration, configuration_2, index, config, frequencies);
+        })
+    }
+
+    /// Set the outputs and get the feedback of all channels, a response with an incorrect checksum gives the last valid feedback marked as stale.
+    /// The values are sent to the module as they are, use [OutputModule::set_outputs] to have them checked against the function of the channels
+    pub fn set_outputs_get_feedback(&self, channel1: u16, channel2: u16, channel3: u16, channel4: u16, channel5: u16, channel6:u16) -> error::Result<OutputModule6ChFeedback> {
+        self.exchange_values([channel1, channel2, channel3, channel4, channel5, channel6])
+    }
+
+    #[cfg(feature="async")]
+    /// Set the outputs and get the feedback of all channels on the blocking thread pool of the tokio runtime
+    pub async fn set_outputs_get_feedback_async(&self, channel1: u16, channel2: u16, channel3: u16, channel4: u16, channel5: u16, channel6:u16) -> error::Result<OutputModule6ChFeedback> {
+        self.exchange_values_async([channel1, channel2, channel3, channel4, channel5, channel6]).await
+    }
+}
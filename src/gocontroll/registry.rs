@@ -0,0 +1,160 @@
+use super::{module::{GOcontrollModule,ModuleSlot,SpidevTransport},
+    inventory::ModuleInventory,
+    inputmodule6ch::{self,InputModule6Ch,Inputmodule6chSupplyConfig,InputModuleSupply},
+    inputmodule10ch::{self,InputModule10Ch},
+    inputmodule420ma::{self,InputModule420mA},
+    outputmodule6ch::{self,OutputModule6Ch,OutputModule6ChFrequecyConfig,OutputModule6ChFrequency},
+    outputmodule10ch::{self,OutputModule10Ch,OutputModule10ChFrequencyConfig,OutputModule10ChFrequency},
+    error::{self,PlatformError}};
+
+/// Creates the driver of a module in a slot
+pub type ModuleConstructor = fn(ModuleSlot) -> Box<dyn GOcontrollModule>;
+
+#[derive(Debug,Clone,Copy)]
+/// A driver in the [ModuleRegistry], found by the module id of its module or by its name
+pub struct ModuleDriver {
+    pub module_id: u8,
+    pub name: &'static str,
+    pub constructor: ModuleConstructor,
+}
+
+impl ModuleDriver {
+    pub const fn new(module_id: u8, name: &'static str, constructor: ModuleConstructor) -> ModuleDriver {
+        ModuleDriver { module_id, name, constructor }
+    }
+}
+
+/// The drivers of the modules that ship with this crate, they leave all channels unused and the sensor supplies off
+const BUILTIN: [ModuleDriver;5] = [
+    ModuleDriver::new(inputmodule6ch::MODULEID, "InputModule6Ch", |slot| Box::new(InputModule6Ch::<SpidevTransport>::new(slot, [None;6],
+        Inputmodule6chSupplyConfig::new(InputModuleSupply::Off, InputModuleSupply::Off, InputModuleSupply::Off)))),
+    ModuleDriver::new(inputmodule10ch::MODULEID, "InputModule10Ch", |slot| Box::new(InputModule10Ch::<SpidevTransport>::new(slot, [None;10], InputModuleSupply::Off))),
+    ModuleDriver::new(inputmodule420ma::MODULEID, "InputModule420mA", |slot| Box::new(InputModule420mA::<SpidevTransport>::new(slot, [None;6], [InputModuleSupply::Off;3]))),
+    ModuleDriver::new(outputmodule6ch::MODULEID, "OutputModule6Ch", |slot| Box::new(OutputModule6Ch::<SpidevTransport>::new(slot, [None;6],
+        OutputModule6ChFrequecyConfig::new(OutputModule6ChFrequency::Freq1KHz, OutputModule6ChFrequency::Freq1KHz, OutputModule6ChFrequency::Freq1KHz)))),
+    ModuleDriver::new(outputmodule10ch::MODULEID, "OutputModule10Ch", |slot| Box::new(OutputModule10Ch::<SpidevTransport>::new(slot, [None;10],
+        OutputModule10ChFrequencyConfig::new(OutputModule10ChFrequency::Freq1KHz, OutputModule10ChFrequency::Freq1KHz, OutputModule10ChFrequency::Freq1KHz,
+        OutputModule10ChFrequency::Freq1KHz, OutputModule10ChFrequency::Freq1KHz)))),
+];
+
+/// The module drivers the crate can create for a slot, the first driver matching a module id or name wins
+#[derive(Debug,Clone)]
+pub struct ModuleRegistry {
+    drivers: Vec<ModuleDriver>,
+}
+
+impl Default for ModuleRegistry {
+    fn default() -> Self {
+        Self::builtin()
+    }
+}
+
+#[allow(unused)]
+impl ModuleRegistry {
+    /// The drivers of all modules supported by this crate
+    pub fn builtin() -> ModuleRegistry {
+        ModuleRegistry { drivers: BUILTIN.to_vec() }
+    }
+
+    /// A registry without any drivers
+    pub fn empty() -> ModuleRegistry {
+        ModuleRegistry { drivers: Vec::new() }
+    }
+
+    /// Add a driver in front of the registry, so it takes precedence over the drivers already registered with the same module id or name
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// # use gocontroll_platform::gocontroll::{registry::*,module::{GOcontrollModule,ModuleSlot,SpidevTransport},outputmodule6ch::*};
+    /// let mut registry = ModuleRegistry::builtin();
+    /// registry.register(ModuleDriver::new(30, "ValveModule", |slot| Box::new(OutputModule6Ch::<SpidevTransport>::new(slot, [None;6],
+    ///     OutputModule6ChFrequecyConfig::new(OutputModule6ChFrequency::Freq1KHz, OutputModule6ChFrequency::Freq1KHz, OutputModule6ChFrequency::Freq1KHz)))));
+    /// assert_eq!(registry.find(30).unwrap().name, "ValveModule");
+    /// assert_eq!(registry.create_named("InputModule10Ch", ModuleSlot::Moduleslot2).unwrap().module_id(), 12);
+    ///
+    /// let valves = registry.create(30, ModuleSlot::Moduleslot1).unwrap();
+    /// let valves: Box<OutputModule6Ch> = valves.downcast().unwrap();
+    /// assert_eq!(valves.get_slot(), ModuleSlot::Moduleslot1);
+    /// ```
+    pub fn register(&mut self, driver: ModuleDriver) {
+        self.drivers.insert(0, driver);
+    }
+
+    pub fn drivers(&self) -> &[ModuleDriver] {
+        &self.drivers
+    }
+
+    /// Find the driver of the module with `module_id`
+    pub fn find(&self, module_id: u8) -> Option<&ModuleDriver> {
+        self.drivers.iter().find(|driver| driver.module_id == module_id)
+    }
+
+    /// Find the driver registered as `name`
+    pub fn find_named(&self, name: &str) -> Option<&ModuleDriver> {
+        self.drivers.iter().find(|driver| driver.name == name)
+    }
+
+    /// Create the driver of the module with `module_id` for `slot`, [downcast](../module/trait.GOcontrollModule.html#method.downcast) it to use the API of the driver itself
+    pub fn create(&self, module_id: u8, slot: ModuleSlot) -> error::Result<Box<dyn GOcontrollModule>> {
+        self.find(module_id)
+            .map(|driver| (driver.constructor)(slot))
+            .ok_or(PlatformError::UnknownModule { slot, module_id })
+    }
+
+    /// Create the driver registered as `name` for `slot`
+    pub fn create_named(&self, name: &str, slot: ModuleSlot) -> error::Result<Box<dyn GOcontrollModule>> {
+        self.find_named(name)
+            .map(|driver| (driver.constructor)(slot))
+            .ok_or_else(|| PlatformError::UnknownDriver(name.to_string()))
+    }
+
+    /// Create the drivers of all modules found by [MainBoard::discover_modules](super::mainboard::MainBoard::discover_modules), fails on a module without a registered driver
+    pub fn create_discovered(&self, inventory: &ModuleInventory) -> error::Result<Vec<Box<dyn GOcontrollModule>>> {
+        inventory.modules()
+            .map(|(slot, identity)| self.create(identity.module_id(), slot))
+            .collect()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::gocontroll::{mainboard::ModuleLayout,inventory::SlotContents,module::{ModuleIdentity,FirmwareVersion}};
+
+    fn identity(article: [u8;3]) -> SlotContents {
+        SlotContents::Module(ModuleIdentity { article, hardware_revision: 1, firmware: FirmwareVersion::new(1,0,0), serial: None })
+    }
+
+    #[test]
+    fn create_drivers() {
+        let registry = ModuleRegistry::builtin();
+        for driver in registry.drivers() {
+            let module = registry.create_named(driver.name, ModuleSlot::Moduleslot3).unwrap();
+            assert_eq!(module.module_id(), driver.module_id);
+            assert_eq!(module.get_slot(), ModuleSlot::Moduleslot3);
+        }
+        assert!(matches!(registry.create(99, ModuleSlot::Moduleslot1), Err(PlatformError::UnknownModule { slot: ModuleSlot::Moduleslot1, module_id: 99 })));
+        assert!(matches!(registry.create_named("InputModule12Ch", ModuleSlot::Moduleslot1), Err(PlatformError::UnknownDriver(_))));
+
+        let mut inventory = ModuleInventory::new(ModuleLayout::ModulineMini);
+        inventory.set(ModuleSlot::Moduleslot1, identity([20,10,1]));
+        inventory.set(ModuleSlot::Moduleslot2, SlotContents::Bootloader);
+        inventory.set(ModuleSlot::Moduleslot4, identity([20,20,3]));
+        let modules = registry.create_discovered(&inventory).unwrap();
+        assert_eq!(modules.iter().map(|module| (module.get_slot(), module.module_id())).collect::<Vec<_>>(),
+            [(ModuleSlot::Moduleslot1, 11), (ModuleSlot::Moduleslot4, 23)]);
+
+        let mut registry = ModuleRegistry::empty();
+        registry.register(BUILTIN[0]);
+        assert!(matches!(registry.create_discovered(&inventory), Err(PlatformError::UnknownModule { slot: ModuleSlot::Moduleslot4, module_id: 23 })));
+        registry.register(ModuleDriver::new(11, "CustomInput", BUILTIN[1].constructor));
+        assert_eq!(registry.create(11, ModuleSlot::Moduleslot1).unwrap().module_id(), 12);
+        assert_eq!(registry.find_named("InputModule6Ch").unwrap().module_id, 11);
+
+        let module = registry.create(11, ModuleSlot::Moduleslot1).unwrap();
+        assert!(module.is::<InputModule10Ch>() && module.downcast_ref::<InputModule6Ch>().is_none());
+        let module = module.downcast::<OutputModule6Ch>().unwrap_err();
+        assert_eq!(module.downcast::<InputModule10Ch>().unwrap().get_slot(), ModuleSlot::Moduleslot1);
+    }
+}
@@ -0,0 +1,461 @@
+use std::collections::VecDeque;
+use std::fmt::Debug;
+use std::io;
+use std::ops::ControlFlow;
+use std::sync::{Arc,Mutex,RwLock,PoisonError,atomic::{AtomicBool,Ordering},mpsc::{self,RecvTimeoutError}};
+use std::thread::JoinHandle;
+use std::time::{Duration,Instant};
+
+use super::{calibration::{Millivolts,SupplyCalibration},mainboard::AdcChannel,error};
+
+#[derive(Debug,Copy,Clone,PartialEq,Eq)]
+/// How often the [SupplySampler] reads the supply measurements and how many readings it keeps
+pub struct SamplerConfig {
+    /// The time between the starts of two rounds of readings
+    pub interval: Duration,
+    /// The number of readings kept per measurement, at least 1
+    pub history: usize,
+    /// The number of latest readings in the moving average, at least 1 and at most `history`
+    pub average: usize,
+}
+
+impl Default for SamplerConfig {
+    /// 100 rounds per second, with 10 seconds of history and a moving average over 100 ms
+    fn default() -> Self {
+        SamplerConfig::new(Duration::from_millis(10))
+    }
+}
+
+impl SamplerConfig {
+    pub const fn new(interval: Duration) -> SamplerConfig {
+        SamplerConfig { interval, history: 1000, average: 10 }
+    }
+
+    pub const fn with_history(mut self, history: usize) -> SamplerConfig {
+        self.history = history;
+        self
+    }
+
+    pub const fn with_average(mut self, average: usize) -> SamplerConfig {
+        self.average = average;
+        self
+    }
+}
+
+#[derive(Debug,Copy,Clone,PartialEq,Eq)]
+/// A calibrated supply voltage and the moment it was read
+pub struct SupplySample {
+    pub time: Instant,
+    pub voltage: Millivolts,
+}
+
+#[derive(Debug,Copy,Clone,PartialEq,Eq)]
+/// The moving average and the extremes of the history of a supply measurement
+pub struct SupplyStatistics {
+    pub average: Millivolts,
+    pub min: SupplySample,
+    pub max: SupplySample,
+    /// The number of readings in the history
+    pub samples: usize,
+}
+
+/// Called by the sampler thread after every round of readings with the reading of every measurement in the order of [AdcChannel::ALL],
+/// None for a failed reading. Returning [ControlFlow::Break] unsubscribes the listener.
+pub type SupplyListener = Box<dyn FnMut(&[Option<SupplySample>;4]) -> ControlFlow<()> + Send>;
+
+struct SamplerState {
+    config: SamplerConfig,
+    channels: [VecDeque<SupplySample>;4],
+    errors: u64,
+}
+
+#[derive(Clone)]
+/// The readings of a [SupplySampler], cheap to clone and to query from any thread while the sampler keeps running.
+/// A sampler started again with [SupplySampler::restart] keeps recording into the same readings
+pub struct SupplyReadings {
+    state: Arc<RwLock<SamplerState>>,
+    listeners: Arc<Mutex<Vec<SupplyListener>>>,
+}
+
+#[allow(unused)]
+impl SupplyReadings {
+    fn new(config: SamplerConfig) -> SupplyReadings {
+        let channel = || VecDeque::with_capacity(config.history);
+        SupplyReadings {
+            state: Arc::new(RwLock::new(SamplerState { config, channels: [channel(), channel(), channel(), channel()], errors: 0 })),
+            listeners: Arc::new(Mutex::new(Vec::new())),
+        }
+    }
+
+    /// Take the settings of `config`, the history of every measurement is cut to its new length
+    fn reconfigure(&self, config: SamplerConfig) {
+        let mut state = self.state.write().unwrap_or_else(PoisonError::into_inner);
+        state.config = config;
+        for samples in &mut state.channels {
+            samples.drain(..samples.len().saturating_sub(config.history.max(1)));
+        }
+    }
+
+    fn read<R>(&self, f: impl FnOnce(&SamplerState) -> R) -> R {
+        f(&self.state.read().unwrap_or_else(PoisonError::into_inner))
+    }
+
+    pub fn config(&self) -> SamplerConfig {
+        self.read(|state| state.config)
+    }
+
+    /// The latest reading of `channel`, None before the first successful reading
+    pub fn latest(&self, channel: AdcChannel) -> Option<SupplySample> {
+        self.read(|state| state.channels[channel.index()].back().copied())
+    }
+
+    /// The latest reading of all measurements in the order of [AdcChannel::ALL]
+    pub fn latest_all(&self) -> [Option<SupplySample>;4] {
+        self.read(|state| state.channels.each_ref().map(|channel| channel.back().copied()))
+    }
+
+    /// The readings of `channel` kept by the sampler, oldest first
+    pub fn history(&self, channel: AdcChannel) -> Vec<SupplySample> {
+        self.read(|state| state.channels[channel.index()].iter().copied().collect())
+    }
+
+    /// The readings of `channel` taken at or after `since`, oldest first
+    pub fn history_since(&self, channel: AdcChannel, since: Instant) -> Vec<SupplySample> {
+        self.read(|state| state.channels[channel.index()].iter().filter(|sample| sample.time >= since).copied().collect())
+    }
+
+    /// The average of the latest [SamplerConfig::average] readings of `channel`
+    pub fn average(&self, channel: AdcChannel) -> Option<Millivolts> {
+        self.read(|state| Self::moving_average(&state.channels[channel.index()], state.config.average))
+    }
+
+    /// The moving average of `channel` and the lowest and highest reading in its history, the latest of equal readings
+    pub fn statistics(&self, channel: AdcChannel) -> Option<SupplyStatistics> {
+        self.read(|state| {
+            let samples = &state.channels[channel.index()];
+            Some(SupplyStatistics {
+                average: Self::moving_average(samples, state.config.average)?,
+                min: *samples.iter().rev().min_by_key(|sample| sample.voltage)?,
+                max: *samples.iter().max_by_key(|sample| sample.voltage)?,
+                samples: samples.len(),
+            })
+        })
+    }
+
+    /// The number of readings that failed, a failed reading leaves no sample in the history of its measurement
+    pub fn errors(&self) -> u64 {
+        self.read(|state| state.errors)
+    }
+
+    /// Call `listener` after every following round of readings, it runs on the sampler thread so it should return quickly
+    /// and must not subscribe another listener itself
+    pub fn subscribe<F>(&self, listener: F)
+    where
+        F: FnMut(&[Option<SupplySample>;4]) -> ControlFlow<()> + Send + 'static,
+    {
+        self.listeners.lock().unwrap_or_else(PoisonError::into_inner).push(Box::new(listener));
+    }
+
+    fn moving_average(samples: &VecDeque<SupplySample>, average: usize) -> Option<Millivolts> {
+        let count = samples.len().min(average.max(1));
+        if count == 0 {
+            return None;
+        }
+        let sum: u32 = samples.iter().rev().take(count).map(|sample| sample.voltage.0 as u32).sum();
+        Some(Millivolts(((sum + count as u32 / 2) / count as u32) as u16))
+    }
+
+    fn notify(&self, round: &[Option<SupplySample>;4]) {
+        self.listeners.lock().unwrap_or_else(PoisonError::into_inner).retain_mut(|listener| listener(round).is_continue());
+    }
+
+    fn record(&self, round: [Option<SupplySample>;4]) {
+        let mut state = self.state.write().unwrap_or_else(PoisonError::into_inner);
+        let history = state.config.history.max(1);
+        for (index, sample) in round.into_iter().enumerate() {
+            match sample {
+                Some(sample) => {
+                    let samples = &mut state.channels[index];
+                    if samples.len() >= history {
+                        samples.pop_front();
+                    }
+                    samples.push_back(sample);
+                },
+                None => state.errors += 1,
+            }
+        }
+    }
+}
+
+impl Debug for SupplyReadings {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("SupplyReadings")
+            .field("config", &self.config())
+            .field("latest", &self.latest_all())
+            .finish_non_exhaustive()
+    }
+}
+
+type Callback<E> = Box<dyn FnMut(&E) + Send>;
+
+/// The callbacks and channels the events of a monitor on the supply readings are published to
+pub(crate) struct Subscribers<E> {
+    callbacks: Mutex<Vec<Callback<E>>>,
+    senders: Mutex<Vec<mpsc::Sender<E>>>,
+    closed: AtomicBool,
+}
+
+impl<E: Clone + Send + 'static> Subscribers<E> {
+    pub(crate) fn new() -> Subscribers<E> {
+        Subscribers { callbacks: Mutex::new(Vec::new()), senders: Mutex::new(Vec::new()), closed: AtomicBool::new(false) }
+    }
+
+    /// Register `callback`, it is dropped right away once the subscribers are closed
+    pub(crate) fn on_event<F>(&self, callback: F)
+    where
+        F: FnMut(&E) + Send + 'static,
+    {
+        let mut callbacks = self.callbacks.lock().unwrap_or_else(PoisonError::into_inner);
+        if !self.is_closed() {
+            callbacks.push(Box::new(callback));
+        }
+    }
+
+    /// A channel receiving the following events, it is disconnected right away once the subscribers are closed
+    pub(crate) fn subscribe(&self) -> mpsc::Receiver<E> {
+        let (sender, receiver) = mpsc::channel();
+        let mut senders = self.senders.lock().unwrap_or_else(PoisonError::into_inner);
+        if !self.is_closed() {
+            senders.push(sender);
+        }
+        receiver
+    }
+
+    /// Drop every callback and channel, which breaks the cycle of a callback holding a clone of its monitor
+    pub(crate) fn close(&self) {
+        self.closed.store(true, Ordering::Relaxed);
+        self.senders.lock().unwrap_or_else(PoisonError::into_inner).clear();
+        // a callback may hold the last clone of the monitor owning these subscribers, so they are dropped without the lock
+        let callbacks = std::mem::take(&mut *self.callbacks.lock().unwrap_or_else(PoisonError::into_inner));
+        drop(callbacks);
+    }
+
+    pub(crate) fn is_closed(&self) -> bool {
+        self.closed.load(Ordering::Relaxed)
+    }
+
+    /// Send `event` to every channel that still has a receiver and call every callback
+    pub(crate) fn publish(&self, event: &E) {
+        self.senders.lock().unwrap_or_else(PoisonError::into_inner).retain(|sender| sender.send(event.clone()).is_ok());
+        // the callbacks run without the lock, so they can register more callbacks
+        let mut callbacks = std::mem::take(&mut *self.callbacks.lock().unwrap_or_else(PoisonError::into_inner));
+        for callback in &mut callbacks {
+            callback(event);
+        }
+        let mut registered = self.callbacks.lock().unwrap_or_else(PoisonError::into_inner);
+        if self.is_closed() {
+            // a callback closed the subscribers
+            drop(registered);
+            return;
+        }
+        callbacks.append(&mut registered);
+        *registered = callbacks;
+    }
+}
+
+/// Reads the 4 supply measurements on a background thread, started with [MainBoard::start_supply_sampler](super::mainboard::MainBoard::start_supply_sampler).
+/// The thread stops when the sampler is dropped.
+pub struct SupplySampler {
+    readings: SupplyReadings,
+    calibration: Arc<Mutex<SupplyCalibration>>,
+    stop: Option<mpsc::Sender<()>>,
+    thread: Option<JoinHandle<()>>,
+}
+
+#[allow(unused)]
+impl SupplySampler {
+    /// Start a thread that calls `read` for the raw reading of every measurement each [SamplerConfig::interval] and converts it with `calibration`.
+    /// A round that takes longer than the interval delays the next round instead of cramming in the missed ones.
+    pub fn start<F>(config: SamplerConfig, calibration: SupplyCalibration, read: F) -> io::Result<SupplySampler>
+    where
+        F: FnMut(AdcChannel) -> error::Result<u16> + Send + 'static,
+    {
+        Self::run(SupplyReadings::new(config), calibration, read)
+    }
+
+    /// Stop the sampler and start it again with `config`, recording into the same readings so their listeners and monitors keep getting every round.
+    /// The history is cut to the history of `config`
+    pub fn restart<F>(self, config: SamplerConfig, calibration: SupplyCalibration, read: F) -> io::Result<SupplySampler>
+    where
+        F: FnMut(AdcChannel) -> error::Result<u16> + Send + 'static,
+    {
+        let readings = self.readings();
+        drop(self);
+        readings.reconfigure(config);
+        Self::run(readings, calibration, read)
+    }
+
+    fn run<F>(readings: SupplyReadings, calibration: SupplyCalibration, mut read: F) -> io::Result<SupplySampler>
+    where
+        F: FnMut(AdcChannel) -> error::Result<u16> + Send + 'static,
+    {
+        let config = readings.config();
+        let calibration = Arc::new(Mutex::new(calibration));
+        let (stop, stopped) = mpsc::channel::<()>();
+        let thread = {
+            let readings = readings.clone();
+            let calibration = calibration.clone();
+            std::thread::Builder::new().name("supply-sampler".to_string()).spawn(move || {
+                let mut next = Instant::now();
+                loop {
+                    let calibration = *calibration.lock().unwrap_or_else(PoisonError::into_inner);
+                    let round = AdcChannel::ALL.map(|channel| read(channel).ok().map(|raw| SupplySample {
+                        time: Instant::now(),
+                        voltage: calibration.channels[channel.index()].millivolts(raw),
+                    }));
+                    readings.record(round);
+                    readings.notify(&round);
+                    next += config.interval;
+                    let now = Instant::now();
+                    next = next.max(now);
+                    match stopped.recv_timeout(next - now) {
+                        Err(RecvTimeoutError::Timeout) => (),
+                        _ => break,
+                    }
+                }
+            })?
+        };
+        Ok(SupplySampler { readings, calibration, stop: Some(stop), thread: Some(thread) })
+    }
+
+    /// A handle on the readings that stays valid after the sampler stopped
+    pub fn readings(&self) -> SupplyReadings {
+        self.readings.clone()
+    }
+
+    /// Convert the next readings with `calibration`, the history keeps the voltages it was recorded with
+    pub fn set_calibration(&self, calibration: SupplyCalibration) {
+        *self.calibration.lock().unwrap_or_else(PoisonError::into_inner) = calibration;
+    }
+
+    /// Stop the thread and wait for its last round to finish
+    pub fn stop(self) {}
+}
+
+impl Drop for SupplySampler {
+    fn drop(&mut self) {
+        // closing the channel wakes the thread
+        self.stop.take();
+        if let Some(thread) = self.thread.take() {
+            let _ = thread.join();
+        }
+    }
+}
+
+impl Debug for SupplySampler {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("SupplySampler")
+            .field("readings", &self.readings)
+            .finish_non_exhaustive()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::gocontroll::{calibration::AdcCalibration,error::PlatformError};
+
+    #[test]
+    fn sample_supplies() {
+        let unity = AdcCalibration::new(1.0, 0.0);
+        let mut round = 0u16;
+        let sampler = SupplySampler::start(SamplerConfig::new(Duration::from_millis(1)).with_history(5).with_average(2),
+            SupplyCalibration::new(unity, unity, unity, unity), move |channel| {
+                match channel {
+                    AdcChannel::K30 => { round += 1; Ok(round * 100) },
+                    AdcChannel::K15A => Ok(1000 - round),
+                    AdcChannel::K15B => Ok(500),
+                    AdcChannel::K15C => Err(PlatformError::MissingHardware("K15C")),
+                }
+            }).unwrap();
+        let readings = sampler.readings();
+        let start = Instant::now();
+        while readings.history(AdcChannel::K30).len() < 5 {
+            assert!(start.elapsed() < Duration::from_secs(5));
+            std::thread::sleep(Duration::from_millis(1));
+        }
+        drop(sampler);
+
+        let history = readings.history(AdcChannel::K30);
+        assert_eq!(history.len(), 5);
+        assert!(history.windows(2).all(|pair| pair[0].time <= pair[1].time && pair[0].voltage.0 + 100 == pair[1].voltage.0));
+        let last = history[4].voltage.0;
+        let statistics = readings.statistics(AdcChannel::K30).unwrap();
+        assert_eq!(statistics, SupplyStatistics { average: Millivolts(last - 50), min: history[0], max: history[4], samples: 5 });
+        let k15a = readings.statistics(AdcChannel::K15A).unwrap();
+        assert_eq!((k15a.min.voltage.0, k15a.max.voltage.0), (1000 - last / 100, 1000 - last / 100 + 4));
+        assert_eq!(readings.latest(AdcChannel::K15B).unwrap().voltage, Millivolts(500));
+        assert_eq!(readings.latest(AdcChannel::K15C), None);
+        assert_eq!(readings.statistics(AdcChannel::K15C), None);
+        assert_eq!(readings.errors(), (last / 100) as u64);
+        assert_eq!(readings.history_since(AdcChannel::K30, history[3].time).len(), 2);
+
+        // the thread stopped with the sampler
+        std::thread::sleep(Duration::from_millis(5));
+        assert_eq!(readings.latest(AdcChannel::K30), Some(history[4]));
+    }
+
+    #[test]
+    fn restart_keeps_readings() {
+        let unity = AdcCalibration::new(1.0, 0.0);
+        let calibration = SupplyCalibration::new(unity, unity, unity, unity);
+        let sampler = SupplySampler::start(SamplerConfig::new(Duration::from_millis(1)).with_history(10), calibration, |_| Ok(1000)).unwrap();
+        let readings = sampler.readings();
+        let (sender, rounds) = mpsc::channel();
+        readings.subscribe(move |round| {
+            let _ = sender.send(round[AdcChannel::K30.index()].map(|sample| sample.voltage));
+            ControlFlow::Continue(())
+        });
+        assert_eq!(rounds.recv_timeout(Duration::from_secs(5)).unwrap(), Some(Millivolts(1000)));
+
+        let sampler = sampler.restart(SamplerConfig::new(Duration::from_millis(1)).with_history(2).with_average(2), calibration, |_| Ok(2000)).unwrap();
+        while rounds.recv_timeout(Duration::from_secs(5)).unwrap() != Some(Millivolts(2000)) {}
+        assert_eq!(readings.config().history, 2);
+        drop(sampler);
+        assert_eq!(readings.history(AdcChannel::K30).len(), 2);
+        assert_eq!(readings.latest(AdcChannel::K30).unwrap().voltage, Millivolts(2000));
+    }
+
+    #[test]
+    fn subscribers_lifecycle() {
+        let subscribers = Arc::new(Subscribers::<u32>::new());
+        let events = subscribers.subscribe();
+        let (sender, callbacks) = mpsc::channel();
+        let held = subscribers.clone();
+        subscribers.on_event(move |event| {
+            sender.send(*event).unwrap();
+            // a callback can register another one while it runs
+            if *event == 1 {
+                held.on_event(|_| ());
+            }
+        });
+        subscribers.publish(&1);
+        subscribers.publish(&2);
+        assert_eq!(events.try_iter().collect::<Vec<_>>(), [1, 2]);
+        assert_eq!(callbacks.try_iter().collect::<Vec<_>>(), [1, 2]);
+        assert_eq!(subscribers.callbacks.lock().unwrap().len(), 2);
+
+        // closing drops the callbacks, including the one holding the subscribers, and disconnects the channels
+        let weak = Arc::downgrade(&subscribers);
+        subscribers.close();
+        assert!(subscribers.is_closed());
+        assert_eq!(events.try_recv(), Err(mpsc::TryRecvError::Disconnected));
+        assert_eq!(callbacks.try_recv(), Err(mpsc::TryRecvError::Disconnected));
+        let late = subscribers.subscribe();
+        subscribers.on_event(|_| unreachable!());
+        subscribers.publish(&3);
+        assert_eq!(late.try_recv(), Err(mpsc::TryRecvError::Disconnected));
+        drop(subscribers);
+        assert!(weak.upgrade().is_none());
+    }
+}
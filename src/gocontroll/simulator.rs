@@ -0,0 +1,403 @@
+use std::collections::BTreeMap;
+use std::io;
+use std::sync::{Arc,Mutex,MutexGuard};
+
+use super::module::{ModuleSlot,ModuleTransport,CommunicationDirection,MessageType,BOOTMESSAGELENGTH,FIRMWARERESPONSE};
+use super::mainboard::{MainBoard,PlatformPaths};
+#[cfg(feature = "experimental")]
+use super::flasher::{BootloaderProtocol,BootloaderLayout};
+
+const BOOTLOADERCOMMAND: u8 = 19;
+const BOOTLOADERRESPONSE: u8 = 9;
+
+#[cfg(feature = "experimental")]
+/// The flashing commands and message layout a simulated module answers in its bootloader.
+/// They are only for exercising [ModuleFlasher](super::flasher::ModuleFlasher) against the simulator, a real module does not answer them.
+pub const SIMULATEDBOOTLOADER: BootloaderProtocol = BootloaderProtocol { erase: 29, program: 39, verify: 49, block_size: 32,
+    layout: BootloaderLayout { command: Some(2), address: 6, address_big_endian: false, length: 10, data: 11, read_data: 11, echo: Some(2), status: 10, status_ok: 0 } };
+
+#[allow(unused)]
+#[derive(Debug,Copy,Clone,PartialEq,Eq)]
+/// The modules that can be simulated, they answer with the same module id and message layout as the real hardware.
+pub enum SimulatedModuleType {
+    InputModule6Ch,
+    InputModule10Ch,
+    InputModule420mA,
+    OutputModule6Ch,
+    OutputModule10Ch,
+}
+
+impl SimulatedModuleType {
+    /// The module id used in the header of the messages of this module
+    pub const fn module_id(self) -> u8 {
+        match self {
+            SimulatedModuleType::InputModule6Ch => 11,
+            SimulatedModuleType::InputModule10Ch => 12,
+            SimulatedModuleType::InputModule420mA => 13,
+            SimulatedModuleType::OutputModule6Ch => 22,
+            SimulatedModuleType::OutputModule10Ch => 23,
+        }
+    }
+
+    /// The number of channels on this module
+    pub const fn channels(self) -> usize {
+        match self {
+            SimulatedModuleType::InputModule10Ch | SimulatedModuleType::OutputModule10Ch => 10,
+            _ => 6,
+        }
+    }
+
+    /// The hardware identification reported by the bootloader of this module
+    const fn hardware(self) -> [u8;4] {
+        match self {
+            SimulatedModuleType::InputModule6Ch => [FIRMWARERESPONSE,10,1,3],
+            SimulatedModuleType::InputModule10Ch => [FIRMWARERESPONSE,10,2,1],
+            SimulatedModuleType::InputModule420mA => [FIRMWARERESPONSE,10,3,1],
+            SimulatedModuleType::OutputModule6Ch => [FIRMWARERESPONSE,20,2,2],
+            SimulatedModuleType::OutputModule10Ch => [FIRMWARERESPONSE,20,3,1],
+        }
+    }
+}
+
+#[allow(unused)]
+#[derive(Debug,Copy,Clone,PartialEq,Eq)]
+/// Faults that can be injected into a simulated module:
+/// ChecksumError       -> Every response of the module carries a corrupted checksum\
+/// Unresponsive        -> The module doesn't drive the bus, every response reads as zeroes\
+/// StuckInBootloader   -> The module answers the bootloader escape but never starts its firmware\
+/// ProgramError        -> The bootloader answers every program command with an error status
+pub enum SimulatedFault {
+    ChecksumError,
+    Unresponsive,
+    StuckInBootloader,
+    ProgramError,
+}
+
+type ChannelScript = Box<dyn FnMut(u32) -> i32 + Send>;
+
+struct SimulatedState {
+    module_type: SimulatedModuleType,
+    in_bootloader: bool,
+    firmware: [u8;3],
+    serial: u32,
+    flash: BTreeMap<u32,u8>,
+    values: Vec<i32>,
+    scripts: Vec<Option<ChannelScript>>,
+    commanded: Vec<u16>,
+    configuration: Vec<Option<Vec<u8>>>,
+    temperature: i16,
+    ground_shift: u16,
+    fault_codes: u32,
+    faults: Vec<SimulatedFault>,
+    reads: u32,
+    checksum_errors: usize,
+}
+
+/// A software model of the firmware of a Moduline module.
+/// Cloning it gives another handle to the same module, so a test can keep one while the driver talks to it through a [SimulatedTransport].
+#[derive(Clone)]
+pub struct SimulatedModule {
+    state: Arc<Mutex<SimulatedState>>,
+}
+
+/// The transport a module driver uses to talk to a [SimulatedModule]
+pub struct SimulatedTransport {
+    module: SimulatedModule,
+}
+
+#[allow(unused)]
+impl SimulatedModule {
+    /// Create a new simulated module, it starts in its bootloader like a module that just came out of reset
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use gocontroll_platform::gocontroll::{inputmodule6ch::*,mainboard::MainBoard,module::ModuleSlot,simulator::*};
+    /// let simulated = SimulatedModule::new(SimulatedModuleType::InputModule6Ch);
+    /// let mut input_module: InputModule6Ch<SimulatedTransport> = InputModule6Ch::new(ModuleSlot::Moduleslot1, [None;6],
+    /// Inputmodule6chSupplyConfig::new(InputModuleSupply::On, InputModuleSupply::On, InputModuleSupply::On))
+    /// .with_transport(simulated.transport());
+    /// MainBoard::escape_module_bootloader(&input_module).unwrap();
+    /// simulated.set_value(0, 4200);
+    /// assert_eq!(input_module.get_raw_values().unwrap()[0], 4200);
+    /// ```
+    pub fn new(module_type: SimulatedModuleType) -> SimulatedModule {
+        let channels = module_type.channels();
+        SimulatedModule {
+            state: Arc::new(Mutex::new(SimulatedState {
+                module_type,
+                in_bootloader: true,
+                firmware: [1,0,0],
+                serial: 0,
+                flash: BTreeMap::new(),
+                values: vec![0;channels],
+                scripts: (0..channels).map(|_| None).collect(),
+                commanded: vec![0;channels],
+                configuration: vec![None;4],
+                temperature: 25,
+                ground_shift: 0,
+                fault_codes: 0,
+                faults: Vec::new(),
+                reads: 0,
+                checksum_errors: 0,
+            })),
+        }
+    }
+
+    /// Get a transport connected to this module
+    pub fn transport(&self) -> SimulatedTransport {
+        SimulatedTransport { module: self.clone() }
+    }
+
+    pub fn module_type(&self) -> SimulatedModuleType {
+        self.state().module_type
+    }
+
+    /// Set the value reported for a channel, for input modules this is the measured value (the loop current in µA on a 4-20 mA module), for output modules the channel current in mA
+    pub fn set_value(&self, channel: usize, value: i32) {
+        let mut state = self.state();
+        state.scripts[channel] = None;
+        state.values[channel] = value;
+    }
+
+    /// Let a channel report values generated by `script`, which gets the number of data messages served so far
+    pub fn script_value(&self, channel: usize, script: impl FnMut(u32) -> i32 + Send + 'static) {
+        self.state().scripts[channel] = Some(Box::new(script));
+    }
+
+    /// The value last commanded to a channel of an output module
+    pub fn commanded_value(&self, channel: usize) -> u16 {
+        self.state().commanded[channel]
+    }
+
+    /// The payload of the last configuration message with `index`, None if it was never received
+    pub fn configuration(&self, index: u8) -> Option<Vec<u8>> {
+        self.state().configuration.get(index as usize).cloned().flatten()
+    }
+
+    pub fn set_firmware_version(&self, major: u8, minor: u8, patch: u8) {
+        self.state().firmware = [major,minor,patch];
+    }
+
+    /// Set the serial number reported with the firmware version, 0 behaves like firmware that doesn't report one
+    pub fn set_serial(&self, serial: u32) {
+        self.state().serial = serial;
+    }
+
+    pub fn set_temperature(&self, temperature: i16) {
+        self.state().temperature = temperature;
+    }
+
+    pub fn set_ground_shift(&self, ground_shift: u16) {
+        self.state().ground_shift = ground_shift;
+    }
+
+    /// Set the raw fault codes reported in the feedback of an output module
+    pub fn set_fault_codes(&self, fault_codes: u32) {
+        self.state().fault_codes = fault_codes;
+    }
+
+    pub fn inject_fault(&self, fault: SimulatedFault) {
+        let mut state = self.state();
+        if !state.faults.contains(&fault) {
+            state.faults.push(fault);
+        }
+    }
+
+    pub fn clear_faults(&self) {
+        self.state().faults.clear();
+    }
+
+    /// Put the module back into its bootloader, like toggling its reset line
+    pub fn reset(&self) {
+        self.state().in_bootloader = true;
+    }
+
+    /// The contents of the application flash of the module, erased bytes read as 0xff
+    pub fn flash_contents(&self, address: u32, length: usize) -> Vec<u8> {
+        let state = self.state();
+        (address..address+length as u32).map(|address| state.flash.get(&address).copied().unwrap_or(0xff)).collect()
+    }
+
+    pub fn in_bootloader(&self) -> bool {
+        self.state().in_bootloader
+    }
+
+    /// The number of messages received with an incorrect checksum
+    pub fn checksum_errors(&self) -> usize {
+        self.state().checksum_errors
+    }
+
+    fn state(&self) -> MutexGuard<'_, SimulatedState> {
+        self.state.lock().unwrap_or_else(|poisoned| poisoned.into_inner())
+    }
+
+    fn handle_transfer(&self, tx: &[u8], rx: &mut [u8]) {
+        rx.fill(0);
+        let mut state = self.state();
+        if state.faults.contains(&SimulatedFault::Unresponsive) || tx.len() < 6 {
+            return;
+        }
+        #[cfg(feature = "experimental")]
+        let flashing = state.in_bootloader && SimulatedState::flashing_command(tx);
+        #[cfg(not(feature = "experimental"))]
+        let flashing = false;
+        // the layout of a flashing message comes from the protocol, it doesn't carry its length
+        let length = if flashing { BOOTMESSAGELENGTH } else { tx[1] as usize + 1 };
+        if length > tx.len() || length > rx.len() || length < 7 {
+            return;
+        }
+        if MainBoard::module_checksum(tx, length).is_err() {
+            state.checksum_errors += 1;
+            return;
+        }
+        let escape = tx[0] == BOOTLOADERCOMMAND && tx[2] == BOOTLOADERCOMMAND;
+        let bootloader_message = escape || flashing;
+        if escape {
+            state.escape_bootloader(rx);
+        } else if flashing {
+            #[cfg(feature = "experimental")]
+            state.bootloader_command(tx, rx);
+        } else if !state.in_bootloader && tx[3] == state.module_type.module_id() {
+            state.handle_message(tx, rx, length);
+        } else {
+            return;
+        }
+        let length = if bootloader_message { BOOTMESSAGELENGTH } else { length };
+        rx[length-1] = MainBoard::calculate_checksum(rx, length);
+        if state.faults.contains(&SimulatedFault::ChecksumError) {
+            rx[length-1] = !rx[length-1];
+        }
+    }
+}
+
+impl SimulatedState {
+    fn escape_bootloader(&mut self, rx: &mut [u8]) {
+        if self.in_bootloader {
+            rx[0] = BOOTLOADERRESPONSE;
+        }
+        if self.faults.contains(&SimulatedFault::StuckInBootloader) {
+            return;
+        }
+        rx[6..10].copy_from_slice(&self.module_type.hardware());
+        rx[10..13].copy_from_slice(&self.firmware);
+        rx[13..17].copy_from_slice(&self.serial.to_le_bytes());
+        self.in_bootloader = false;
+    }
+
+    #[cfg(feature = "experimental")]
+    fn flashing_command(tx: &[u8]) -> bool {
+        let protocol = SIMULATEDBOOTLOADER;
+        tx.len() >= BOOTMESSAGELENGTH && [protocol.erase, protocol.program, protocol.verify].contains(&tx[0]) && protocol.layout.command.is_none_or(|command| tx[command] == tx[0])
+    }
+
+    #[cfg(feature = "experimental")]
+    fn bootloader_command(&mut self, tx: &[u8], rx: &mut [u8]) {
+        let (protocol, layout) = (SIMULATEDBOOTLOADER, SIMULATEDBOOTLOADER.layout);
+        let address = u32::from_le_bytes(tx[layout.address..layout.address+4].try_into().unwrap_or_default());
+        let count = (tx[layout.length] as usize).min(protocol.block_size);
+        rx[0] = BOOTLOADERRESPONSE;
+        if let Some(echo) = layout.echo {
+            rx[echo] = tx[0];
+        }
+        rx[layout.status] = layout.status_ok;
+        match tx[0] {
+            command if command == protocol.erase => self.flash.clear(),
+            command if command == protocol.program && self.faults.contains(&SimulatedFault::ProgramError) => rx[layout.status] = !layout.status_ok,
+            command if command == protocol.program => {
+                for (offset, byte) in tx[layout.data..layout.data+count].iter().enumerate() {
+                    self.flash.insert(address + offset as u32, *byte);
+                }
+            },
+            _ => {
+                for (offset, byte) in rx[layout.read_data..layout.read_data+count].iter_mut().enumerate() {
+                    *byte = self.flash.get(&(address + offset as u32)).copied().unwrap_or(0xff);
+                }
+            },
+        }
+    }
+
+    fn handle_message(&mut self, tx: &[u8], rx: &mut [u8], length: usize) {
+        let command = [tx[0], tx[1], CommunicationDirection::FromModule as u8, tx[3], tx[4], tx[5]];
+        rx[..6].copy_from_slice(&command);
+        if tx[4] == MessageType::Configuration as u8 {
+            let Some(configuration) = self.configuration.get_mut(tx[5] as usize) else {
+                return;
+            };
+            if tx[2] == CommunicationDirection::ToModule as u8 {
+                *configuration = Some(tx[6..length-1].to_vec());
+            }
+            return;
+        }
+        if tx[4] != MessageType::Data as u8 {
+            return;
+        }
+        match (self.module_type, tx[5]) {
+            (SimulatedModuleType::OutputModule6Ch, 1) => {
+                for channel in 0..6 {
+                    self.commanded[channel] = u16::from_le_bytes([tx[channel*6+6], tx[channel*6+7]]);
+                }
+                self.sample_values();
+                rx[6..8].copy_from_slice(&self.temperature.to_le_bytes());
+                rx[8..10].copy_from_slice(&self.ground_shift.to_le_bytes());
+                for channel in 0..6 {
+                    rx[channel*2+10..channel*2+12].copy_from_slice(&(self.values[channel] as i16).to_le_bytes());
+                }
+                rx[22..26].copy_from_slice(&self.fault_codes.to_le_bytes());
+            },
+            (SimulatedModuleType::OutputModule10Ch, 1) => {
+                for channel in 0..10 {
+                    self.commanded[channel] = u16::from_le_bytes([tx[channel*2+6], tx[channel*2+7]]);
+                }
+                self.sample_values();
+                rx[6..8].copy_from_slice(&self.temperature.to_le_bytes());
+                rx[8..10].copy_from_slice(&self.ground_shift.to_le_bytes());
+                for channel in 0..10 {
+                    rx[channel*2+10..channel*2+12].copy_from_slice(&(self.values[channel] as i16).to_le_bytes());
+                }
+                rx[30..34].copy_from_slice(&self.fault_codes.to_le_bytes());
+            },
+            (SimulatedModuleType::InputModule6Ch, 1) => {
+                self.sample_values();
+                for channel in 0..6 {
+                    rx[channel*8+6..channel*8+10].copy_from_slice(&self.values[channel].to_le_bytes());
+                }
+            },
+            (SimulatedModuleType::InputModule10Ch | SimulatedModuleType::InputModule420mA, 1) => {
+                self.sample_values();
+                for channel in 0..self.values.len() {
+                    rx[channel*4+6..channel*4+10].copy_from_slice(&self.values[channel].to_le_bytes());
+                }
+            },
+            (SimulatedModuleType::InputModule6Ch | SimulatedModuleType::InputModule10Ch, 2) => {
+                let channel = tx[6] as usize;
+                if channel < self.values.len() {
+                    self.scripts[channel] = None;
+                    self.values[channel] = i32::from_le_bytes([tx[7], tx[8], tx[9], tx[10]]);
+                }
+            },
+            _ => (),
+        }
+    }
+
+    fn sample_values(&mut self) {
+        let reads = self.reads;
+        for (value, script) in self.values.iter_mut().zip(self.scripts.iter_mut()) {
+            if let Some(script) = script {
+                *value = script(reads);
+            }
+        }
+        self.reads += 1;
+    }
+}
+
+impl ModuleTransport for SimulatedTransport {
+    fn open(_paths: &PlatformPaths, slot: ModuleSlot) -> io::Result<Self> {
+        Err(io::Error::new(io::ErrorKind::NotFound, format!("No simulated module was attached to {}", slot)))
+    }
+
+    fn transfer(&mut self, tx: &[u8], rx: &mut [u8]) -> io::Result<()> {
+        self.module.handle_transfer(tx, rx);
+        Ok(())
+    }
+}
@@ -1,8 +1,9 @@
 pub mod gocontroll;
 
-#[cfg(modules)]
+#[cfg(feature = "modules")]
 #[cfg(test)]
 mod tests {
+    use std::io;
     use crate::gocontroll::{
         mainboard::*,
         module::*,
@@ -11,8 +12,456 @@ mod tests {
         outputmodule6ch::*,
     };
 
+    /// Answers every transfer by echoing the header with a data message holding the channel number times 1000 for every channel
+    struct ScriptedTransport;
+
+    impl ModuleTransport for ScriptedTransport {
+        fn open(_paths: &PlatformPaths, _slot: ModuleSlot) -> io::Result<Self> {
+            Ok(ScriptedTransport)
+        }
+
+        fn transfer(&mut self, tx: &[u8], rx: &mut [u8]) -> io::Result<()> {
+            let length = tx[1] as usize + 1;
+            rx[..6].copy_from_slice(&tx[..6]);
+            rx[2] = CommunicationDirection::FromModule as u8;
+            for channel in 0..6 {
+                rx[channel*8+6..channel*8+10].copy_from_slice(&((channel as i32 + 1) * 1000).to_le_bytes());
+            }
+            rx[length-1] = MainBoard::calculate_checksum(rx, length);
+            Ok(())
+        }
+    }
+
+    #[test]
+    fn module_over_custom_transport() {
+        let mut input_module: InputModule6Ch<ScriptedTransport> = InputModule6Ch::new(ModuleSlot::Moduleslot1, [None;6],
+        Inputmodule6chSupplyConfig::new(InputModuleSupply::On, InputModuleSupply::On, InputModuleSupply::On));
+        assert!(input_module.get_raw_values().is_err());
+        input_module.open_transport(&PlatformPaths::default()).unwrap();
+        let values = input_module.get_raw_values().unwrap();
+        assert_eq!(values[0], 1000);
+        assert_eq!(values[4], 5000);
+    }
+
+    #[test]
+    fn config_builders() {
+        use crate::gocontroll::error::ConfigError;
+        assert!(matches!(InputModule6ChConfig::builder(InputModule6ChFunction::AnalogmV).pull_up(InputModule6ChPullUp::PullUp3_3k)
+            .pull_down(InputModule6ChPullDown::PullDown10k).build(), Err(ConfigError::PullUpAndPullDown)));
+        assert!(matches!(InputModule6ChConfig::builder(InputModule6ChFunction::PulseCounter).pulses_per_rotation(2).build(),
+            Err(ConfigError::NotApplicable("pulses_per_rotation"))));
+        assert!(matches!(InputModule6ChConfig::builder(InputModule6ChFunction::Rpm).build(), Err(ConfigError::MissingSetting("pulses_per_rotation"))));
+        assert!(InputModule6ChConfig::builder(InputModule6ChFunction::AnalogmV).voltage_range(InputModule6ChVoltageRange::Voltage0_24V)
+            .analog_filter_samples(10).build().is_ok());
+
+        assert!(matches!(InputModule10ChConfig::builder(InputModule10ChFunction::DigitalIn).pull_up(InputModule10ChPullUp::PullUp3_3k)
+            .pull_down(InputModule10ChPullDown::PullDown3_3k).build(), Err(ConfigError::PullUpAndPullDown)));
+        assert!(InputModule10ChConfig::builder(InputModule10ChFunction::DigitalIn).pull_up(InputModule10ChPullUp::PullUp3_3k).build().is_ok());
+
+        assert!(matches!(OutputModule6ChConfig::builder(OutputModule6ChFunction::PeakAndHold).peak_current(3000).build(),
+            Err(ConfigError::MissingSetting("peak_time"))));
+        assert!(matches!(OutputModule6ChConfig::builder(OutputModule6ChFunction::LowSideDutyCycle).max_current(2000).peak_time(20).build(),
+            Err(ConfigError::NotApplicable("peak_time"))));
+        assert!(OutputModule6ChConfig::builder(OutputModule6ChFunction::LowSideDutyCycle).max_current(2000).build().is_ok());
+    }
+
+    #[test]
+    #[cfg(feature = "simulator")]
+    fn simulated_module_values_and_faults() {
+        use crate::gocontroll::simulator::*;
+        let simulated = SimulatedModule::new(SimulatedModuleType::InputModule6Ch);
+        let input_module: InputModule6Ch<SimulatedTransport> = InputModule6Ch::new(ModuleSlot::Moduleslot1, [None;6],
+        Inputmodule6chSupplyConfig::new(InputModuleSupply::On, InputModuleSupply::On, InputModuleSupply::On))
+        .with_transport(simulated.transport());
+        // a module that is still in its bootloader doesn't answer data requests
+        assert!(input_module.get_raw_values().is_err());
+        MainBoard::escape_module_bootloader(&input_module).unwrap();
+        assert!(!simulated.in_bootloader());
+
+        simulated.script_value(1, |read| read as i32 * 10);
+        assert_eq!(input_module.get_raw_values().unwrap()[1], 0);
+        assert_eq!(input_module.get_raw_values().unwrap()[1], 10);
+
+        input_module.reset_pulse_counter(crate::gocontroll::inputmodule6ch::InputModuleChannel::Channel2, 1234).unwrap();
+        assert_eq!(input_module.get_raw_values().unwrap()[1], 1234);
+
+        simulated.inject_fault(SimulatedFault::ChecksumError);
+        assert!(input_module.get_raw_values().is_err());
+        simulated.clear_faults();
+        assert!(input_module.get_raw_values().is_ok());
+    }
+
+    #[test]
+    #[cfg(feature = "simulator")]
+    fn output_module_6ch_faults() {
+        use crate::gocontroll::simulator::*;
+        let simulated = SimulatedModule::new(SimulatedModuleType::OutputModule6Ch);
+        let output_module: OutputModule6Ch<SimulatedTransport> = OutputModule6Ch::new(ModuleSlot::Moduleslot1, [None;6],
+            OutputModule6ChFrequecyConfig::new(OutputModule6ChFrequency::Freq1KHz, OutputModule6ChFrequency::Freq1KHz, OutputModule6ChFrequency::Freq1KHz))
+            .with_transport(simulated.transport());
+        let feedback = output_module.set_outputs_get_feedback(0, 0, 0, 0, 0, 0);
+        assert!(feedback.is_err());
+        MainBoard::escape_module_bootloader(&output_module).unwrap();
+
+        simulated.inject_fault(SimulatedFault::ChecksumError);
+        let feedback = output_module.set_outputs_get_feedback(0, 0, 0, 0, 0, 0).unwrap();
+        assert!(feedback.checksum_error && feedback.stale);
+        assert_eq!(feedback.channels[0].current, 0);
+        simulated.clear_faults();
+
+        simulated.set_temperature(61);
+        simulated.set_ground_shift(120);
+        simulated.set_value(5, 2500);
+        simulated.set_fault_codes(0x0012_3456);
+        let feedback = output_module.set_outputs_get_feedback(0, 0, 0, 0, 0, 1000).unwrap();
+        assert!(!feedback.checksum_error && !feedback.stale);
+        assert_eq!((feedback.temperature, feedback.ground_shift), (61, 120));
+        assert_eq!(feedback.fault_codes, 0x0012_3456);
+        assert_eq!(feedback.channels[5], OutputChannelFeedback { current: 2500 });
+
+        simulated.inject_fault(SimulatedFault::ChecksumError);
+        let stale = output_module.set_outputs_get_feedback(0, 0, 0, 0, 0, 0).unwrap();
+        assert!(stale.checksum_error && stale.stale);
+        assert_eq!(stale.channels, feedback.channels);
+    }
+
+    #[test]
+    #[cfg(feature = "simulator")]
+    fn output_commands() {
+        use crate::gocontroll::{error::PlatformError,simulator::*};
+        let simulated = SimulatedModule::new(SimulatedModuleType::OutputModule6Ch);
+        let output_module: OutputModule6Ch<SimulatedTransport> = OutputModule6Ch::new(ModuleSlot::Moduleslot1, [
+            Some(OutputModule6ChConfig::new(OutputModule6ChFunction::HighSideDutyCycle, None, None, None)),
+            Some(OutputModule6ChConfig::new(OutputModule6ChFunction::HighSideSwitch, None, None, None)),
+            Some(OutputModule6ChConfig::new(OutputModule6ChFunction::HalfBridge, None, None, None)),
+            Some(OutputModule6ChConfig::new(OutputModule6ChFunction::PeakAndHold, None, Some(3000), Some(50))),
+            Some(OutputModule6ChConfig::new(OutputModule6ChFunction::FrequencyOut, None, None, None)),
+            None,
+        ], OutputModule6ChFrequecyConfig::new(OutputModule6ChFrequency::Freq1KHz, OutputModule6ChFrequency::Freq1KHz, OutputModule6ChFrequency::Freq1KHz))
+            .with_transport(simulated.transport());
+        MainBoard::escape_module_bootloader(&output_module).unwrap();
+
+        output_module.set_outputs([OutputCommand::Duty(250), OutputCommand::On, OutputCommand::HalfBridge(HalfBridgeState::High),
+            OutputCommand::PeakAndHoldTrigger, OutputCommand::Frequency(440), OutputCommand::Off]).unwrap();
+        assert_eq!((0..6).map(|channel| simulated.commanded_value(channel)).collect::<Vec<_>>(), [250, 1, 2, 1, 440, 0]);
+
+        output_module.set_output(OutputModuleChannel::Channel2, OutputCommand::Off).unwrap();
+        assert_eq!(simulated.commanded_value(1), 0);
+        assert_eq!(simulated.commanded_value(0), 250);
+
+        assert!(matches!(output_module.set_output(OutputModuleChannel::Channel2, OutputCommand::Duty(500)),
+            Err(PlatformError::InvalidOutputCommand { channel: 1, command: OutputCommand::Duty(500) })));
+        assert!(output_module.set_output(OutputModuleChannel::Channel1, OutputCommand::Duty(1001)).is_err());
+        assert!(output_module.set_output(OutputModuleChannel::Channel6, OutputCommand::On).is_err());
+        assert!(output_module.set_outputs([OutputCommand::Duty(900), OutputCommand::On, OutputCommand::Off, OutputCommand::Off, OutputCommand::Off, OutputCommand::On]).is_err());
+        assert_eq!(simulated.commanded_value(0), 250);
+    }
+
+    #[test]
+    #[cfg(feature = "simulator")]
+    fn reconfigure_channels() {
+        use crate::gocontroll::simulator::*;
+        let input = SimulatedModule::new(SimulatedModuleType::InputModule6Ch);
+        let output = SimulatedModule::new(SimulatedModuleType::OutputModule6Ch);
+        let frequency = InputModule6ChConfig::builder(InputModule6ChFunction::FrequencyIn).build().unwrap();
+        let mut input_module: InputModule6Ch<SimulatedTransport> = InputModule6Ch::new(ModuleSlot::Moduleslot1, [Some(frequency), None, None, None, None, None],
+            Inputmodule6chSupplyConfig::new(InputModuleSupply::On, InputModuleSupply::On, InputModuleSupply::On))
+            .with_transport(input.transport());
+        let mut output_module: OutputModule6Ch<SimulatedTransport> = OutputModule6Ch::new(ModuleSlot::Moduleslot2,
+            [Some(OutputModule6ChConfig::new(OutputModule6ChFunction::HighSideSwitch, Some(1000), None, None)), None, None, None, None, None],
+            OutputModule6ChFrequecyConfig::new(OutputModule6ChFrequency::Freq1KHz, OutputModule6ChFrequency::Freq1KHz, OutputModule6ChFrequency::Freq1KHz))
+            .with_transport(output.transport());
+        MainBoard::escape_module_bootloader(&input_module).unwrap();
+        MainBoard::escape_module_bootloader(&output_module).unwrap();
+
+        input.set_value(0, 1500);
+        assert_eq!(input_module.get_values().unwrap()[0], InputModuleValue::Frequency(1500));
+        let analog = InputModule6ChConfig::builder(InputModule6ChFunction::AnalogmV).analog_filter_samples(20).build().unwrap();
+        input_module.reconfigure_channel(crate::gocontroll::inputmodule6ch::InputModuleChannel::Channel1, Some(analog)).unwrap();
+        assert_eq!(input.configuration(1).unwrap()[0..4], [2,0,0,20]);
+        assert_eq!(input_module.get_values().unwrap()[0], InputModuleValue::Millivolts(1500));
+
+        let counter = InputModule6ChConfig::builder(InputModule6ChFunction::PulseCounter).build().unwrap();
+        input_module.reconfigure_channel(crate::gocontroll::inputmodule6ch::InputModuleChannel::Channel1, Some(counter)).unwrap();
+        input.set_value(0, 1510);
+        assert_eq!(input_module.read_pulse_counter(crate::gocontroll::inputmodule6ch::InputModuleChannel::Channel1).unwrap().total, 10);
+
+        // a module that doesn't answer after the configuration keeps the driver on its old configuration
+        input.reset();
+        assert!(input_module.reconfigure_channel(crate::gocontroll::inputmodule6ch::InputModuleChannel::Channel1, Some(analog)).is_err());
+        MainBoard::escape_module_bootloader(&input_module).unwrap();
+        assert!(matches!(input_module.get_values().unwrap()[0], InputModuleValue::PulseCount(_)));
+
+        output_module.set_output(OutputModuleChannel::Channel1, OutputCommand::On).unwrap();
+        let duty = OutputModule6ChConfig::builder(OutputModule6ChFunction::HighSideDutyCycle).max_current(4000).build().unwrap();
+        output_module.reconfigure_channel(OutputModuleChannel::Channel1, Some(duty)).unwrap();
+        assert_eq!(output.configuration(1).unwrap()[6..8], 4000u16.to_le_bytes());
+        assert_eq!(output.commanded_value(0), 0);
+        output_module.set_output(OutputModuleChannel::Channel1, OutputCommand::Duty(300)).unwrap();
+        assert_eq!(output.commanded_value(0), 300);
+        assert!(output_module.set_output(OutputModuleChannel::Channel1, OutputCommand::On).is_err());
+
+        let switch = OutputModule6ChConfig::builder(OutputModule6ChFunction::HighSideSwitch).build().unwrap();
+        output_module.reconfigure_channel(OutputModuleChannel::Channel1, Some(switch)).unwrap();
+        assert_eq!(output.configuration(1).unwrap()[6..8], [0xff,0xff]);
+        assert_eq!(output.commanded_value(0), 0);
+        assert!(output_module.set_output(OutputModuleChannel::Channel1, OutputCommand::On).is_ok());
+    }
+
+    #[test]
+    #[cfg(feature = "simulator")]
+    fn extended_pulse_counters() {
+        use crate::gocontroll::{inputmodule10ch::InputModuleChannel,simulator::*};
+        let simulated = SimulatedModule::new(SimulatedModuleType::InputModule10Ch);
+        let counter = InputModule10ChConfig::builder(InputModule10ChFunction::PulseCounter).build().unwrap();
+        let input_module: InputModule10Ch<SimulatedTransport> = InputModule10Ch::new(ModuleSlot::Moduleslot1, [Some(counter), None, None, None, None, None, None, None, None, None],
+            InputModuleSupply::On)
+            .with_transport(simulated.transport());
+        MainBoard::escape_module_bootloader(&input_module).unwrap();
+        assert!(input_module.read_pulse_counter(InputModuleChannel::Channel2).is_err());
+
+        simulated.set_value(0, 100);
+        let reading = input_module.read_pulse_counter(InputModuleChannel::Channel1).unwrap();
+        assert_eq!((reading.total, reading.delta, reading.rate), (100, 100, 0.0));
+        for raw in [2_000_000_000, -2_000_000_000, 50] {
+            simulated.set_value(0, raw);
+            input_module.get_raw_values().unwrap();
+        }
+        // a value behind the last one is taken for a late response and ignored
+        simulated.set_value(0, 40);
+        let reading = input_module.read_pulse_counter(InputModuleChannel::Channel1).unwrap();
+        assert_eq!((reading.total, reading.delta), ((1 << 32) + 50, (1 << 32) - 50));
+
+        input_module.preset_pulse_counter(InputModuleChannel::Channel1, 5_000_000_000).unwrap();
+        assert_eq!(input_module.get_raw_values().unwrap()[0], 5_000_000_000u64 as u32 as i32);
+        let reading = input_module.read_pulse_counter(InputModuleChannel::Channel1).unwrap();
+        assert_eq!((reading.total, reading.delta), (5_000_000_000, 0));
+
+        simulated.set_value(0, 25);
+        input_module.restore_pulse_counter(InputModuleChannel::Channel1, 1_000).unwrap();
+        let reading = input_module.read_pulse_counter(InputModuleChannel::Channel1).unwrap();
+        assert_eq!((reading.total, reading.delta), (1_025, 25));
+
+        // a module that was reset counts from 0 again once it left its bootloader
+        simulated.reset();
+        MainBoard::escape_module_bootloader(&input_module).unwrap();
+        simulated.set_value(0, 10);
+        let reading = input_module.read_pulse_counter(InputModuleChannel::Channel1).unwrap();
+        assert_eq!((reading.total, reading.delta), (1_035, 10));
+
+        // a module that restarted without passing its bootloader, after a brown-out or a watchdog reset, is followed from 0 as well
+        simulated.set_value(0, 2_000_000);
+        input_module.get_raw_values().unwrap();
+        simulated.set_value(0, 7);
+        let reading = input_module.read_pulse_counter(InputModuleChannel::Channel1).unwrap();
+        assert_eq!((reading.total, reading.delta), (2_001_032, 1_999_997));
+    }
+
+    #[test]
+    #[cfg(feature = "simulator")]
+    fn typed_input_values() {
+        use crate::gocontroll::simulator::*;
+        let simulated = SimulatedModule::new(SimulatedModuleType::InputModule10Ch);
+        let config = |function| Some(InputModule10ChConfig::new(function, InputModule10ChPullDown::PullDownNone, InputModule10ChPullUp::PulUpnNone));
+        let input_module: InputModule10Ch<SimulatedTransport> = InputModule10Ch::new(ModuleSlot::Moduleslot1,
+            [config(InputModule10ChFunction::AnalogmV), config(InputModule10ChFunction::DigitalIn), config(InputModule10ChFunction::FrequencyIn),
+            config(InputModule10ChFunction::DutyCycleHigh), config(InputModule10ChFunction::Rpm), config(InputModule10ChFunction::PulseCounter),
+            config(InputModule10ChFunction::None), None, None, config(InputModule10ChFunction::Adc12Bit)],
+            InputModuleSupply::On)
+            .with_transport(simulated.transport());
+        MainBoard::escape_module_bootloader(&input_module).unwrap();
+        for channel in 0..10 {
+            simulated.set_value(channel, channel as i32 + 1);
+        }
+        assert_eq!(input_module.get_values().unwrap(), [
+            InputModuleValue::Millivolts(1),
+            InputModuleValue::Digital(true),
+            InputModuleValue::Frequency(3),
+            InputModuleValue::DutyCycle(4),
+            InputModuleValue::Rpm(5),
+            InputModuleValue::PulseCount(6),
+            InputModuleValue::Unused,
+            InputModuleValue::Unused,
+            InputModuleValue::Unused,
+            InputModuleValue::Adc(10),
+        ]);
+    }
+
+    #[test]
+    #[cfg(feature = "simulator")]
+    fn simulated_output_module_10ch() {
+        use crate::gocontroll::{outputmodule10ch::*,simulator::*};
+        let simulated = SimulatedModule::new(SimulatedModuleType::OutputModule10Ch);
+        let mut channels = [None;10];
+        channels[9] = Some(OutputModule10ChConfig::new(OutputModule10ChFunction::HighSideDutyCycle, Some(2500)));
+        let mut output_module: OutputModule10Ch<SimulatedTransport> = OutputModule10Ch::new(ModuleSlot::Moduleslot1, channels,
+            OutputModule10ChFrequencyConfig::new(OutputModule10ChFrequency::Freq1KHz, OutputModule10ChFrequency::Freq1KHz, OutputModule10ChFrequency::Freq1KHz,
+            OutputModule10ChFrequency::Freq1KHz, OutputModule10ChFrequency::Freq10KHz))
+            .with_transport(simulated.transport());
+        let mut mainboard = MainBoard::new();
+        MainBoard::escape_module_bootloader(&output_module).unwrap();
+        output_module.put_configuration(&mut mainboard).unwrap();
+        let configuration = simulated.configuration(1).unwrap();
+        assert_eq!(configuration[9], (OutputModule10ChFunction::HighSideDutyCycle as u8) << 4 | OutputModule10ChFrequency::Freq10KHz as u8);
+        assert_eq!(configuration[28..30], 2500u16.to_le_bytes());
+        assert_eq!(configuration[26..28], [0,0]);
+
+        simulated.set_value(9, 1800);
+        simulated.set_fault_codes(0x8000_0001);
+        let feedback = output_module.set_outputs_get_feedback([0,0,0,0,0,0,0,0,100,1000]).unwrap();
+        assert_eq!(feedback.channels[9].current, 1800);
+        assert_eq!(feedback.fault_codes, 0x8000_0001);
+        assert!(!feedback.stale);
+        assert_eq!(simulated.commanded_value(8), 100);
+        assert_eq!(simulated.commanded_value(9), 1000);
+
+        simulated.inject_fault(SimulatedFault::ChecksumError);
+        let stale = output_module.set_outputs_get_feedback([0;10]).unwrap();
+        assert!(stale.checksum_error && stale.stale);
+        assert_eq!(stale.channels, feedback.channels);
+    }
+
+    #[test]
+    #[cfg(feature = "simulator")]
+    fn simulated_420ma_readings() {
+        use crate::gocontroll::{inputmodule420ma::*,simulator::*};
+        let simulated = SimulatedModule::new(SimulatedModuleType::InputModule420mA);
+        let pressure = InputModule420mAConfig::new(InputModule420mAFunction::Current, 300).with_scale(InputModule420mAScale::new(0.0, 250.0));
+        let mut input_module: InputModule420mA<SimulatedTransport> = InputModule420mA::new(ModuleSlot::Moduleslot1,
+            [Some(pressure), Some(InputModule420mAConfig::new(InputModule420mAFunction::Current, 10)), None, None, None, None],
+            [InputModuleSupply::On, InputModuleSupply::Off, InputModuleSupply::Off])
+            .with_transport(simulated.transport());
+        let mut mainboard = MainBoard::new();
+        MainBoard::escape_module_bootloader(&input_module).unwrap();
+        input_module.put_configuration(&mut mainboard).unwrap();
+        let configuration = simulated.configuration(1).unwrap();
+        assert_eq!(configuration[0..3], [1,1,44]);
+        assert_eq!(configuration[24..27], [1,2,2]);
+
+        simulated.set_value(0, 12000);
+        simulated.set_value(1, 21500);
+        let readings = input_module.get_readings().unwrap();
+        assert_eq!(readings[0].status, InputModule420mAStatus::Ok);
+        assert_eq!(readings[0].value, Some(125.0));
+        assert_eq!(readings[1].status, InputModule420mAStatus::OverRange);
+        assert_eq!(readings[1].value, None);
+        assert_eq!(readings[2].status, InputModule420mAStatus::Unused);
+        assert_eq!(readings[2].value, None);
+
+        input_module.reconfigure_channel(InputModuleChannel::Channel3, Some(InputModule420mAConfig::new(InputModule420mAFunction::Current, 10))).unwrap();
+        assert_eq!(input_module.get_readings().unwrap()[2].status, InputModule420mAStatus::WireBreak);
+
+        simulated.set_value(0, 2000);
+        let reading = input_module.get_readings().unwrap()[0];
+        assert_eq!((reading.current, reading.status, reading.value), (2000, InputModule420mAStatus::WireBreak, None));
+    }
+
+    #[tokio::test]
+    #[cfg(all(feature = "modules", feature = "async"))]
+    async fn init_async_needs_multi_thread_runtime() {
+        let mut mainboard = MainBoard::new();
+        let result = mainboard.init_async(&mut []).await;
+        assert!(matches!(result, Err(crate::gocontroll::error::PlatformError::Io(err)) if err.kind() == io::ErrorKind::Unsupported));
+    }
+
+    #[tokio::test(flavor = "multi_thread")]
+    #[cfg(all(feature = "simulator", feature = "async"))]
+    async fn simulated_modules_async() {
+        use crate::gocontroll::simulator::*;
+        let input = SimulatedModule::new(SimulatedModuleType::InputModule10Ch);
+        let output = SimulatedModule::new(SimulatedModuleType::OutputModule6Ch);
+        let input_module: InputModule10Ch<SimulatedTransport> = InputModule10Ch::new(ModuleSlot::Moduleslot1, [None;10], InputModuleSupply::On)
+            .with_transport(input.transport());
+        let output_module: OutputModule6Ch<SimulatedTransport> = OutputModule6Ch::new(ModuleSlot::Moduleslot2, [None;6],
+            OutputModule6ChFrequecyConfig::new(OutputModule6ChFrequency::Freq1KHz, OutputModule6ChFrequency::Freq1KHz, OutputModule6ChFrequency::Freq1KHz))
+            .with_transport(output.transport());
+        MainBoard::escape_module_bootloader(&input_module).unwrap();
+        MainBoard::escape_module_bootloader(&output_module).unwrap();
+
+        input.set_value(9, -42);
+        assert_eq!(input_module.get_raw_values_async().await.unwrap()[9], -42);
+
+        output.set_value(2, 1500);
+        output.set_temperature(40);
+        let feedback = output_module.set_outputs_get_feedback_async(0, 0, 500, 0, 0, 1000).await.unwrap();
+        assert_eq!(feedback.channels[2].current, 1500);
+        assert_eq!(feedback.temperature, 40);
+        assert_eq!(output.commanded_value(2), 500);
+        assert_eq!(output.commanded_value(5), 1000);
+    }
+
+    #[test]
+    #[cfg(all(feature = "simulator", feature = "toml", feature = "json"))]
+    fn controller_config() {
+        use crate::gocontroll::{config::*,error::{PlatformError,ConfigError},simulator::*};
+        let config = ControllerConfig::from_toml(r#"
+            [[module]]
+            type = "InputModule6Ch"
+            slot = "Moduleslot1"
+            supplies = ["On", "Off", "Off"]
+
+            [[module.channel]]
+            channel = 2
+            function = "AnalogmV"
+            analog_filter_samples = 20
+
+            [[module.channel]]
+            channel = 6
+            function = "Rpm"
+            pull_up = "PullUp10k"
+            pulses_per_rotation = 4
+
+            [[module]]
+            type = "OutputModule6Ch"
+            slot = "Moduleslot2"
+            frequencies = ["Freq1KHz", "Freq1KHz", "Freq10KHz"]
+
+            [[module.channel]]
+            channel = 1
+            function = "HighSideDutyCycle"
+            max_current = 4000
+        "#).unwrap();
+        let json = ControllerConfig::from_json(&config.to_json().unwrap()).unwrap();
+        assert_eq!(ControllerConfig::from_toml(&json.to_toml().unwrap()).unwrap().to_json().unwrap(), config.to_json().unwrap());
+
+        let input = SimulatedModule::new(SimulatedModuleType::InputModule6Ch);
+        let output = SimulatedModule::new(SimulatedModuleType::OutputModule6Ch);
+        let mut modules: Vec<ConfiguredModule<SimulatedTransport>> = config.build().unwrap();
+        let mut simulated = [input.transport(), output.transport()].into_iter();
+        modules = modules.into_iter().map(|module| module.with_transport(simulated.next().unwrap())).collect();
+        let mut mainboard = MainBoard::new();
+        MainBoard::escape_module_bootloader(modules[0].module()).unwrap();
+        modules[0].module_mut().put_configuration(&mut mainboard).unwrap();
+        assert_eq!(input.configuration(1).unwrap()[6..10], [2,0,0,20]);
+        assert_eq!(input.configuration(1).unwrap()[30..33], [7,2,4]);
+        let ConfiguredModule::OutputModule6Ch(output_module) = &modules[1] else { panic!("expected a 6 channel output module") };
+        assert_eq!(output_module.get_slot(), ModuleSlot::Moduleslot2);
+        MainBoard::escape_module_bootloader(output_module).unwrap();
+        output_module.set_output(OutputModuleChannel::Channel1, OutputCommand::Duty(300)).unwrap();
+        assert_eq!(output.commanded_value(0), 300);
+        assert!(output_module.set_output(OutputModuleChannel::Channel1, OutputCommand::On).is_err());
+
+        let mixed_pulls = r#"
+            [[module]]
+            type = "InputModule10Ch"
+            slot = "Moduleslot1"
+            supply = "On"
+
+            [[module.channel]]
+            channel = 1
+            function = "DigitalIn"
+            pull_up = "PullUp3_3k"
+            pull_down = "PullDown3_3k"
+        "#;
+        assert!(ControllerConfig::from_toml(mixed_pulls).unwrap_err().to_string().contains("pull-up"));
+        let missing_channel = ControllerConfig::from_json(r#"{"module": [{"type": "OutputModule10Ch", "slot": "Moduleslot1",
+            "frequencies": ["Freq1KHz", "Freq1KHz", "Freq1KHz", "Freq1KHz", "Freq1KHz"],
+            "channel": [{"channel": 11, "function": "HighSideSwitch"}]}]}"#).unwrap();
+        assert!(matches!(missing_channel.build::<SimulatedTransport>(), Err(PlatformError::InvalidConfig(ConfigError::NoSuchChannel(11)))));
+    }
 
     #[test]
+    #[ignore = "needs a Moduline controller"]
     fn it_works() {
         let mut mainboard: MainBoard = MainBoard::new();
         let mut input_module: InputModule6Ch = InputModule6Ch::new( ModuleSlot::Moduleslot1,
@@ -50,7 +499,7 @@ mod tests {
         ],
         InputModuleSupply::On);
         let modules: &mut [&mut dyn GOcontrollModule] = &mut [&mut input_module, &mut output_module, &mut input_module_10ch];
-        mainboard.init(modules);
+        mainboard.init(modules).unwrap();
 
         //other initialisation
     }